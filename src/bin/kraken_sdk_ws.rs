@@ -1,11 +1,15 @@
 use futures::StreamExt;
-use kraken_ws_client::api::{SubscribeTickerRequest, TickerEvent, SubscribeBookRequest, BookEvent, BookData};
+use kraken_ws_client::api::{SubscribeTickerRequest, TickerEvent, SubscribeBookRequest, UnsubscribeBookRequest, BookEvent, BookData};
 use kraken_ws_client::client::MyMessage;
 use kraken_ws_client::types::Depth;
 use serde::{Deserialize, Serialize};
 use serde::de::Unexpected::Str;
 use std::string::String;
-use websocket::models::order_book::{OrderBook, OrderBookUpdate};
+use websocket::models::book_registry::BookRegistry;
+use websocket::models::order_book::{OrderBook, OrderBookUpdate, UpdateOutcome};
+use websocket::models::kraken::instrument::parse_instrument_message;
+use websocket::models::kraken::translate;
+use websocket::metrics::{self, MetricsCalculator, MetricsConfig, MetricsMessage};
 
 
 use tokio::sync::mpsc;
@@ -19,15 +23,21 @@ use tracing_subscriber::{fmt, EnvFilter};
 use tracing_appender::rolling;
 use tracing_subscriber::util::SubscriberInitExt;
 
-use websocket::models::order_book::PriceLevel;
 
 use uuid::Uuid;
-
-// #[derive(Debug)]
-// pub struct MyMessage {
-//     correlation_id: Uuid,
-//     payload: String,
-// }
+use std::collections::HashMap;
+use std::env;
+use websocket::messages::now_ns;
+use websocket::quote::Exchange;
+use websocket::recorder::{RecordedFrame, Recorder};
+use websocket::replay::{replay, ReplaySpeed};
+
+// Frame handed to the book task, from the SDK or from a recording
+#[derive(Debug)]
+pub struct RawMessage {
+    correlation_id: Uuid,
+    payload: String,
+}
 
 #[derive(Debug)]
 pub struct DisplayMessage {
@@ -36,7 +46,6 @@ pub struct DisplayMessage {
 }
 
 use tracing_subscriber::layer::SubscriberExt;
-use websocket::models::order_book::QuoteType::{ASK, BID};
 
 fn setup_logging() {
     // File appender setup for logging to a file, with non-blocking behavior.
@@ -71,17 +80,48 @@ fn setup_logging() {
         .init();
 }
 
+const DEFAULT_SYMBOLS: &str = "BTC/USD";
+const DEPTH: Depth = Depth::D10;
+
+// Comma separated SYMBOLS, each gets its own book
+fn symbols() -> Vec<String> {
+    env::var("SYMBOLS").unwrap_or_else(|_| DEFAULT_SYMBOLS.to_string())
+        .split(',')
+        .map(|symbol| symbol.trim().to_string())
+        .filter(|symbol| !symbol.is_empty())
+        .collect()
+}
+
+// Checksum price and quantity precisions of each pair of a Kraken instrument snapshot
+fn parse_precisions(text: &str) -> HashMap<String, (u32, u32)> {
+    parse_instrument_message(text)
+        .map(|message| message.data.pairs.into_iter()
+            .map(|pair| (pair.symbol, (pair.price_precision, pair.qty_precision)))
+            .collect())
+        .unwrap_or_default()
+}
+
+// From INSTRUMENT_FILES, comma separated Kraken instrument snapshots
+fn load_precisions() -> HashMap<String, (u32, u32)> {
+    let mut precisions = HashMap::new();
+    let paths = env::var("INSTRUMENT_FILES").unwrap_or_default();
+    for path in paths.split(',').map(str::trim).filter(|path| !path.is_empty()) {
+        let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+        precisions.extend(parse_precisions(&text));
+    }
+    precisions
+}
+
 #[tokio::main]
 async fn main() {
     setup_logging();
+    let symbols = symbols();
 
-    let mut client = kraken_ws_client::connect_public()
-        .await
-        .expect("cannot connect");
-
-
-    let (data_tx, mut data_rx) = mpsc::channel::<MyMessage>(128); // Create a channel with a buffer size of 32.
+    let (raw_tx, raw_rx) = mpsc::channel::<RawMessage>(128);
     let (display_tx, mut display_rx) = mpsc::channel::<DisplayMessage>(128); // Create a channel with a buffer size of 32.
+    let (metrics_tx, mut metrics_rx) = mpsc::channel::<MetricsMessage>(128);
+    // Symbols whose book failed the checksum and must be resubscribed to get a fresh snapshot
+    let (resync_tx, mut resync_rx) = mpsc::channel::<String>(8);
 
     // Spawn a task to handle printing.
     tokio::spawn(async move {
@@ -111,72 +151,62 @@ async fn main() {
         }
     });
 
-    // Spawn a task to handle printing.
     tokio::spawn(async move {
-        let mut order_book:  OrderBook = OrderBook::new();
-
-        while let Some(message) = data_rx.recv().await {
-            // println!();
-            // // Handle trade message
-            // println!("Handling trade message: {}", message);
-            let msg = message.payload();
-            let correlation_id = message.correl_id();
-            let parsed = serde_json::from_str::<BookEvent>(&msg).ok();
-            match parsed {
-                Some(msg) => {
-                    // println!("parsed message: {:?}", msg);
-                    // self.order_book.
-                    // print_book_event(&msg);
-                    // let update = from_kraken(&event);
-                    let mut updates = Vec::<PriceLevel>::new();
-                    for book_event in msg.data {
-                        for level_data in book_event.bids {
-                            updates.push(PriceLevel::new(level_data.price, level_data.qty, BID));
-                        }
-                        for level_data in book_event.asks {
-                            updates.push(PriceLevel::new(level_data.price, level_data.qty, ASK));
-                        }
-                    }
-                    order_book.update(&updates);
-                    // Clone the order book and send the clone to the logging task
-                    let display = DisplayMessage{
-                        correlation_id,
-                        payload: (order_book.clone(), OrderBookUpdate::new(updates))};
-                    if display_tx.send(display).await.is_err() {
-                        error!("Logger task has been terminated");
-                        break;
-                    }
-                },
-                None => {
-                    if  !message.payload().eq("{\"channel\":\"heartbeat\"}") {
-                        error!("error while parsing", )
-                    } else { () }
-
-                },
-                // None => (),
-            }
-            // println!();
-
-
-
-
-
-            // dbg!(&event);
-
+        while let Some(message) = metrics_rx.recv().await {
+            let m = message.metrics;
+            debug!(
+                "{}-mid: {}, spread: {} ({:.2} bps), microprice: {:.2}, imbalance: {:.3}",
+                message.correlation_id, m.mid, m.spread, m.spread_bps, m.microprice, m.imbalance
+            );
         }
     });
 
-
-
+    let book_task = tokio::spawn(run_books(symbols.clone(), load_precisions(), raw_rx, display_tx, metrics_tx, resync_tx));
 
     use websocket::models::kraken::translate::from_kraken;
 
-    client
-        .send(SubscribeBookRequest::symbol("BTC/USD").depth(Depth::D10))
+    if let Ok(path) = env::var("REPLAY_FILE") {
+        // Nothing resubscribes during a replay: with the receiver dropped, checksum failures
+        // are only logged instead of filling the channel and blocking the book task
+        drop(resync_rx);
+        let speed = env::var("REPLAY_SPEED").ok()
+            .map(|speed| speed.parse::<ReplaySpeed>().expect("Invalid REPLAY_SPEED"))
+            .unwrap_or(ReplaySpeed::AsFastAsPossible);
+        replay_feed(path, speed, raw_tx).await;
+        book_task.await.expect("Book task panicked");
+        return;
+    }
+
+    let mut client = kraken_ws_client::connect_public()
         .await
-        .expect("cannot send request");
+        .expect("cannot connect");
+
+    let (data_tx, data_rx) = mpsc::channel::<MyMessage>(128); // Create a channel with a buffer size of 32.
+    tokio::spawn(record_and_forward(data_rx, raw_tx, symbols.join(",")));
 
-    client.start_book_delta(data_tx.clone()).await;
+    for symbol in &symbols {
+        client
+            .send(SubscribeBookRequest::symbol(symbol).depth(DEPTH))
+            .await
+            .expect("cannot send request");
+    }
+
+    loop {
+        tokio::select! {
+            _ = client.start_book_delta(data_tx.clone()) => break,
+            Some(symbol) = resync_rx.recv() => {
+                // Kraken sends a new snapshot when the book is subscribed again
+                client
+                    .send(UnsubscribeBookRequest::symbol(&symbol).depth(DEPTH))
+                    .await
+                    .expect("cannot send request");
+                client
+                    .send(SubscribeBookRequest::symbol(&symbol).depth(DEPTH))
+                    .await
+                    .expect("cannot send request");
+            }
+        }
+    }
 
     // while let Some(event) = client.book_delta_events().next().await {
     //
@@ -196,6 +226,155 @@ async fn main() {
     //     // dbg!(&event);
     // }
 }
+// Keeps a book per symbol from the frames of the SDK or of a recording. A book failing its
+// checksum is resubscribed through resync_tx, when something still listens to it. The checksum
+// is computed with the pair's precisions, a pair without them is not verified.
+async fn run_books(symbols: Vec<String>, precisions: HashMap<String, (u32, u32)>, mut raw_rx: mpsc::Receiver<RawMessage>,
+                   display_tx: mpsc::Sender<DisplayMessage>, metrics_tx: mpsc::Sender<MetricsMessage>,
+                   resync_tx: mpsc::Sender<String>) {
+    let mut registry = BookRegistry::new();
+    let mut metrics_calculators = HashMap::new();
+    for symbol in &symbols {
+        let book = OrderBook::new().with_max_depth(translate::depth_levels(DEPTH));
+        let book = match precisions.get(symbol) {
+            Some(&(price_precision, qty_precision)) => book.with_precision(price_precision, qty_precision),
+            None => {
+                error!(symbol = %symbol, "No instrument data in INSTRUMENT_FILES, checksums are not verified");
+                book
+            },
+        };
+        registry.subscribe(Exchange::Kraken, symbol, book);
+        metrics_calculators.insert(symbol.clone(), MetricsCalculator::new(MetricsConfig::default()));
+    }
+
+    while let Some(message) = raw_rx.recv().await {
+        // println!();
+        // // Handle trade message
+        // println!("Handling trade message: {}", message);
+        let correlation_id = message.correlation_id;
+        let parsed = serde_json::from_str::<BookEvent>(&message.payload).ok();
+        match parsed {
+            Some(msg) => {
+                // println!("parsed message: {:?}", msg);
+                // self.order_book.
+                // print_book_event(&msg);
+                // let update = from_kraken(&event);
+                let is_snapshot = translate::is_snapshot(&msg);
+                for book_event in msg.data {
+                    let update = match registry.route_kraken(&book_event, is_snapshot) {
+                        Some(routed) if routed.outcome == UpdateOutcome::Applied => routed.update,
+                        Some(routed) => {
+                            debug!("{:?} {} update while book is {}", routed.outcome, routed.key,
+                                registry.get(Exchange::Kraken, &book_event.symbol).unwrap().state());
+                            continue;
+                        },
+                        None => {
+                            debug!("{} is not subscribed", book_event.symbol);
+                            continue;
+                        }
+                    };
+                    let order_book = registry.get_mut(Exchange::Kraken, &book_event.symbol).unwrap();
+
+                    // With the default precisions, those of another pair, the checksum would never match
+                    let verified = precisions.contains_key(&book_event.symbol)
+                        .then(|| order_book.verify_checksum(book_event.checksum));
+                    if let Some(Err(mismatch)) = verified {
+                        let stats = order_book.checksum_stats();
+                        error!(
+                            symbol = %book_event.symbol,
+                            passed = stats.passed,
+                            failed = stats.failed,
+                            "{}, resubscribing", mismatch
+                        );
+                        if resync_tx.send(book_event.symbol.clone()).await.is_err() {
+                            error!("Subscription task has been terminated");
+                        } else {
+                            order_book.begin_resync();
+                        }
+                    }
+
+                    if order_book.is_synced() {
+                        let metrics_calculator = metrics_calculators.get_mut(&book_event.symbol).unwrap();
+                        if let Some(book_metrics) = metrics_calculator.on_update(order_book, &update) {
+                            if !metrics::publish(&metrics_tx, correlation_id, book_metrics).await {
+                                error!("Metrics task has been terminated");
+                            }
+                        }
+                    }
+
+                    // Clone the order book and send the clone to the logging task
+                    let display = DisplayMessage{
+                        correlation_id,
+                        payload: (order_book.clone(), update)};
+                    if display_tx.send(display).await.is_err() {
+                        error!("Logger task has been terminated");
+                        return;
+                    }
+                }
+            },
+            None => {
+                if  !message.payload.eq("{\"channel\":\"heartbeat\"}") {
+                    error!("error while parsing", )
+                } else { () }
+
+            },
+            // None => (),
+        }
+        // println!();
+
+
+
+
+
+        // dbg!(&event);
+
+    }
+}
+
+// Every raw SDK frame is appended to RECORD_FILE when it is set, tagged with all the subscribed
+// symbols as each BookData names its own
+async fn record_and_forward(mut data_rx: mpsc::Receiver<MyMessage>, raw_tx: mpsc::Sender<RawMessage>, symbols: String) {
+    let mut recorder = match env::var("RECORD_FILE") {
+        Ok(path) => Some(Recorder::open(&path).await.expect("Failed to open recording file")),
+        Err(_) => None,
+    };
+
+    while let Some(message) = data_rx.recv().await {
+        // Stamped on receipt, recording the previous frame must not delay it
+        let received_ns = now_ns();
+        let payload = message.payload();
+        if let Some(recorder) = recorder.as_mut() {
+            let frame = RecordedFrame { received_ns, ..RecordedFrame::new(Exchange::Kraken, &symbols, payload.clone()) };
+            if let Err(e) = recorder.record(&frame).await {
+                error!("Failed to record frame: {}", e);
+            }
+        }
+        if raw_tx.send(RawMessage { correlation_id: message.correl_id(), payload }).await.is_err() {
+            error!("Book task has been terminated");
+            break;
+        }
+    }
+}
+
+// Feeds the book task from a recording, REPLAY_SPEED is "original", "max" (default) or an
+// acceleration factor
+async fn replay_feed(path: String, speed: ReplaySpeed, raw_tx: mpsc::Sender<RawMessage>) {
+    let (frame_tx, mut frame_rx) = mpsc::channel::<RecordedFrame>(128);
+    tokio::spawn(async move {
+        while let Some(frame) = frame_rx.recv().await {
+            let message = RawMessage { correlation_id: Uuid::new_v4(), payload: frame.frame };
+            if raw_tx.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    match replay(&path, speed, frame_tx).await {
+        Ok(frames) => debug!("replayed {} frames from {}", frames, path),
+        Err(e) => error!("Failed to replay {}: {}", path, e),
+    }
+}
+
 // The function to print BookEvent data
 pub fn print_book_event(book_event: &BookEvent) {
     for book_data in &book_event.data {
@@ -210,4 +389,89 @@ pub fn print_book_event(book_event: &BookEvent) {
             println!("\tPrice: {:.2}, Quantity: {:.10}", ask.price, ask.qty);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use rust_decimal_macros::dec;
+    use websocket::models::order_book::{PriceLevel, QuoteType};
+
+    const INSTRUMENTS: &str = include_str!("../../tests/fixtures/kraken/instrument_snapshot.json");
+
+    // Snapshot whose checksum never matches
+    fn mismatching_snapshot(price: u32) -> String {
+        format!(r#"{{"channel":"book","type":"snapshot","data":[{{"symbol":"BTC/USD","bids":[{{"price":{},"qty":1.0}}],"asks":[{{"price":{},"qty":1.0}}],"checksum":1,"timestamp":null}}]}}"#,
+                price, price + 1)
+    }
+
+    #[tokio::test]
+    async fn test_replay_with_checksum_mismatches() {
+        let path = env::temp_dir().join(format!("kraken-sdk-replay-{}.jsonl", Uuid::new_v4()));
+        let mut recorder = Recorder::open(&path).await.unwrap();
+        // More mismatches than the resync channel holds
+        for i in 0..12 {
+            recorder.record(&RecordedFrame::new(Exchange::Kraken, "BTC/USD", mismatching_snapshot(64000 + i))).await.unwrap();
+        }
+        recorder.flush().await.unwrap();
+        drop(recorder);
+
+        let (raw_tx, raw_rx) = mpsc::channel(128);
+        let (display_tx, mut display_rx) = mpsc::channel(128);
+        let (metrics_tx, _metrics_rx) = mpsc::channel(128);
+        let (resync_tx, resync_rx) = mpsc::channel(8);
+        let book_task = tokio::spawn(run_books(vec!["BTC/USD".to_string()], parse_precisions(INSTRUMENTS), raw_rx, display_tx,
+                                               metrics_tx, resync_tx));
+        drop(resync_rx);
+
+        replay_feed(path.to_string_lossy().to_string(), ReplaySpeed::AsFastAsPossible, raw_tx).await;
+        tokio::time::timeout(Duration::from_secs(5), book_task).await
+            .expect("Book task blocked on resync requests")
+            .unwrap();
+        let mut displayed = 0;
+        while display_rx.try_recv().is_ok() {
+            displayed += 1;
+        }
+        assert_eq!(displayed, 12);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_with_pair_precision() {
+        // ETH/USD prices have 2 decimals, with the default precision of 1 the checksum differs
+        let levels = vec![
+            PriceLevel::new(dec!(3000.55), dec!(1.5), QuoteType::BID),
+            PriceLevel::new(dec!(3000.65), dec!(2), QuoteType::ASK),
+        ];
+        let mut book = OrderBook::new().with_precision(2, 8);
+        book.update(&OrderBookUpdate::snapshot(levels.clone()));
+        let mut default_book = OrderBook::new();
+        default_book.update(&OrderBookUpdate::snapshot(levels));
+        assert_ne!(book.checksum(), default_book.checksum());
+
+        let snapshot = |symbol: &str, checksum: u32| format!(
+            r#"{{"channel":"book","type":"snapshot","data":[{{"symbol":"{}","bids":[{{"price":3000.55,"qty":1.5}}],"asks":[{{"price":3000.65,"qty":2.0}}],"checksum":{},"timestamp":null}}]}}"#,
+            symbol, checksum);
+        let (raw_tx, raw_rx) = mpsc::channel(128);
+        let (display_tx, mut display_rx) = mpsc::channel(128);
+        let (metrics_tx, _metrics_rx) = mpsc::channel(128);
+        let (resync_tx, mut resync_rx) = mpsc::channel(8);
+        let symbols = vec!["ETH/USD".to_string(), "XYZ/USD".to_string()];
+        let book_task = tokio::spawn(run_books(symbols, parse_precisions(INSTRUMENTS), raw_rx, display_tx, metrics_tx, resync_tx));
+
+        // XYZ/USD has no instrument data, its checksum is not verified
+        for payload in [snapshot("ETH/USD", book.checksum()), snapshot("XYZ/USD", 1)] {
+            raw_tx.send(RawMessage { correlation_id: Uuid::new_v4(), payload }).await.unwrap();
+        }
+        drop(raw_tx);
+        tokio::time::timeout(Duration::from_secs(5), book_task).await.unwrap().unwrap();
+        let mut displayed = Vec::new();
+        while let Ok(message) = display_rx.try_recv() {
+            displayed.push(message.payload.0);
+        }
+        assert_eq!(displayed.len(), 2);
+        assert!(displayed.iter().all(|book| book.is_synced()));
+        assert!(resync_rx.try_recv().is_err());
+    }
+}
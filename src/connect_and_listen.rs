@@ -1,6 +1,12 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future;
+use std::time::Duration;
 use futures::SinkExt;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
-use tokio_tungstenite::connect_async;
+use tokio::time;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use tungstenite::Message;
 use url::Url;
 
@@ -14,71 +20,622 @@ use futures::{StreamExt};
 
 // use serde_json::{Value, Result};
 
-use crate::messages::IncomingMsg;
+use binance_spot_connector_rust::hyper::BinanceHttpClient;
+use binance_spot_connector_rust::market;
+
+use crate::connector::binance::{self, DEFAULT_SYMBOL as BINANCE_SYMBOL, DEFAULT_URL as BINANCE_URL};
+use crate::connector::{Connector, ConnectorConfig, Sequence};
+use crate::messages::{now_ns, IncomingMsg};
+use crate::models::binance::depth::{parse_depth_snapshot, parse_depth_update, DepthOutcome, DepthSync, DepthUpdate};
+use crate::models::order_book::OrderBookUpdate;
 use crate::quote::Exchange;
 
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// Upper bound on depth event texts held for the next snapshot, oldest are dropped first
+const MAX_HELD_EVENTS: usize = 1024;
+
+/// Why a feed connection ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectCause {
+    ConnectFailed(String),
+    SubscribeFailed(String),
+    PingFailed(String),
+    Error(String),
+    // Nothing received for longer than the idle timeout
+    Idle(Duration),
+    // A message was lost, resubscribing gives a fresh snapshot
+    SequenceGap { expected: u64, received: u64 },
+    // Requested by the consumer, e.g. on a checksum mismatch
+    Resync(String),
+    ClosedByPeer,
+    // Nobody consumes the feed anymore, it is not reconnected
+    ReceiverClosed,
+}
+
+impl fmt::Display for DisconnectCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectCause::ConnectFailed(e) => write!(f, "connect failed: {}", e),
+            DisconnectCause::SubscribeFailed(e) => write!(f, "subscribe failed: {}", e),
+            DisconnectCause::PingFailed(e) => write!(f, "ping failed: {}", e),
+            DisconnectCause::Error(e) => write!(f, "error: {}", e),
+            DisconnectCause::Idle(timeout) => write!(f, "nothing received for {:?}", timeout),
+            DisconnectCause::SequenceGap { expected, received } =>
+                write!(f, "sequence gap: expected {}, received {}", expected, received),
+            DisconnectCause::Resync(reason) => write!(f, "resync: {}", reason),
+            DisconnectCause::ClosedByPeer => write!(f, "closed by peer"),
+            DisconnectCause::ReceiverClosed => write!(f, "receiver closed"),
+        }
+    }
+}
+
+/// Connection lifecycle of a supervised feed. Books fed by it are stale from a `Disconnected`
+/// until the next snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    // Connected and subscribed, after `attempt` failed or dropped connections
+    Connected { exchange: Exchange, symbol: String, attempt: u32 },
+    Disconnected { exchange: Exchange, symbol: String, cause: DisconnectCause },
+    Reconnecting { exchange: Exchange, symbol: String, attempt: u32, delay: Duration },
+}
+
+impl ConnectionEvent {
+    pub fn exchange(&self) -> Exchange {
+        match self {
+            ConnectionEvent::Connected { exchange, .. }
+            | ConnectionEvent::Disconnected { exchange, .. }
+            | ConnectionEvent::Reconnecting { exchange, .. } => *exchange,
+        }
+    }
+}
 
+impl fmt::Display for ConnectionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionEvent::Connected { exchange, symbol, attempt } =>
+                write!(f, "{:?} {} connected (attempt {})", exchange, symbol, attempt),
+            ConnectionEvent::Disconnected { exchange, symbol, cause } =>
+                write!(f, "{:?} {} disconnected: {}", exchange, symbol, cause),
+            ConnectionEvent::Reconnecting { exchange, symbol, attempt, delay } =>
+                write!(f, "{:?} {} reconnecting in {:?} (attempt {})", exchange, symbol, delay, attempt),
+        }
+    }
+}
 
-pub async fn connect_and_listen_kraken(sender: mpsc::Sender<IncomingMsg>) {
-    let url = Url::parse("wss://ws.kraken.com").unwrap();
-    let (mut ws_stream, _) =
-        connect_async(&url).await
-            .expect("Failed to connect to Kraken");
-    println!("connected to Kraken on {}", url);
+/// Exponential backoff between reconnect attempts.
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    // Fraction of the delay randomly taken away, so that feeds do not reconnect in lockstep
+    pub jitter: f64,
+    // A connection silent for longer is considered dead, venue heartbeats keep it alive
+    pub idle_timeout: Option<Duration>,
+}
 
-    let subscribe_message = serde_json::json!({
-        "event": "subscribe",
-        "pair": ["XBT/USD"],
-        "subscription": {
-            "name": "book",
-            "depth": 10,
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        ReconnectConfig {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            idle_timeout: Some(Duration::from_secs(60)),
         }
-    }).to_string();
+    }
+}
+
+impl ReconnectConfig {
+    // Clamped to [0, 1], more would make the delay negative
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = clamp_jitter(jitter);
+        self
+    }
+
+    // Delay before the given attempt, starting at 1. `random` is in [0, 1).
+    pub fn delay(&self, attempt: u32, random: f64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = (self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_delay.as_secs_f64());
+        // The fields are public, a config built without with_jitter is clamped too
+        Duration::from_secs_f64(delay * (1.0 - clamp_jitter(self.jitter) * random))
+    }
+}
+
+fn clamp_jitter(jitter: f64) -> f64 {
+    if jitter.is_nan() {
+        0.0
+    } else {
+        jitter.clamp(0.0, 1.0)
+    }
+}
 
-    ws_stream.send(Message::Text(subscribe_message)).await.
-        expect("Failed to subscribe to Kraken");
+// Good enough randomness for jitter, from the clock scrambled by xorshift
+fn jitter_random() -> f64 {
+    let mut x = now_ns() | 1;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    (x % 1_000_000) as f64 / 1_000_000.0
+}
+
+// Connects to the connector's endpoint and sends its subscriptions
+pub async fn connect(connector: &dyn Connector) -> Result<WsStream, DisconnectCause> {
+    let url = connector.endpoint();
+    let (mut ws_stream, _) = connect_async(&url).await
+        .map_err(|e| DisconnectCause::ConnectFailed(e.to_string()))?;
+    for subscribe_message in connector.subscribe_messages() {
+        ws_stream.send(Message::Text(subscribe_message)).await
+            .map_err(|e| DisconnectCause::SubscribeFailed(e.to_string()))?;
+    }
+    Ok(ws_stream)
+}
 
-    while let Some(message) = ws_stream.next().await {
+// Forwards every text frame of the connection, pinging the venue if it needs it, until the
+// connection ends. A frame breaking the connector's sequence ends it, unforwarded.
+pub async fn listen(connector: &dyn Connector, mut ws_stream: WsStream, sender: &mpsc::Sender<IncomingMsg>,
+                    idle_timeout: Option<Duration>, mut resync: Option<&mut mpsc::Receiver<String>>) -> DisconnectCause {
+    let exchange = connector.exchange();
+    let mut ping = connector.ping().map(|(period, message)| {
+        (time::interval_at(time::Instant::now() + period, period), message)
+    });
+    let idle_timeout = idle_timeout.unwrap_or(Duration::MAX);
+    let mut last_sequence = None;
+    loop {
+        let message = tokio::select! {
+            message = time::timeout(idle_timeout, ws_stream.next()) => message,
+            _ = async {
+                match ping.as_mut() {
+                    Some((interval, _)) => { interval.tick().await; },
+                    None => future::pending::<()>().await,
+                }
+            } => {
+                let ping_message = ping.as_ref().map(|(_, message)| message.clone()).unwrap_or_default();
+                if let Err(err) = ws_stream.send(Message::Text(ping_message)).await {
+                    return DisconnectCause::PingFailed(err.to_string());
+                }
+                continue;
+            },
+            Some(reason) = async {
+                match resync.as_mut() {
+                    Some(resync) => resync.recv().await,
+                    None => future::pending().await,
+                }
+            } => return DisconnectCause::Resync(reason),
+        };
         match message {
-            Ok(Message::Text(text)) => {
-                println!("{}", text);
-                if sender.send(IncomingMsg{exchange: Exchange::Kraken, msg: text}).await.is_err() {
-                    eprintln!("Failed to send message from Kraken");
-                    break;
+            Ok(Some(Ok(Message::Text(text)))) => {
+                if let Some(sequence) = connector.sequence(&text) {
+                    match sequence.follow(last_sequence) {
+                        Ok(current) => last_sequence = Some(current),
+                        Err(expected) => {
+                            let received = match sequence {
+                                Sequence::Chained { previous, .. } => previous,
+                                Sequence::Next(current) | Sequence::Reset(current) => current,
+                            };
+                            return DisconnectCause::SequenceGap { expected, received };
+                        },
+                    }
+                }
+                if sender.send(IncomingMsg::new(exchange, connector.symbol(), text)).await.is_err() {
+                    return DisconnectCause::ReceiverClosed;
                 }
             }
-            Ok(_) => {
-                // Ignore non-Text messages
-            }
-            Err(err) => {
-                eprintln!("Error receiving message from Kraken: {:?}", err);
-                break;
+            Ok(Some(Ok(Message::Close(_)))) | Ok(None) => return DisconnectCause::ClosedByPeer,
+            Ok(Some(Ok(_))) => {
+                // Ignore other non-Text messages
             }
+            Ok(Some(Err(err))) => return DisconnectCause::Error(err.to_string()),
+            Err(_) => return DisconnectCause::Idle(idle_timeout),
         }
     }
 }
 
+// A single connection, without reconnect
+pub async fn connect_and_listen(connector: Box<dyn Connector>, sender: mpsc::Sender<IncomingMsg>) -> DisconnectCause {
+    match connect(connector.as_ref()).await {
+        Ok(ws_stream) => listen(connector.as_ref(), ws_stream, &sender, None, None).await,
+        Err(cause) => cause,
+    }
+}
+
+// Reports the end of a connection and waits for the backoff delay of the next attempt. False
+// when the feed must stop instead: its frames or its events are no longer received.
+async fn wait_to_reconnect(exchange: Exchange, symbol: &str, cause: DisconnectCause, attempt: &mut u32,
+                           config: &ReconnectConfig, events: &mpsc::Sender<ConnectionEvent>) -> bool {
+    if cause == DisconnectCause::ReceiverClosed {
+        return false;
+    }
+    if events.send(ConnectionEvent::Disconnected { exchange, symbol: symbol.to_string(), cause }).await.is_err() {
+        return false;
+    }
 
+    *attempt += 1;
+    let delay = config.delay(*attempt, jitter_random());
+    let reconnecting = ConnectionEvent::Reconnecting { exchange, symbol: symbol.to_string(), attempt: *attempt, delay };
+    if events.send(reconnecting).await.is_err() {
+        return false;
+    }
+    time::sleep(delay).await;
+    true
+}
 
-pub async fn connect_and_listen_binance(sender: mpsc::Sender<IncomingMsg>) {
-    let url = Url::parse("wss://stream.binance.com:9443/ws/btcusdt@depth5@100ms").unwrap();
-    let (mut ws_stream, _) = connect_async(&url).await.expect("Failed to connect to Binance");
-    println!("connected to Binance on {}", url);
-    while let Some(message) = ws_stream.next().await {
-        match message {
-            Ok(Message::Text(text)) => {
-                if sender.send(IncomingMsg{exchange: Exchange::Binance, msg: text}).await.is_err() {
-                    eprintln!("Failed to send message from Binance");
-                    break;
+// Keeps the feed connected: every connection ending is reported with its cause and followed by
+// a new connection, with its subscriptions, after a backoff delay. A reason received on resync
+// ends the connection too, the books get fresh snapshots. Stops once the frames or the events
+// are no longer received.
+pub async fn supervise(connector: Box<dyn Connector>, config: ReconnectConfig,
+                       sender: mpsc::Sender<IncomingMsg>, events: mpsc::Sender<ConnectionEvent>,
+                       mut resync: mpsc::Receiver<String>) {
+    let exchange = connector.exchange();
+    let symbol = connector.symbol().to_string();
+    let mut attempt = 0;
+    loop {
+        let cause = match connect(connector.as_ref()).await {
+            Ok(ws_stream) => {
+                let connected = ConnectionEvent::Connected { exchange, symbol: symbol.clone(), attempt };
+                if events.send(connected).await.is_err() {
+                    return;
                 }
+                attempt = 0;
+                listen(connector.as_ref(), ws_stream, &sender, config.idle_timeout, Some(&mut resync)).await
+            },
+            Err(cause) => cause,
+        };
+        if !wait_to_reconnect(exchange, &symbol, cause, &mut attempt, &config, &events).await {
+            return;
+        }
+    }
+}
+
+
+// Endpoints of the Binance depth synchronization, overridable to point at a local stand-in
+#[derive(Debug, Clone)]
+pub struct BinanceDepthConfig {
+    pub ws_url: String,
+    pub rest_url: String,
+    pub symbol: String,
+    pub snapshot_limit: u32,
+    // Between snapshot fetches that failed, or were needed again soon after the previous one
+    pub snapshot_backoff: ReconnectConfig,
+    // Also listen to the book ticker and the trades, forwarded as they come
+    pub top_of_book: bool,
+    pub trades: bool,
+}
+
+impl Default for BinanceDepthConfig {
+    fn default() -> Self {
+        BinanceDepthConfig {
+            ws_url: BINANCE_URL.to_string(),
+            rest_url: "https://api.binance.com".to_string(),
+            symbol: BINANCE_SYMBOL.to_string(),
+            snapshot_limit: 1000,
+            snapshot_backoff: ReconnectConfig::default(),
+            top_of_book: false,
+            trades: false,
+        }
+    }
+}
+
+impl BinanceDepthConfig {
+    // The streams a Binance connector built from the config would listen to, with the full depth
+    pub fn for_connector(config: &ConnectorConfig) -> Self {
+        let default = BinanceDepthConfig::default();
+        BinanceDepthConfig {
+            ws_url: config.base_url.clone().unwrap_or(default.ws_url),
+            symbol: config.symbol.clone().unwrap_or(default.symbol),
+            top_of_book: config.top_of_book,
+            trades: config.trades,
+            ..BinanceDepthConfig::default()
+        }
+    }
+
+    pub fn stream_url(&self) -> String {
+        let symbol = self.symbol.to_lowercase();
+        let mut streams = vec![format!("{}@depth@100ms", symbol)];
+        streams.extend(binance::extra_streams(&symbol, self.top_of_book, self.trades));
+        binance::stream_url(&self.ws_url, &streams)
+    }
+}
+
+// The raw response, forwarded as is once it proves usable
+pub async fn fetch_binance_depth_snapshot(config: &BinanceDepthConfig) -> Result<String, String> {
+    let client = BinanceHttpClient::with_url(&config.rest_url);
+    let request = market::depth(&config.symbol).limit(config.snapshot_limit);
+    client.send(request).await
+        .map_err(|e| format!("{:?}", e))?
+        .into_body_str().await
+        .map_err(|e| format!("{:?}", e))
+}
+
+/// When the next depth snapshot may be fetched.
+///
+/// The first fetch, and the first after the book stayed synced for a while, are immediate.
+/// Fetches that fail, give an unusable snapshot or follow a gap soon after the previous fetch
+/// back off like reconnects.
+#[derive(Debug)]
+struct SnapshotSchedule {
+    backoff: ReconnectConfig,
+    // Fetches since the book last stayed synced longer than the backoff's max delay
+    failures: u32,
+    last_fetch: Option<time::Instant>,
+    not_before: Option<time::Instant>,
+}
+
+impl SnapshotSchedule {
+    fn new(backoff: ReconnectConfig) -> Self {
+        SnapshotSchedule { backoff, failures: 0, last_fetch: None, not_before: None }
+    }
+
+    fn is_due(&self, now: time::Instant) -> bool {
+        self.not_before.map_or(true, |not_before| now >= not_before)
+    }
+
+    fn fetching(&mut self, now: time::Instant) {
+        self.last_fetch = Some(now);
+    }
+
+    fn failed(&mut self, now: time::Instant, random: f64) {
+        self.failures += 1;
+        self.not_before = Some(now + self.backoff.delay(self.failures, random));
+    }
+
+    fn gap(&mut self, now: time::Instant, random: f64) {
+        let recent = self.last_fetch.map_or(false, |last_fetch| now.duration_since(last_fetch) < self.backoff.max_delay);
+        if !recent {
+            self.failures = 0;
+        }
+        self.not_before = match self.failures {
+            0 => None,
+            failures => Some(now + self.backoff.delay(failures, random)),
+        };
+        self.failures += 1;
+    }
+
+    fn synced(&mut self) {
+        self.not_before = None;
+    }
+}
+
+// The diff-depth stream needs no subscription
+pub async fn connect_binance_depth(config: &BinanceDepthConfig) -> Result<WsStream, DisconnectCause> {
+    let url = Url::parse(&config.stream_url())
+        .map_err(|e| DisconnectCause::ConnectFailed(e.to_string()))?;
+    let (ws_stream, _) = connect_async(&url).await
+        .map_err(|e| DisconnectCause::ConnectFailed(e.to_string()))?;
+    Ok(ws_stream)
+}
+
+// Texts of the depth events the book does not reflect yet, by their update ids, forwarded
+// once a snapshot bridges them
+#[derive(Debug, Default)]
+struct HeldEvents {
+    events: VecDeque<(u64, u64, String)>,
+}
+
+impl HeldEvents {
+    fn hold(&mut self, update: &DepthUpdate, text: String) {
+        if self.events.len() == MAX_HELD_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back((update.first_update_id, update.final_update_id, text));
+    }
+
+    // The texts of the updates applied on top of a snapshot, in their order
+    fn release(&mut self, applied: &[OrderBookUpdate]) -> Vec<String> {
+        let texts = applied.iter()
+            .filter_map(|update| update.sequence())
+            .filter_map(|(first, last)| self.events.iter()
+                .find(|(held_first, held_last, _)| (*held_first, *held_last) == (first, last))
+                .map(|(_, _, text)| text.clone()))
+            .collect();
+        self.events.clear();
+        texts
+    }
+
+    // Events the snapshot covers are never needed again
+    fn discard_until(&mut self, last_update_id: u64) {
+        self.events.retain(|(_, last, _)| *last > last_update_id);
+    }
+}
+
+// Keeps a Binance book in sync with the diff-depth stream of the connection until it ends, and
+// forwards the frames that make it: the REST snapshot first, then the events in sequence. An
+// event breaking the sequence is forwarded too, the receiving book goes stale on it until the
+// next snapshot, followed by the events it bridges. One snapshot is fetched at a time, events
+// keep being held while the next one is backed off. Each connection starts over from a fresh
+// snapshot, as does a reason received on resync. Other frames are forwarded as they come.
+pub async fn sync_binance_depth(config: &BinanceDepthConfig, mut ws_stream: WsStream, sender: &mpsc::Sender<IncomingMsg>,
+                                idle_timeout: Option<Duration>, mut resync: Option<&mut mpsc::Receiver<String>>) -> DisconnectCause {
+    let idle_timeout = idle_timeout.unwrap_or(Duration::MAX);
+    let mut sync = DepthSync::new();
+    let mut schedule = SnapshotSchedule::new(config.snapshot_backoff.clone());
+    let mut held = HeldEvents::default();
+    let mut buffering = false;
+    let mut texts = Vec::new();
+    loop {
+        // Wakes up for a backed off fetch even if the stream is quiet
+        let retry_at = schedule.not_before.filter(|_| buffering && !sync.is_synced());
+        let message = tokio::select! {
+            message = time::timeout(idle_timeout, ws_stream.next()) => Some(message),
+            _ = async {
+                match retry_at {
+                    Some(retry_at) => time::sleep_until(retry_at).await,
+                    None => future::pending::<()>().await,
+                }
+            } => None,
+            Some(reason) = async {
+                match resync.as_mut() {
+                    Some(resync) => resync.recv().await,
+                    None => future::pending().await,
+                }
+            } => return DisconnectCause::Resync(reason),
+        };
+        match message {
+            // The backed off fetch is due
+            None => {},
+            Some(Ok(Some(Ok(Message::Text(text))))) => match parse_depth_update(&text) {
+                Some(update) => {
+                    buffering = true;
+                    match sync.on_update(update.clone()) {
+                        DepthOutcome::Applied(_) => texts.push(text),
+                        DepthOutcome::Ignored => {},
+                        DepthOutcome::Gap(gap) => {
+                            eprintln!("Binance {} {}, fetching a new snapshot", config.symbol, gap);
+                            schedule.gap(time::Instant::now(), jitter_random());
+                            held.hold(&update, text.clone());
+                            texts.push(text);
+                        },
+                        DepthOutcome::Buffered => held.hold(&update, text),
+                    }
+                },
+                None => texts.push(text),
+            },
+            Some(Ok(Some(Ok(Message::Close(_))))) | Some(Ok(None)) => return DisconnectCause::ClosedByPeer,
+            Some(Ok(Some(Ok(_)))) => {},
+            Some(Ok(Some(Err(err)))) => return DisconnectCause::Error(err.to_string()),
+            Some(Err(_)) => return DisconnectCause::Idle(idle_timeout),
+        }
+
+        // The snapshot is fetched once events are buffered, so that they can be bridged
+        let now = time::Instant::now();
+        if buffering && !sync.is_synced() && schedule.is_due(now) {
+            schedule.fetching(now);
+            let snapshot = fetch_binance_depth_snapshot(config).await
+                .and_then(|body| parse_depth_snapshot(&body).map(|snapshot| (snapshot, body)).map_err(|e| e.to_string()));
+            match snapshot {
+                Ok((snapshot, body)) => match sync.on_snapshot(&snapshot) {
+                    Ok(applied) => {
+                        schedule.synced();
+                        texts.push(body);
+                        texts.extend(held.release(&applied));
+                    },
+                    Err(gap) => {
+                        eprintln!("Binance {} snapshot {} unusable: {}", config.symbol, snapshot.last_update_id, gap);
+                        schedule.failed(time::Instant::now(), jitter_random());
+                        held.discard_until(snapshot.last_update_id);
+                    },
+                },
+                Err(e) => {
+                    eprintln!("Failed to fetch Binance {} snapshot: {}", config.symbol, e);
+                    schedule.failed(time::Instant::now(), jitter_random());
+                },
             }
-            Ok(_) => {
-                // Ignore non-Text messages
-            }
-            Err(err) => {
-                eprintln!("Error receiving message from Binance: {:?}", err);
-                break;
+        }
+
+        for text in texts.drain(..) {
+            if sender.send(IncomingMsg::new(Exchange::Binance, &config.symbol, text)).await.is_err() {
+                return DisconnectCause::ReceiverClosed;
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// A single connection, without reconnect
+pub async fn connect_and_sync_binance_depth(config: BinanceDepthConfig, sender: mpsc::Sender<IncomingMsg>) -> DisconnectCause {
+    match connect_binance_depth(&config).await {
+        Ok(ws_stream) => sync_binance_depth(&config, ws_stream, &sender, None, None).await,
+        Err(cause) => cause,
+    }
+}
+
+// Keeps the Binance book synced across connections like supervise keeps a feed connected: each
+// connection ending is reported and followed by a new one after a backoff delay, synced again
+// from a fresh snapshot.
+pub async fn supervise_binance_depth(config: BinanceDepthConfig, reconnect: ReconnectConfig,
+                                     sender: mpsc::Sender<IncomingMsg>, events: mpsc::Sender<ConnectionEvent>,
+                                     mut resync: mpsc::Receiver<String>) {
+    let symbol = config.symbol.clone();
+    let mut attempt = 0;
+    loop {
+        let cause = match connect_binance_depth(&config).await {
+            Ok(ws_stream) => {
+                let connected = ConnectionEvent::Connected { exchange: Exchange::Binance, symbol: symbol.clone(), attempt };
+                if events.send(connected).await.is_err() {
+                    return;
+                }
+                attempt = 0;
+                sync_binance_depth(&config, ws_stream, &sender, reconnect.idle_timeout, Some(&mut resync)).await
+            },
+            Err(cause) => cause,
+        };
+        if !wait_to_reconnect(Exchange::Binance, &symbol, cause, &mut attempt, &reconnect, &events).await {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        let config = ReconnectConfig { jitter: 0.0, ..ReconnectConfig::default() };
+        assert_eq!(config.delay(1, 0.5), Duration::from_millis(500));
+        assert_eq!(config.delay(2, 0.5), Duration::from_secs(1));
+        assert_eq!(config.delay(4, 0.5), Duration::from_secs(4));
+        assert_eq!(config.delay(100, 0.5), Duration::from_secs(30));
+
+        let config = ReconnectConfig::default();
+        assert_eq!(config.delay(2, 0.0), Duration::from_secs(1));
+        assert_eq!(config.delay(2, 0.5), Duration::from_millis(900));
+        for _ in 0..10 {
+            let random = jitter_random();
+            assert!((0.0..1.0).contains(&random));
+        }
+    }
+
+    #[test]
+    fn test_jitter_clamped() {
+        assert_eq!(ReconnectConfig::default().with_jitter(1.5).jitter, 1.0);
+        assert_eq!(ReconnectConfig::default().with_jitter(-0.5).jitter, 0.0);
+        assert_eq!(ReconnectConfig::default().with_jitter(f64::NAN).jitter, 0.0);
+        assert_eq!(ReconnectConfig::default().with_jitter(1.5).delay(2, 0.99), Duration::from_millis(10));
+
+        let config = ReconnectConfig { jitter: 3.0, ..ReconnectConfig::default() };
+        assert_eq!(config.delay(2, 0.5), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_snapshot_schedule() {
+        let backoff = ReconnectConfig { jitter: 0.0, ..ReconnectConfig::default() };
+        let mut schedule = SnapshotSchedule::new(backoff);
+        let start = time::Instant::now();
+        assert!(schedule.is_due(start));
+
+        // Failed fetches back off
+        schedule.fetching(start);
+        schedule.failed(start, 0.0);
+        assert!(!schedule.is_due(start + Duration::from_millis(499)));
+        assert!(schedule.is_due(start + Duration::from_millis(500)));
+        schedule.failed(start, 0.0);
+        assert!(!schedule.is_due(start + Duration::from_millis(999)));
+
+        // A gap soon after the last fetch backs off too
+        schedule.synced();
+        assert!(schedule.is_due(start));
+        schedule.gap(start + Duration::from_secs(1), 0.0);
+        assert!(!schedule.is_due(start + Duration::from_millis(1999)));
+        assert!(schedule.is_due(start + Duration::from_secs(2)));
+
+        // The first gap after the book stayed synced is fetched right away
+        schedule.synced();
+        schedule.gap(start + Duration::from_secs(60), 0.0);
+        assert!(schedule.is_due(start + Duration::from_secs(60)));
+        schedule.fetching(start + Duration::from_secs(60));
+        schedule.synced();
+        schedule.gap(start + Duration::from_secs(61), 0.0);
+        assert!(!schedule.is_due(start + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn test_binance_depth_streams() {
+        assert_eq!(BinanceDepthConfig::default().stream_url(), "wss://stream.binance.com:9443/ws/btcusdt@depth@100ms");
+        let config = BinanceDepthConfig::for_connector(&ConnectorConfig::new().with_symbol("ETHUSDT").with_top_of_book().with_trades());
+        assert_eq!(config.stream_url(),
+                   "wss://stream.binance.com:9443/stream?streams=ethusdt@depth@100ms/ethusdt@bookTicker/ethusdt@trade");
+    }
+}
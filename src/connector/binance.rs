@@ -0,0 +1,190 @@
+// Binance partial book depth stream: every message is the top of the book. The frames of the
+// diff-depth sync are parsed too, the REST snapshot then the events it forwards in sequence.
+use url::Url;
+
+use crate::connector::{Connector, ConnectorConfig, FeedEvent};
+use crate::models::binance::book_ticker::parse_book_ticker;
+use crate::models::binance::depth::{parse_combined_partial_depth, parse_depth_snapshot, parse_depth_update};
+use crate::models::binance::trade::parse_trade;
+use crate::quote::Exchange;
+
+pub const NAME: &str = "binance";
+pub const DEFAULT_URL: &str = "wss://stream.binance.com:9443";
+pub const DEFAULT_SYMBOL: &str = "BTCUSDT";
+// Partial depth streams publish 5, 10 or 20 levels
+pub const PARTIAL_DEPTHS: [usize; 3] = [5, 10, 20];
+pub const DEFAULT_DEPTH: usize = 5;
+
+// The smallest partial depth covering the requested one, the deepest beyond it: any other
+// stream name is rejected by Binance
+pub fn partial_depth(depth: usize) -> usize {
+    PARTIAL_DEPTHS.iter().copied().find(|&levels| levels >= depth).unwrap_or(PARTIAL_DEPTHS[2])
+}
+
+// A single stream is served raw, several are combined and their messages wrapped with their
+// stream name
+pub fn stream_url(base: &str, streams: &[String]) -> String {
+    let base = base.trim_end_matches('/');
+    match streams {
+        [stream] => format!("{}/ws/{}", base, stream),
+        _ => format!("{}/stream?streams={}", base, streams.join("/")),
+    }
+}
+
+// The book ticker and the trade streams that go along the depth one
+pub fn extra_streams(symbol: &str, top_of_book: bool, trades: bool) -> Vec<String> {
+    let mut streams = Vec::new();
+    if top_of_book {
+        streams.push(format!("{}@bookTicker", symbol));
+    }
+    // Every fill, aggTrade ids are another numbering
+    if trades {
+        streams.push(format!("{}@trade", symbol));
+    }
+    streams
+}
+
+pub struct BinanceConnector {
+    url: String,
+    symbol: String,
+    depth: usize,
+    top_of_book: bool,
+    trades: bool,
+}
+
+impl BinanceConnector {
+    pub fn new(config: ConnectorConfig) -> Self {
+        BinanceConnector {
+            url: config.base_url.unwrap_or_else(|| DEFAULT_URL.to_string()),
+            symbol: config.symbol.unwrap_or_else(|| DEFAULT_SYMBOL.to_string()),
+            depth: partial_depth(config.depth.unwrap_or(DEFAULT_DEPTH)),
+            top_of_book: config.top_of_book,
+            trades: config.trades,
+        }
+    }
+}
+
+impl Connector for BinanceConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Binance
+    }
+
+    fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    // A combined stream with the book ticker or the trades
+    fn endpoint(&self) -> Url {
+        let symbol = self.symbol.to_lowercase();
+        let mut streams = vec![format!("{}@depth{}@100ms", symbol, self.depth)];
+        streams.extend(extra_streams(&symbol, self.top_of_book, self.trades));
+        Url::parse(&stream_url(&self.url, &streams)).unwrap()
+    }
+
+    // The stream is selected by the endpoint
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![]
+    }
+
+    // Server pings are answered by the websocket layer, there is no application heartbeat
+    fn parse(&self, text: &str) -> Vec<FeedEvent> {
+        if let Ok(snapshot) = parse_depth_snapshot(text) {
+            return vec![FeedEvent::Book(snapshot.to_update())];
+        }
+        if let Some((_, snapshot)) = parse_combined_partial_depth(text) {
+            return vec![FeedEvent::Book(snapshot.to_update())];
+        }
+        if let Some(update) = parse_depth_update(text) {
+            return vec![FeedEvent::Book(update.to_update())];
+        }
+        if let Some(trade) = parse_trade(text, 0) {
+            return vec![FeedEvent::Trade(trade)];
+        }
+        parse_book_ticker(text)
+            .map(|ticker| vec![FeedEvent::TopOfBook(ticker.to_top_of_book())])
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::trade::TradeId;
+
+    #[test]
+    fn test_parse() {
+        let connector = BinanceConnector::new(ConnectorConfig::new());
+        let text = r#"{"lastUpdateId":160,"bids":[["64000.00","1.5"],["63999.00","2.0"]],"asks":[["64001.00","0.8"]]}"#;
+        let mut book = connector.new_book();
+        match connector.parse(text).as_slice() {
+            [FeedEvent::Book(update)] => {
+                assert!(update.is_snapshot());
+                book.update(update);
+            },
+            events => panic!("unexpected events {:?}", events),
+        }
+        assert_eq!(book.best_bid(), Some((dec!(64000), dec!(1.5))));
+        assert_eq!(book.best_ask(), Some((dec!(64001), dec!(0.8))));
+        assert!(connector.parse(r#"{"result":null,"id":1}"#).is_empty());
+    }
+
+    #[test]
+    fn test_parse_depth_sync_frames() {
+        let connector = BinanceConnector::new(ConnectorConfig::new());
+        let mut book = connector.new_book();
+        let snapshot = include_str!("../../tests/fixtures/binance/depth_snapshot.json");
+        let events: Vec<_> = include_str!("../../tests/fixtures/binance/depth_updates.jsonl").lines()
+            .map(|line| format!(r#"{{"stream":"btcusdt@depth@100ms","data":{}}}"#, line))
+            .collect();
+        for text in std::iter::once(snapshot).chain(events.iter().map(String::as_str)) {
+            for event in connector.parse(text) {
+                match event {
+                    FeedEvent::Book(update) => { book.update(&update); },
+                    event => panic!("unexpected event {:?}", event),
+                }
+            }
+        }
+        // The events up to the snapshot are skipped by their update ids
+        assert!(book.is_synced());
+        assert_eq!(book.best_bid(), Some((dec!(64000.50), dec!(0.3))));
+        assert_eq!(book.best_ask(), Some((dec!(64001.00), dec!(0.5))));
+    }
+
+    #[test]
+    fn test_partial_depths() {
+        assert_eq!([1, 5, 6, 10, 20, 25].map(partial_depth), [5, 5, 10, 10, 20, 20]);
+        let connector = BinanceConnector::new(ConnectorConfig::new().with_depth(25));
+        assert_eq!(connector.endpoint().as_str(), "wss://stream.binance.com:9443/ws/btcusdt@depth20@100ms");
+    }
+
+    #[test]
+    fn test_top_of_book_stream() {
+        let connector = BinanceConnector::new(ConnectorConfig::new().with_top_of_book());
+        assert_eq!(connector.endpoint().as_str(),
+                   "wss://stream.binance.com:9443/stream?streams=btcusdt@depth5@100ms/btcusdt@bookTicker");
+
+        let depth = r#"{"stream":"btcusdt@depth5@100ms","data":{"lastUpdateId":160,"bids":[["64000.00","1.5"]],"asks":[]}}"#;
+        assert!(matches!(connector.parse(depth).as_slice(), [FeedEvent::Book(update)] if update.is_snapshot()));
+        let ticker = include_str!("../../tests/fixtures/binance/book_ticker.json");
+        assert!(matches!(connector.parse(ticker).as_slice(), [FeedEvent::TopOfBook(top)] if top.ask_qty == dec!(0.6)));
+    }
+
+    #[test]
+    fn test_trade_stream() {
+        let connector = BinanceConnector::new(ConnectorConfig::new().with_trades());
+        assert_eq!(connector.endpoint().as_str(),
+                   "wss://stream.binance.com:9443/stream?streams=btcusdt@depth5@100ms/btcusdt@trade");
+        let connector = BinanceConnector::new(ConnectorConfig::new().with_top_of_book().with_trades());
+        assert!(connector.endpoint().as_str().ends_with("btcusdt@bookTicker/btcusdt@trade"));
+
+        let trade = r#"{"stream":"btcusdt@trade","data":{"e":"trade","E":1712000000210,"s":"BTCUSDT","t":3512000001,"p":"64000.50000000","q":"0.01200000","T":1712000000209,"m":true,"M":true}}"#;
+        match connector.parse(trade).as_slice() {
+            [FeedEvent::Trade(trade)] => {
+                assert_eq!((trade.trade_id, trade.price), (TradeId::Trade(3512000001), dec!(64000.5)));
+                assert_eq!(trade.received_ns, 0);
+            },
+            events => panic!("unexpected events {:?}", events),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Bybit v5 public spot orderbook feed
+use std::time::Duration;
+use url::Url;
+
+use crate::connector::{Connector, ConnectorConfig, FeedEvent, Sequence};
+use crate::messages::ms_to_ns;
+use crate::models::bybit::orderbook::{parse_orderbook, topic, Kind};
+use crate::quote::Exchange;
+
+pub const NAME: &str = "bybit";
+pub const DEFAULT_URL: &str = "wss://stream.bybit.com/v5/public/spot";
+pub const DEFAULT_SYMBOL: &str = "BTCUSDT";
+// Spot depths are 1, 50 and 200
+pub const DEFAULT_DEPTH: usize = 50;
+const PING_INTERVAL: Duration = Duration::from_secs(20);
+
+pub struct BybitConnector {
+    url: String,
+    symbol: String,
+    depth: usize,
+}
+
+impl BybitConnector {
+    pub fn new(config: ConnectorConfig) -> Self {
+        BybitConnector {
+            url: config.base_url.unwrap_or_else(|| DEFAULT_URL.to_string()),
+            symbol: config.symbol.unwrap_or_else(|| DEFAULT_SYMBOL.to_string()),
+            depth: config.depth.unwrap_or(DEFAULT_DEPTH),
+        }
+    }
+}
+
+impl Connector for BybitConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bybit
+    }
+
+    fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    fn endpoint(&self) -> Url {
+        Url::parse(&self.url).unwrap()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![serde_json::json!({
+            "op": "subscribe",
+            "args": [topic(self.depth, &self.symbol)],
+        }).to_string()]
+    }
+
+    fn parse(&self, text: &str) -> Vec<FeedEvent> {
+        if let Some(message) = parse_orderbook(text) {
+            if message.data.symbol == self.symbol {
+                return vec![FeedEvent::Book(message.to_update())];
+            }
+            return vec![];
+        }
+        // {"success": true, "ret_msg": "pong", "op": "ping", ...}
+        match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(reply) if reply["op"] == "ping" => vec![FeedEvent::Heartbeat],
+            _ => vec![],
+        }
+    }
+
+    fn sequence(&self, text: &str) -> Option<Sequence> {
+        let message = parse_orderbook(text)?;
+        match message.kind {
+            Kind::Snapshot => Some(Sequence::Reset(message.data.update_id)),
+            Kind::Delta => Some(Sequence::Next(message.data.update_id)),
+        }
+    }
+
+    fn exchange_ns(&self, text: &str) -> Option<u64> {
+        parse_orderbook(text).map(|message| ms_to_ns(message.ts))
+    }
+
+    fn ping(&self) -> Option<(Duration, String)> {
+        Some((PING_INTERVAL, serde_json::json!({"op": "ping"}).to_string()))
+    }
+
+    fn max_depth(&self) -> Option<usize> {
+        Some(self.depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = include_str!("../../tests/fixtures/bybit/orderbook_snapshot.json");
+    const DELTA: &str = include_str!("../../tests/fixtures/bybit/orderbook_delta.json");
+
+    #[test]
+    fn test_parse_and_sequence() {
+        let connector = BybitConnector::new(ConnectorConfig::new());
+        assert!(connector.subscribe_messages()[0].contains("orderbook.50.BTCUSDT"));
+        assert!(matches!(connector.parse(SNAPSHOT).as_slice(), [FeedEvent::Book(update)] if update.is_snapshot()));
+        assert_eq!(connector.exchange_ns(SNAPSHOT), Some(1_712_052_930_120_000_000));
+
+        let last = connector.sequence(SNAPSHOT).unwrap().follow(None).unwrap();
+        assert_eq!(connector.sequence(DELTA).unwrap().follow(Some(last)), Ok(501));
+        assert_eq!(connector.sequence(DELTA).unwrap().follow(Some(last - 1)), Err(500));
+
+        let pong = r#"{"success":true,"ret_msg":"pong","conn_id":"0970e817-426e-429a-a679-ff7f55e0b16a","op":"ping"}"#;
+        assert!(matches!(connector.parse(pong).as_slice(), [FeedEvent::Heartbeat]));
+        assert!(connector.parse(r#"{"success":true,"ret_msg":"","op":"subscribe"}"#).is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+// Coinbase Advanced Trade level2 feed, the heartbeats channel keeps the connection alive
+use url::Url;
+
+use crate::connector::{Connector, ConnectorConfig, FeedEvent, Sequence};
+use crate::messages::parse_rfc3339_ns;
+use crate::models::coinbase::level2::{parse_message, parse_sequence_num, Message};
+use crate::quote::Exchange;
+
+pub const NAME: &str = "coinbase";
+pub const DEFAULT_URL: &str = "wss://advanced-trade-ws.coinbase.com";
+pub const DEFAULT_PRODUCT: &str = "BTC-USD";
+
+pub struct CoinbaseConnector {
+    url: String,
+    product_id: String,
+}
+
+impl CoinbaseConnector {
+    pub fn new(config: ConnectorConfig) -> Self {
+        CoinbaseConnector {
+            url: config.base_url.unwrap_or_else(|| DEFAULT_URL.to_string()),
+            product_id: config.symbol.unwrap_or_else(|| DEFAULT_PRODUCT.to_string()),
+        }
+    }
+}
+
+impl Connector for CoinbaseConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Coinbase
+    }
+
+    fn symbol(&self) -> &str {
+        &self.product_id
+    }
+
+    fn endpoint(&self) -> Url {
+        Url::parse(&self.url).unwrap()
+    }
+
+    // level2 always starts with a full snapshot, the depth cannot be chosen
+    fn subscribe_messages(&self) -> Vec<String> {
+        ["level2", "heartbeats"].iter()
+            .map(|channel| serde_json::json!({
+                "type": "subscribe",
+                "product_ids": [self.product_id],
+                "channel": channel,
+            }).to_string())
+            .collect()
+    }
+
+    fn parse(&self, text: &str) -> Vec<FeedEvent> {
+        match parse_message(text) {
+            Some(Message::Level2 { events, .. }) => events.iter()
+                .filter(|event| event.product_id == self.product_id)
+                .map(|event| FeedEvent::Book(event.to_update()))
+                .collect(),
+            Some(Message::Heartbeats { .. }) => vec![FeedEvent::Heartbeat],
+            _ => vec![],
+        }
+    }
+
+    // Numbers every message of the connection, across channels
+    fn sequence(&self, text: &str) -> Option<Sequence> {
+        parse_sequence_num(text).map(Sequence::Next)
+    }
+
+    fn exchange_ns(&self, text: &str) -> Option<u64> {
+        match parse_message(text)? {
+            Message::Level2 { timestamp, .. } => parse_rfc3339_ns(&timestamp),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const SNAPSHOT: &str = include_str!("../../tests/fixtures/coinbase/level2_snapshot.json");
+    const UPDATE: &str = include_str!("../../tests/fixtures/coinbase/level2_update.json");
+    const HEARTBEATS: &str = include_str!("../../tests/fixtures/coinbase/heartbeats.json");
+
+    #[test]
+    fn test_parse() {
+        let connector = CoinbaseConnector::new(ConnectorConfig::new());
+        let mut book = connector.new_book();
+        for text in [SNAPSHOT, UPDATE] {
+            for event in connector.parse(text) {
+                if let FeedEvent::Book(update) = event {
+                    book.update(&update);
+                }
+            }
+        }
+        assert_eq!(book.best_ask(), Some((dec!(66011.00), dec!(2))));
+        assert!(matches!(connector.parse(HEARTBEATS).as_slice(), [FeedEvent::Heartbeat]));
+        assert_eq!(connector.sequence(HEARTBEATS), Some(Sequence::Next(3)));
+        assert_eq!(connector.exchange_ns(SNAPSHOT), Some(1_712_052_930_120_456_789));
+
+        // Other products of the connection are not this connector's
+        let other = CoinbaseConnector::new(ConnectorConfig::new().with_symbol("ETH-USD"));
+        assert!(other.parse(SNAPSHOT).is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+// Kraken websocket v1 book feed. v1 trades carry no trade id, trades are only parsed from v2,
+// see models::kraken::trade.
+use std::time::Duration;
+use serde_json::Value;
+use url::Url;
+
+use crate::connector::{Connector, ConnectorConfig, FeedEvent};
+use crate::models::book::Book;
+use crate::models::kraken::book::parse_frame;
+use crate::models::kraken::checksum::{self, CHECKSUM_DEPTH};
+use crate::models::kraken::ticker::{self, parse_ticker};
+use crate::models::order_book::{OrderBook, QuoteType};
+use crate::quote::Exchange;
+
+pub const NAME: &str = "kraken";
+pub const DEFAULT_URL: &str = "wss://ws.kraken.com";
+pub const DEFAULT_PAIR: &str = "XBT/USD";
+// Levels per side subscribed, the book keeps the same depth
+pub const DEFAULT_DEPTH: usize = 10;
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct KrakenConnector {
+    url: String,
+    pair: String,
+    depth: usize,
+    top_of_book: bool,
+}
+
+impl KrakenConnector {
+    pub fn new(config: ConnectorConfig) -> Self {
+        KrakenConnector {
+            url: config.base_url.unwrap_or_else(|| DEFAULT_URL.to_string()),
+            pair: config.symbol.unwrap_or_else(|| DEFAULT_PAIR.to_string()),
+            depth: config.depth.unwrap_or(DEFAULT_DEPTH),
+            top_of_book: config.top_of_book,
+        }
+    }
+}
+
+impl Connector for KrakenConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Kraken
+    }
+
+    fn symbol(&self) -> &str {
+        &self.pair
+    }
+
+    fn endpoint(&self) -> Url {
+        Url::parse(&self.url).unwrap()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        let mut messages = vec![serde_json::json!({
+            "event": "subscribe",
+            "pair": [self.pair],
+            "subscription": {
+                "name": "book",
+                "depth": self.depth,
+            }
+        }).to_string()];
+        if self.top_of_book {
+            messages.push(serde_json::json!({
+                "event": "subscribe",
+                "pair": [self.pair],
+                "subscription": {"name": ticker::CHANNEL}
+            }).to_string());
+        }
+        messages
+    }
+
+    fn parse(&self, text: &str) -> Vec<FeedEvent> {
+        if let Ok(frame) = parse_frame(text) {
+            let mut events = vec![FeedEvent::Book(frame.to_update())];
+            // Verified once the update is applied
+            events.extend(frame.checksum().map(FeedEvent::Checksum));
+            return events;
+        }
+        if let Some(frame) = parse_ticker(text) {
+            return vec![FeedEvent::TopOfBook(frame.to_top_of_book())];
+        }
+        match serde_json::from_str::<Value>(text) {
+            Ok(Value::Object(event)) if event.get("event").and_then(Value::as_str) == Some("heartbeat") =>
+                vec![FeedEvent::Heartbeat],
+            _ => vec![],
+        }
+    }
+
+    // Top 10 asks ascending then top 10 bids descending, at the scale they were received
+    fn checksum(&self, book: &OrderBook) -> Option<u32> {
+        let asks = book.top_levels(QuoteType::ASK, CHECKSUM_DEPTH);
+        let bids = book.top_levels(QuoteType::BID, CHECKSUM_DEPTH);
+        Some(checksum::compute_exact(
+            asks.iter().map(|(price, volume)| (price, volume)),
+            bids.iter().map(|(price, volume)| (price, volume)),
+        ))
+    }
+
+    fn ping(&self) -> Option<(Duration, String)> {
+        Some((PING_INTERVAL, serde_json::json!({"event": "ping"}).to_string()))
+    }
+
+    fn max_depth(&self) -> Option<usize> {
+        Some(self.depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use rust_decimal::Decimal;
+    use crate::models::book::BookKind;
+    use crate::models::kraken::book as v1;
+    use crate::models::order_book::{OrderBookUpdate, PriceLevel};
+
+    #[test]
+    fn test_subscribe_top_of_book() {
+        assert_eq!(KrakenConnector::new(ConnectorConfig::new()).subscribe_messages().len(), 1);
+        let messages = KrakenConnector::new(ConnectorConfig::new().with_top_of_book()).subscribe_messages();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].contains(r#""name":"ticker""#));
+    }
+
+    #[test]
+    fn test_parse() {
+        let connector = KrakenConnector::new(ConnectorConfig::new());
+        let mut book = connector.new_book();
+
+        let snapshot = r#"[0,{"as":[["5541.30000","2.50700000","1534614248.123678"]],"bs":[["5541.20000","1.52900000","1534614248.765567"]]},"book-10","XBT/USD"]"#;
+        let update = r#"[0,{"a":[["5541.30000","0.00000000","1534614335.345903"]]},{"b":[["5541.25000","1.00000000","1534614335.345903","r"]],"c":"974942666"},"book-10","XBT/USD"]"#;
+        // Same frames through the v1 book model
+        let mut v1_book = v1::OrderBook::new("book-10".to_string(), "XBT/USD".to_string());
+        match connector.parse(snapshot).as_slice() {
+            [FeedEvent::Book(update)] => { book.update(update); },
+            events => panic!("unexpected events {:?}", events),
+        }
+        v1_book.apply(&parse_frame(snapshot).unwrap()).unwrap();
+        match connector.parse(update).as_slice() {
+            [FeedEvent::Book(update), FeedEvent::Checksum(expected)] => {
+                book.update(update);
+                assert_eq!(*expected, 974942666);
+            },
+            events => panic!("unexpected events {:?}", events),
+        }
+        let _ = v1_book.apply(&parse_frame(update).unwrap());
+        assert_eq!(connector.checksum(&book), Some(v1_book.checksum()));
+        assert_eq!(book.best_bid(), Some((dec!(5541.25), dec!(1))));
+        assert_eq!(book.best_ask(), None);
+
+        assert!(matches!(connector.parse(r#"{"event":"heartbeat"}"#).as_slice(), [FeedEvent::Heartbeat]));
+        let ticker = include_str!("../../tests/fixtures/kraken/ticker.json");
+        assert!(matches!(connector.parse(ticker).as_slice(), [FeedEvent::TopOfBook(top)] if top.bid_price == dec!(64009.95)));
+        assert!(connector.parse(r#"{"event":"systemStatus","status":"online"}"#).is_empty());
+    }
+
+    #[test]
+    fn test_books_keep_subscribed_depth() {
+        let connector = KrakenConnector::new(ConnectorConfig::new().with_depth(2));
+        assert_eq!(connector.new_book().max_depth(), Some(2));
+        let levels: Vec<PriceLevel> = (1..=4).flat_map(|i| [
+            PriceLevel::new(Decimal::from(100 - i), dec!(1), QuoteType::BID),
+            PriceLevel::new(Decimal::from(100 + i), dec!(1), QuoteType::ASK),
+        ]).collect();
+        for kind in [BookKind::BTree, BookKind::Pooled] {
+            let mut book = connector.new_book_of(kind);
+            book.apply(&OrderBookUpdate::snapshot(levels.clone()));
+            assert_eq!(book.top_levels(QuoteType::BID, 10), vec![(dec!(99), dec!(1)), (dec!(98), dec!(1))], "{}", kind);
+            assert_eq!(book.top_levels(QuoteType::ASK, 10).len(), 2, "{}", kind);
+        }
+    }
+}
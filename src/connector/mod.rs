@@ -0,0 +1,238 @@
+// Venue connectors: everything venue specific needed to listen to a book feed, so that a new
+// venue only has to be registered here.
+use std::collections::BTreeMap;
+use std::time::Duration;
+use url::Url;
+
+use crate::models::book::{self, Book, BookKind};
+use crate::models::order_book::{OrderBook, OrderBookUpdate};
+use crate::models::top_of_book::TopOfBook;
+use crate::models::trade::Trade;
+use crate::quote::Exchange;
+
+pub mod binance;
+pub mod bybit;
+pub mod coinbase;
+pub mod kraken;
+pub mod okx;
+
+/// Overrides of a connector's defaults, unset fields keep the venue's default.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectorConfig {
+    pub symbol: Option<String>,
+    pub depth: Option<usize>,
+    pub base_url: Option<String>,
+    // Also listen to the venue's best bid and offer channel
+    pub top_of_book: bool,
+    // Also listen to the venue's trades, a venue without trade ids on its feed ignores it
+    pub trades: bool,
+}
+
+impl ConnectorConfig {
+    pub fn new() -> Self {
+        ConnectorConfig::default()
+    }
+
+    pub fn with_symbol(mut self, symbol: &str) -> Self {
+        self.symbol = Some(symbol.to_string());
+        self
+    }
+
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    // Base websocket URL, e.g. a local stand-in instead of the venue
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn with_top_of_book(mut self) -> Self {
+        self.top_of_book = true;
+        self
+    }
+
+    pub fn with_trades(mut self) -> Self {
+        self.trades = true;
+        self
+    }
+}
+
+/// A venue message normalized.
+#[derive(Debug, Clone)]
+pub enum FeedEvent {
+    Book(OrderBookUpdate),
+    // Checksum of the book once the preceding update is applied, see Connector::checksum
+    Checksum(u32),
+    // Best bid and offer of the ticker channel, see ConnectorConfig::with_top_of_book
+    TopOfBook(TopOfBook),
+    // An execution of the trade channel, see ConnectorConfig::with_trades. The connector doesn't
+    // know when the frame was received, received_ns is 0.
+    Trade(Trade),
+    Heartbeat,
+}
+
+/// Position of a message in the venue's sequence, a message not continuing the previous one
+/// means messages were lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sequence {
+    // Must be the previous number plus one
+    Next(u64),
+    // Carries the number of the message it follows
+    Chained { previous: u64, current: u64 },
+    // Starts a new sequence, e.g. a snapshot
+    Reset(u64),
+}
+
+impl Sequence {
+    // The number the next message must continue from, or the number that was expected
+    pub fn follow(&self, last: Option<u64>) -> Result<u64, u64> {
+        match (*self, last) {
+            (Sequence::Next(current), Some(last)) if current != last + 1 => Err(last + 1),
+            (Sequence::Chained { previous, .. }, Some(last)) if previous != last => Err(last),
+            (Sequence::Next(current), _)
+            | (Sequence::Chained { current, .. }, _)
+            | (Sequence::Reset(current), _) => Ok(current),
+        }
+    }
+}
+
+pub trait Connector: Send + Sync {
+    fn exchange(&self) -> Exchange;
+
+    fn symbol(&self) -> &str;
+
+    fn endpoint(&self) -> Url;
+
+    // Sent once connected, in order
+    fn subscribe_messages(&self) -> Vec<String>;
+
+    // Messages that are not book data nor heartbeats (acks, status, ...) give no event
+    fn parse(&self, text: &str) -> Vec<FeedEvent>;
+
+    // For venues numbering their messages
+    fn sequence(&self, _text: &str) -> Option<Sequence> {
+        None
+    }
+
+    // Time the venue stamped the message with, nanoseconds since epoch
+    fn exchange_ns(&self, _text: &str) -> Option<u64> {
+        None
+    }
+
+    // The venue's checksum of the book, compared with FeedEvent::Checksum
+    fn checksum(&self, _book: &OrderBook) -> Option<u32> {
+        None
+    }
+
+    // Message to send periodically to keep the connection alive, if the venue needs one
+    fn ping(&self) -> Option<(Duration, String)> {
+        None
+    }
+
+    // Levels per side the feed maintains, the books drop the levels beyond
+    fn max_depth(&self) -> Option<usize> {
+        None
+    }
+
+    // A book configured the way the feed maintains it
+    fn new_book(&self) -> OrderBook {
+        match self.max_depth() {
+            Some(depth) => OrderBook::new().with_max_depth(depth),
+            None => OrderBook::new(),
+        }
+    }
+
+    // Same, of any implementation
+    fn new_book_of(&self, kind: BookKind) -> Box<dyn Book + Send> {
+        book::new_book(kind, self.max_depth())
+    }
+}
+
+pub type ConnectorFactory = fn(ConnectorConfig) -> Box<dyn Connector>;
+
+/// Connector factories by venue name.
+pub struct ConnectorRegistry {
+    factories: BTreeMap<&'static str, ConnectorFactory>,
+    // Name each venue's connector is registered under
+    names: BTreeMap<Exchange, &'static str>,
+}
+
+impl ConnectorRegistry {
+    // A registry without any venue, see default() for the built-in ones
+    pub fn new() -> Self {
+        ConnectorRegistry { factories: BTreeMap::new(), names: BTreeMap::new() }
+    }
+
+    pub fn register(&mut self, name: &'static str, factory: ConnectorFactory) {
+        self.names.insert(factory(ConnectorConfig::new()).exchange(), name);
+        self.factories.insert(name, factory);
+    }
+
+    pub fn create(&self, name: &str, config: ConnectorConfig) -> Option<Box<dyn Connector>> {
+        self.factories.get(name).map(|factory| factory(config))
+    }
+
+    pub fn name_of(&self, exchange: Exchange) -> Option<&'static str> {
+        self.names.get(&exchange).copied()
+    }
+
+    pub fn create_for(&self, exchange: Exchange, config: ConnectorConfig) -> Option<Box<dyn Connector>> {
+        self.create(self.name_of(exchange)?, config)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.factories.keys().copied()
+    }
+}
+
+impl Default for ConnectorRegistry {
+    fn default() -> Self {
+        let mut registry = ConnectorRegistry::new();
+        registry.register(kraken::NAME, |config| Box::new(kraken::KrakenConnector::new(config)));
+        registry.register(binance::NAME, |config| Box::new(binance::BinanceConnector::new(config)));
+        registry.register(coinbase::NAME, |config| Box::new(coinbase::CoinbaseConnector::new(config)));
+        registry.register(okx::NAME, |config| Box::new(okx::OkxConnector::new(config)));
+        registry.register(bybit::NAME, |config| Box::new(bybit::BybitConnector::new(config)));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let registry = ConnectorRegistry::default();
+        assert_eq!(registry.names().collect::<Vec<_>>(), vec!["binance", "bybit", "coinbase", "kraken", "okx"]);
+        assert!(registry.create("unknown", ConnectorConfig::new()).is_none());
+
+        for exchange in [Exchange::Kraken, Exchange::Binance, Exchange::Coinbase, Exchange::Okx, Exchange::Bybit] {
+            let connector = registry.create_for(exchange, ConnectorConfig::new()).unwrap();
+            assert_eq!(connector.exchange(), exchange);
+        }
+        assert_eq!(registry.name_of(Exchange::Okx), Some(okx::NAME));
+        assert!(ConnectorRegistry::new().name_of(Exchange::Okx).is_none());
+    }
+
+    #[test]
+    fn test_sequence_follow() {
+        assert_eq!(Sequence::Next(5).follow(None), Ok(5));
+        assert_eq!(Sequence::Next(5).follow(Some(4)), Ok(5));
+        assert_eq!(Sequence::Next(6).follow(Some(4)), Err(5));
+        assert_eq!(Sequence::Chained { previous: 4, current: 9 }.follow(Some(4)), Ok(9));
+        assert_eq!(Sequence::Chained { previous: 7, current: 9 }.follow(Some(4)), Err(4));
+        assert_eq!(Sequence::Reset(1).follow(Some(4)), Ok(1));
+    }
+
+    #[test]
+    fn test_base_url_override() {
+        let registry = ConnectorRegistry::default();
+        let config = ConnectorConfig::new().with_base_url("ws://127.0.0.1:9001").with_symbol("ETHUSDT");
+        let connector = registry.create("binance", config).unwrap();
+        assert_eq!(connector.endpoint().as_str(), "ws://127.0.0.1:9001/ws/ethusdt@depth5@100ms");
+    }
+}
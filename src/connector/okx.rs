@@ -0,0 +1,138 @@
+// OKX v5 public books feed
+use std::time::Duration;
+use url::Url;
+
+use crate::connector::{Connector, ConnectorConfig, FeedEvent, Sequence};
+use crate::messages::ms_to_ns;
+use crate::models::okx::books::{self, parse_books, Action};
+use crate::models::order_book::OrderBook;
+use crate::quote::Exchange;
+
+pub const NAME: &str = "okx";
+pub const DEFAULT_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+pub const DEFAULT_INSTRUMENT: &str = "BTC-USDT";
+// Levels per side of the books channel
+pub const BOOKS_DEPTH: usize = 400;
+// OKX drops connections silent for 30 seconds
+const PING_INTERVAL: Duration = Duration::from_secs(25);
+
+pub struct OkxConnector {
+    url: String,
+    inst_id: String,
+}
+
+impl OkxConnector {
+    pub fn new(config: ConnectorConfig) -> Self {
+        OkxConnector {
+            url: config.base_url.unwrap_or_else(|| DEFAULT_URL.to_string()),
+            inst_id: config.symbol.unwrap_or_else(|| DEFAULT_INSTRUMENT.to_string()),
+        }
+    }
+}
+
+impl Connector for OkxConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Okx
+    }
+
+    fn symbol(&self) -> &str {
+        &self.inst_id
+    }
+
+    fn endpoint(&self) -> Url {
+        Url::parse(&self.url).unwrap()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        vec![serde_json::json!({
+            "op": "subscribe",
+            "args": [{"channel": "books", "instId": self.inst_id}],
+        }).to_string()]
+    }
+
+    // Each data is followed by its checksum
+    fn parse(&self, text: &str) -> Vec<FeedEvent> {
+        if text == "pong" {
+            return vec![FeedEvent::Heartbeat];
+        }
+        let message = match parse_books(text) {
+            Some(message) if message.arg.inst_id == self.inst_id => message,
+            _ => return vec![],
+        };
+        message.data.iter()
+            .flat_map(|data| [
+                FeedEvent::Book(data.to_update(message.action)),
+                FeedEvent::Checksum(data.checksum as u32),
+            ])
+            .collect()
+    }
+
+    // prevSeqId is the seqId of the previous message of the channel
+    fn sequence(&self, text: &str) -> Option<Sequence> {
+        let message = parse_books(text)?;
+        let data = message.data.last()?;
+        let current = data.seq_id as u64;
+        match message.action {
+            Action::Snapshot => Some(Sequence::Reset(current)),
+            Action::Update => Some(Sequence::Chained { previous: data.prev_seq_id as u64, current }),
+        }
+    }
+
+    // Milliseconds, as a string
+    fn exchange_ns(&self, text: &str) -> Option<u64> {
+        let message = parse_books(text)?;
+        message.data.last()?.ts.parse().ok().map(ms_to_ns)
+    }
+
+    fn checksum(&self, book: &OrderBook) -> Option<u32> {
+        Some(books::checksum(book) as u32)
+    }
+
+    fn ping(&self) -> Option<(Duration, String)> {
+        Some((PING_INTERVAL, "ping".to_string()))
+    }
+
+    fn max_depth(&self) -> Option<usize> {
+        Some(BOOKS_DEPTH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = include_str!("../../tests/fixtures/okx/books_snapshot.json");
+    const UPDATE: &str = include_str!("../../tests/fixtures/okx/books_update.json");
+
+    #[test]
+    fn test_parse_and_verify() {
+        let connector = OkxConnector::new(ConnectorConfig::new());
+        let mut book = connector.new_book();
+        let mut verified = 0;
+        for text in [SNAPSHOT, UPDATE] {
+            for event in connector.parse(text) {
+                match event {
+                    FeedEvent::Book(update) => { book.update(&update); },
+                    FeedEvent::Checksum(expected) => {
+                        assert_eq!(connector.checksum(&book), Some(expected));
+                        verified += 1;
+                    },
+                    event => panic!("unexpected event {:?}", event),
+                }
+            }
+        }
+        assert_eq!(verified, 2);
+        assert!(matches!(connector.parse("pong").as_slice(), [FeedEvent::Heartbeat]));
+        assert_eq!(connector.exchange_ns(SNAPSHOT), Some(1_712_052_930_120_000_000));
+    }
+
+    #[test]
+    fn test_sequence() {
+        let connector = OkxConnector::new(ConnectorConfig::new());
+        let snapshot = connector.sequence(SNAPSHOT).unwrap();
+        assert_eq!(snapshot, Sequence::Reset(1000));
+        let update = connector.sequence(UPDATE).unwrap();
+        assert_eq!(update.follow(Some(1000)), Ok(1003));
+        assert_eq!(update.follow(Some(999)), Err(999));
+    }
+}
@@ -1,7 +1,15 @@
 pub mod quote;
 pub mod messages;
 pub mod connect_and_listen;
+pub mod connector;
 
 pub mod log_messages;
 
-pub mod models;
\ No newline at end of file
+pub mod models;
+
+pub mod metrics;
+
+pub mod recorder;
+pub mod replay;
+
+pub mod mock_exchange;
\ No newline at end of file
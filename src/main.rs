@@ -13,10 +13,26 @@ use serde_json::{Value, Result};
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 use url::Url;
 
-use websocket::messages::IncomingMsg;
-use websocket::connect_and_listen::{connect_and_listen_kraken, connect_and_listen_binance};
-use websocket::quote::Quote;
-use websocket::quote::Exchange;
+use websocket::messages::{now_ns, IncomingMsg};
+use websocket::connect_and_listen::{supervise, supervise_binance_depth, BinanceDepthConfig, ConnectionEvent, ReconnectConfig};
+use websocket::connector::{Connector, ConnectorConfig, ConnectorRegistry, FeedEvent};
+use websocket::models::arbitrage::{ArbitrageDetector, FeeSchedule};
+use websocket::models::book_registry::{BookKey, BookRegistry};
+use websocket::models::consolidated_book::ConsolidatedBook;
+use websocket::models::fx::FxRates;
+use websocket::models::instrument::Instrument;
+use websocket::models::instrument_registry::InstrumentRegistry;
+use websocket::models::kraken::instrument::parse_instruments as kraken_instruments;
+use websocket::models::binance::exchange_info::parse_instruments as binance_instruments;
+use websocket::models::order_book::{ChecksumMismatch, OrderBook, UpdateOutcome};
+use websocket::models::top_of_book::{ConsistencyMonitor, TopOfBook};
+use websocket::models::trade::TradeTapes;
+use websocket::quote::{Exchange, Quote, QuoteError};
+use websocket::recorder::{self, Recorder};
+use websocket::replay::{replay_incoming, ReplaySpeed};
+use std::collections::{BTreeMap, HashMap};
+use rust_decimal::Decimal;
+use std::env;
 
 // struct IncomingMsg {
 //     exchange: Exchange,
@@ -92,53 +108,289 @@ use websocket::quote::Exchange;
 // }
 
 
-async fn process_and_compare_quotes(mut receiver: mpsc::Receiver<IncomingMsg>) {
-    let mut last_mid_prices: [Option<f64>; 2] = [None, None]; // [Kraken, Binance]
+// Venues listened to, comma separated names of registered connectors
+const DEFAULT_VENUES: &str = "kraken,binance";
 
-    while let Some(msg) = receiver.recv().await {
+// Books giving the FX rates between quote currencies, comma separated venue:symbol
+const DEFAULT_FX_BOOKS: &str = "kraken:USDT/USD";
+
+// Books and quotes of every venue are compared in this currency
+const REFERENCE_CURRENCY: &str = "USD";
+
+// Base tier taker fees of the venues, in bps
+fn taker_fees() -> FeeSchedule {
+    FeeSchedule::new()
+        .with_taker_bps(Exchange::Kraken, Decimal::from(40))
+        .with_taker_bps(Exchange::Binance, Decimal::from(10))
+        .with_taker_bps(Exchange::Coinbase, Decimal::from(60))
+        .with_taker_bps(Exchange::Okx, Decimal::from(10))
+        .with_taker_bps(Exchange::Bybit, Decimal::from(10))
+}
+
+// Trading rules from INSTRUMENT_FILES, comma separated Kraken instrument snapshots or Binance
+// exchangeInfo responses
+fn load_instruments() -> InstrumentRegistry {
+    let mut instruments = InstrumentRegistry::new();
+    let paths = env::var("INSTRUMENT_FILES").unwrap_or_default();
+    for path in paths.split(',').map(str::trim).filter(|path| !path.is_empty()) {
+        let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+        let mut specs = kraken_instruments(&text);
+        specs.extend(binance_instruments(&text));
+        println!("loaded {} instruments from {}", instruments.extend(specs), path);
+    }
+    instruments
+}
+
+// resync reaches the supervisor of each live feed, a book failing its checksum is resubscribed
+async fn process_and_compare_quotes(mut receiver: mpsc::Receiver<IncomingMsg>, mut events: mpsc::Receiver<ConnectionEvent>,
+                                    resync: HashMap<BookKey, mpsc::Sender<String>>, mut fx: FxRates) {
+    let registry = ConnectorRegistry::default();
+    // Parser of each (venue, symbol) feed, its book is in books
+    let mut parsers: HashMap<BookKey, Box<dyn Connector>> = HashMap::new();
+    let mut books = BookRegistry::new();
+    // Levels of the symbols with a spec are checked against it
+    let instruments = load_instruments();
+    // Frames the feed's connector made nothing of, by the reason they give no quote
+    let mut rejects: BTreeMap<&'static str, u64> = BTreeMap::new();
+    // Last ticker of each feed, checked against its book
+    let mut tickers: HashMap<BookKey, TopOfBook> = HashMap::new();
+    let mut monitor = ConsistencyMonitor::new();
+    // Executions of each feed subscribed with its trades
+    let mut tapes = TradeTapes::new();
+    let mut arbitrage = ArbitrageDetector::new(taker_fees());
+
+    loop {
+        let msg = tokio::select! {
+            // A connection event is always sent before the frames that follow it
+            biased;
+            Some(event) = events.recv() => {
+                println!("{}", event);
+                // The feed's book is stale until the snapshot sent after resubscribing, the other
+                // feeds of the venue have their own connections
+                if let ConnectionEvent::Disconnected { exchange, symbol, .. } = event {
+                    let key = BookKey::new(exchange, &symbol);
+                    books.mark_stale_key(&key);
+                    fx.update_registry(&books, now_ns());
+                    tickers.remove(&key);
+                }
+                continue;
+            },
+            msg = receiver.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+        };
         println!("{}", msg.msg);
-        if let Some(quote) = Quote::parse(&msg) {
-            match quote.exchange {
-                Exchange::Kraken => last_mid_prices[0] = Some((quote.best_bid + quote.best_ask) / 2.0),
-                Exchange::Binance => last_mid_prices[1] = Some((quote.best_bid + quote.best_ask) / 2.0),
+        let key = BookKey::new(msg.exchange, &msg.symbol);
+        let parser = parsers.entry(key.clone()).or_insert_with(|| {
+            let connector = registry.create_for(msg.exchange, ConnectorConfig::new().with_symbol(&msg.symbol))
+                .expect("No connector registered for the venue");
+            books.subscribe(msg.exchange, &msg.symbol, connector.new_book());
+            connector
+        });
+        let feed_events = parser.parse(&msg.msg);
+        if feed_events.is_empty() {
+            if let Err(e) = Quote::parse(&msg, parser.as_ref()) {
+                *rejects.entry(e.kind()).or_insert(0) += 1;
+                if !matches!(e, QuoteError::WrongChannel(_)) {
+                    println!("{} rejected: {} (rejects: {:?})", key, e, rejects);
+                }
             }
         }
+        // Tickers and heartbeats leave the book as it was, nothing to consolidate again
+        let mut book_changed = false;
+        for event in feed_events {
+            match event {
+                FeedEvent::Book(update) => match instruments.check_update(msg.exchange, &msg.symbol, &update) {
+                    Ok(()) => {
+                        // A gap leaves the book stale, consolidated as empty it closes its opportunities
+                        book_changed |= books.route(msg.exchange, &msg.symbol, update)
+                            .is_some_and(|routed| matches!(routed.outcome, UpdateOutcome::Applied | UpdateOutcome::Gap));
+                    },
+                    // The spec or the book is wrong, either way the book can't be trusted anymore
+                    Err(violation) => {
+                        if let Some(book) = books.get_mut(msg.exchange, &msg.symbol) {
+                            book.mark_stale();
+                        }
+                        book_changed = true;
+                        println!("{} {}, resubscribing", key, violation);
+                        if let Some(resync) = resync.get(&key) {
+                            let _ = resync.try_send(violation.to_string());
+                        }
+                    },
+                },
+                FeedEvent::Checksum(expected) => {
+                    let book = match books.get_mut(msg.exchange, &msg.symbol) {
+                        Some(book) if book.is_synced() => book,
+                        _ => continue,
+                    };
+                    match parser.checksum(book) {
+                        Some(computed) if computed != expected => {
+                            book.mark_stale();
+                            book_changed = true;
+                            let mismatch = ChecksumMismatch { expected, computed };
+                            println!("{} {}, resubscribing", key, mismatch);
+                            if let Some(resync) = resync.get(&key) {
+                                let _ = resync.try_send(mismatch.to_string());
+                            }
+                        },
+                        _ => {},
+                    }
+                },
+                FeedEvent::TopOfBook(top) => { tickers.insert(key.clone(), top); },
+                FeedEvent::Trade(mut trade) => {
+                    trade.received_ns = msg.received_ns;
+                    let line = trade.to_string();
+                    if !tapes.push(trade) {
+                        println!("{} already on the tape", line);
+                    }
+                },
+                FeedEvent::Heartbeat => {},
+            }
+        }
+        // FX books only move the rates
+        if fx.is_source(&key) {
+            if let Some(book) = books.get(msg.exchange, &msg.symbol) {
+                fx.update(&key, book, msg.received_ns);
+            }
+            continue;
+        }
+        if let (Some(book), Some(top)) = (books.get(msg.exchange, &msg.symbol), tickers.get(&key)) {
+            if book.is_synced() {
+                if let Some(report) = monitor.check(top, book, msg.received_ns) {
+                    println!("{} ({})", report, monitor.stats(&key));
+                }
+            }
+        }
+
+        // A book that just went stale is consolidated again too, it closes its opportunities
+        if !book_changed {
+            continue;
+        }
+
+        // Venues are merged and compared in the reference currency, those quoting in a currency
+        // without a rate yet are left out
+        if let Some(instrument) = Instrument::from_venue(msg.exchange, &msg.symbol) {
+            let mut consolidated = ConsolidatedBook::new(Instrument::new(&instrument.base, fx.reference())).with_fx(fx.clone());
+            if consolidated.add_registry(&books) > 1 {
+                if let Some(bbo) = consolidated.bbo() {
+                    println!("Consolidated {} {}", consolidated.instrument(), bbo);
+                }
+            }
 
-        // Compare mid prices if both are available
-        if let (Some(kraken_mid), Some(binance_mid)) = (last_mid_prices[0], last_mid_prices[1]) {
-            println!("Mid Price Difference (Kraken - Binance): {}", kraken_mid - binance_mid);
+            // Stale or unconvertible books of the instrument are passed as empty ones, they close
+            // their opportunities
+            let converted: Vec<_> = books.iter()
+                .filter(|(key, _)| Instrument::from_venue(key.exchange, &key.symbol).is_some_and(|other| other.base == instrument.base))
+                .map(|(key, book)| (key, fx.convert_venue_book(key, book, fx.reference()).unwrap_or_else(OrderBook::new)))
+                .collect();
+            let instrument_books: Vec<_> = converted.iter().map(|(key, book)| (*key, book)).collect();
+            for event in arbitrage.check(&instrument_books, msg.received_ns) {
+                println!("{}", event);
+            }
         }
     }
 }
 
+// Feeds the pipeline from a recording instead of the exchanges when REPLAY_FILE is set.
+// REPLAY_SPEED is "original", "max" (default) or an acceleration factor.
+async fn replay_feed(path: String, tx: mpsc::Sender<IncomingMsg>) {
+    let speed = env::var("REPLAY_SPEED").ok()
+        .map(|speed| speed.parse::<ReplaySpeed>().expect("Invalid REPLAY_SPEED"))
+        .unwrap_or(ReplaySpeed::AsFastAsPossible);
+    match replay_incoming(&path, speed, tx).await {
+        Ok(frames) => println!("replayed {} frames from {}", frames, path),
+        Err(e) => eprintln!("Failed to replay {}: {}", path, e),
+    }
+}
+
+// Every raw frame is appended to RECORD_FILE when it is set
+async fn record_and_process(rx: mpsc::Receiver<IncomingMsg>, events: mpsc::Receiver<ConnectionEvent>,
+                            resync: HashMap<BookKey, mpsc::Sender<String>>, fx: FxRates) {
+    let rx = match env::var("RECORD_FILE") {
+        Ok(path) => {
+            let recorder = Recorder::open(&path).await.expect("Failed to open recording file");
+            recorder::tee(rx, recorder)
+        },
+        Err(_) => rx,
+    };
+    process_and_compare_quotes(rx, events, resync, fx).await
+}
+
 fn main() {
     let rt = Arc::new(Mutex::new(Runtime::new().expect("Failed to create Tokio runtime")));
     let (tx1, rx1) = mpsc::channel(32);
+    let (events_tx, events_rx) = mpsc::channel(32);
 
     // let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
 
-    // Launch the WebSocket listeners
-    let kraken_handle = {
-        let rt = rt.clone();
-        let tx1 = tx1.clone();
-        thread::spawn(move || {
-            rt.lock().unwrap().block_on(connect_and_listen_kraken(tx1));
-        })
-    };
+    // Each thread drives its task through a handle: holding the lock for the whole block_on
+    // would serialize the threads
+    let handle = rt.lock().unwrap().handle().clone();
 
-    let binance_handle = {
-        let rt = rt.clone();
-        thread::spawn(move || {
-            rt.lock().unwrap().block_on(connect_and_listen_binance(tx1));
-        })
+    let registry = ConnectorRegistry::default();
+    let fx_books = env::var("FX_BOOKS").unwrap_or_else(|_| DEFAULT_FX_BOOKS.to_string());
+    // Each connector with the config it was built from
+    let fx_connectors: Vec<_> = fx_books.split(',').filter(|book| !book.trim().is_empty()).map(|book| {
+        let (venue, symbol) = book.trim().split_once(':')
+            .unwrap_or_else(|| panic!("Invalid FX book {}, expected venue:symbol", book));
+        let config = ConnectorConfig::new().with_symbol(symbol);
+        let connector = registry.create(venue, config.clone())
+            .unwrap_or_else(|| panic!("Unknown venue {}, registered: {:?}", venue, registry.names().collect::<Vec<_>>()));
+        (connector, config)
+    }).collect();
+    let fx = fx_connectors.iter()
+        .fold(FxRates::new(REFERENCE_CURRENCY), |fx, (connector, _)| fx.with_source(connector.exchange(), connector.symbol()));
+
+    // Launch the WebSocket listeners, or the replay
+    let mut resync = HashMap::new();
+    let feed_handles: Vec<_> = match env::var("REPLAY_FILE") {
+        Ok(path) => {
+            let handle = handle.clone();
+            vec![thread::spawn(move || {
+                handle.block_on(replay_feed(path, tx1));
+            })]
+        },
+        Err(_) => {
+            let venues = env::var("VENUES").unwrap_or_else(|_| DEFAULT_VENUES.to_string());
+            let venue_connectors: Vec<_> = venues.split(',').map(|name| {
+                let config = ConnectorConfig::new().with_top_of_book().with_trades();
+                let connector = registry.create(name.trim(), config.clone())
+                    .unwrap_or_else(|| panic!("Unknown venue {}, registered: {:?}", name, registry.names().collect::<Vec<_>>()));
+                (connector, config)
+            }).collect();
+            let listener_handles = venue_connectors.into_iter().chain(fx_connectors).map(|(connector, config)| {
+                let (resync_tx, resync_rx) = mpsc::channel(8);
+                resync.insert(BookKey::new(connector.exchange(), connector.symbol()), resync_tx);
+                let handle = handle.clone();
+                let tx1 = tx1.clone();
+                let events_tx = events_tx.clone();
+                thread::spawn(move || {
+                    // The partial depth stream only gives the top levels, the full Binance book is
+                    // synced from the diff-depth stream and a REST snapshot
+                    let feed = async move {
+                        match connector.exchange() {
+                            Exchange::Binance => supervise_binance_depth(BinanceDepthConfig::for_connector(&config),
+                                                                         ReconnectConfig::default(), tx1, events_tx, resync_rx).await,
+                            _ => supervise(connector, ReconnectConfig::default(), tx1, events_tx, resync_rx).await,
+                        }
+                    };
+                    handle.block_on(feed);
+                })
+            }).collect();
+            // The handler stops once every listener has
+            drop(tx1);
+            drop(events_tx);
+            listener_handles
+        },
     };
 
 
     // Launch the handler
     let process_and_compare_handle = {
-        let rt = rt.clone();
+        let handle = handle.clone();
         thread::spawn(move || {
-            rt.lock().unwrap().block_on(process_and_compare_quotes(rx1));
+            handle.block_on(record_and_process(rx1, events_rx, resync, fx));
         })
     };
 
@@ -147,9 +399,8 @@ fn main() {
     // shutdown_tx.blocking_send(()).expect("Failed to send shutdown signal");
 
     // Wait for the threads to complete
-    kraken_handle.join().expect("Kraken thread panicked");
-    binance_handle.join().expect("Binance thread panicked");
+    for feed_handle in feed_handles {
+        feed_handle.join().expect("Feed thread panicked");
+    }
     process_and_compare_handle.join().expect("Logger thread panicked");
 }
-
-
@@ -1,6 +1,36 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 use crate::quote::Exchange;
 
 pub struct IncomingMsg {
     pub exchange: Exchange,
+    pub symbol: String,
+    // Local receive time, nanoseconds since epoch
+    pub received_ns: u64,
     pub msg: String,
-}
\ No newline at end of file
+}
+
+impl IncomingMsg {
+    pub fn new(exchange: Exchange, symbol: &str, msg: String) -> Self {
+        IncomingMsg { exchange, symbol: symbol.to_string(), received_ns: now_ns(), msg }
+    }
+}
+
+pub fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+// Millisecond times of the venues in nanoseconds since epoch
+pub fn ms_to_ns(ms: u64) -> u64 {
+    ms.saturating_mul(1_000_000)
+}
+
+// RFC 3339 time of the venues, e.g. "2023-09-25T07:49:37.708706Z", in nanoseconds since epoch
+pub fn parse_rfc3339_ns(text: &str) -> Option<u64> {
+    let time = OffsetDateTime::parse(text, &Rfc3339).ok()?;
+    u64::try_from(time.unix_timestamp_nanos()).ok()
+}
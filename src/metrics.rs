@@ -0,0 +1,249 @@
+// Microstructure metrics computed from the book after each applied update and published
+// with the correlation id of the message that caused it.
+
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::models::book::Book;
+use crate::models::liquidity::{self, bps_from_mid};
+use crate::models::order_book::{OrderBookUpdate, QuoteType};
+use crate::models::types::BuySell;
+
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    // Levels per side used for the imbalance and the size weighted mid
+    pub top_n: usize,
+    // Distances from mid, in bps, at which depth is measured
+    pub depth_bps: Vec<Decimal>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            top_n: 5,
+            depth_bps: vec![Decimal::from(10), Decimal::from(50), Decimal::from(100)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthAtBps {
+    pub bps: Decimal,
+    pub bid_qty: Decimal,
+    pub ask_qty: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookMetrics {
+    pub best_bid: (Decimal, Decimal),
+    pub best_ask: (Decimal, Decimal),
+    pub spread: Decimal,
+    pub spread_bps: Decimal,
+    pub mid: Decimal,
+    // Mid weighted by the opposite side's top of book size
+    pub microprice: Decimal,
+    // Average of the bid and ask VWAPs over the top N levels
+    pub weighted_mid: Decimal,
+    // (bid qty - ask qty) / (bid qty + ask qty) over the top N levels, in [-1, 1]
+    pub imbalance: Decimal,
+    pub depth: Vec<DepthAtBps>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsMessage {
+    pub correlation_id: Uuid,
+    pub metrics: BookMetrics,
+}
+
+fn vwap_and_qty(levels: &[(Decimal, Decimal)]) -> (Decimal, Decimal) {
+    let qty: Decimal = levels.iter().map(|(_, qty)| *qty).sum();
+    let notional: Decimal = levels.iter().map(|(price, qty)| price * qty).sum();
+    if qty.is_zero() {
+        (Decimal::ZERO, Decimal::ZERO)
+    } else {
+        (notional / qty, qty)
+    }
+}
+
+pub fn compute<B: Book + ?Sized>(book: &B, config: &MetricsConfig) -> Option<BookMetrics> {
+    let (best_bid, best_ask) = (book.best_bid()?, book.best_ask()?);
+    let mid = (best_bid.0 + best_ask.0) / Decimal::TWO;
+    if mid.is_zero() {
+        return None;
+    }
+    let spread = best_ask.0 - best_bid.0;
+    let microprice = (best_bid.0 * best_ask.1 + best_ask.0 * best_bid.1) / (best_bid.1 + best_ask.1);
+
+    let (bid_vwap, bid_qty) = vwap_and_qty(&book.top_levels(QuoteType::BID, config.top_n));
+    let (ask_vwap, ask_qty) = vwap_and_qty(&book.top_levels(QuoteType::ASK, config.top_n));
+
+    let depth = config.depth_bps.iter().map(|&bps| DepthAtBps {
+        bps,
+        bid_qty: liquidity::capacity_within_bps(book, BuySell::Sell, bps).qty,
+        ask_qty: liquidity::capacity_within_bps(book, BuySell::Buy, bps).qty,
+    }).collect();
+
+    Some(BookMetrics {
+        best_bid,
+        best_ask,
+        spread,
+        spread_bps: spread / mid * Decimal::from(10_000),
+        mid,
+        microprice,
+        weighted_mid: (bid_vwap + ask_vwap) / Decimal::TWO,
+        imbalance: (bid_qty - ask_qty) / (bid_qty + ask_qty),
+        depth,
+    })
+}
+
+/// Keeps the last metrics and recomputes them only when an update reaches the levels they
+/// are computed from.
+#[derive(Debug)]
+pub struct MetricsCalculator {
+    config: MetricsConfig,
+    last: Option<BookMetrics>,
+    // Worst bid and ask prices the last metrics depend on
+    bid_floor: Decimal,
+    ask_ceiling: Decimal,
+}
+
+impl MetricsCalculator {
+    pub fn new(config: MetricsConfig) -> Self {
+        MetricsCalculator { config, last: None, bid_floor: Decimal::MAX, ask_ceiling: Decimal::MIN }
+    }
+
+    pub fn last(&self) -> Option<&BookMetrics> {
+        self.last.as_ref()
+    }
+
+    fn is_relevant(&self, update: &OrderBookUpdate) -> bool {
+        update.is_snapshot() || update.price_levels().iter().any(|level| match level.quote_type() {
+            QuoteType::BID => level.price() >= self.bid_floor,
+            QuoteType::ASK => level.price() <= self.ask_ceiling,
+        })
+    }
+
+    fn window<B: Book + ?Sized>(&mut self, book: &B, metrics: &BookMetrics) {
+        let max_bps = self.config.depth_bps.iter().cloned().max().unwrap_or_default();
+        // A side with fewer than top_n levels, or whose levels all lie within the depth band,
+        // is changed by a level at any price. Otherwise only up to the n-th level or the first
+        // level outside the band, whichever is further from mid.
+        let nth = |side| book.levels(side).nth(self.config.top_n.max(1) - 1).map(|(price, _)| price);
+        let beyond_bids = book.bids().find(|(price, _)| bps_from_mid(BuySell::Sell, *price, metrics.mid) > max_bps);
+        let beyond_asks = book.asks().find(|(price, _)| bps_from_mid(BuySell::Buy, *price, metrics.mid) > max_bps);
+
+        self.bid_floor = match (nth(QuoteType::BID), beyond_bids) {
+            (Some(nth), Some((beyond, _))) => nth.min(beyond),
+            _ => Decimal::MIN,
+        };
+        self.ask_ceiling = match (nth(QuoteType::ASK), beyond_asks) {
+            (Some(nth), Some((beyond, _))) => nth.max(beyond),
+            _ => Decimal::MAX,
+        };
+    }
+
+    // To be called with the book once `update` has been applied to it. Returns the metrics if
+    // they changed.
+    pub fn on_update<B: Book + ?Sized>(&mut self, book: &B, update: &OrderBookUpdate) -> Option<&BookMetrics> {
+        if self.last.is_some() && !self.is_relevant(update) {
+            return None;
+        }
+        let metrics = compute(book, &self.config);
+        match metrics {
+            Some(metrics) => {
+                self.window(book, &metrics);
+                if self.last.as_ref() == Some(&metrics) {
+                    return None;
+                }
+                self.last = Some(metrics);
+                self.last.as_ref()
+            },
+            None => {
+                self.last = None;
+                self.bid_floor = Decimal::MAX;
+                self.ask_ceiling = Decimal::MIN;
+                None
+            }
+        }
+    }
+}
+
+pub async fn publish(sender: &mpsc::Sender<MetricsMessage>, correlation_id: Uuid, metrics: &BookMetrics) -> bool {
+    sender.send(MetricsMessage { correlation_id, metrics: metrics.clone() }).await.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::book::{new_book, BookKind};
+    use crate::models::order_book::PriceLevel;
+
+    fn snapshot() -> OrderBookUpdate {
+        OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(dec!(99), dec!(3), QuoteType::BID),
+            PriceLevel::new(dec!(98), dec!(1), QuoteType::BID),
+            PriceLevel::new(dec!(90), dec!(50), QuoteType::BID),
+            PriceLevel::new(dec!(101), dec!(1), QuoteType::ASK),
+            PriceLevel::new(dec!(102), dec!(1), QuoteType::ASK),
+        ])
+    }
+
+    #[test]
+    fn test_compute() {
+        let mut book = new_book(BookKind::BTree, None);
+        book.apply(&snapshot());
+        let config = MetricsConfig { top_n: 2, depth_bps: vec![dec!(150)] };
+        let metrics = compute(book.as_ref(), &config).unwrap();
+
+        assert_eq!(metrics.mid, dec!(100));
+        assert_eq!(metrics.spread, dec!(2));
+        assert_eq!(metrics.spread_bps, dec!(200));
+        // (99 * 1 + 101 * 3) / 4
+        assert_eq!(metrics.microprice, dec!(100.5));
+        // bid vwap 98.75, ask vwap 101.5
+        assert_eq!(metrics.weighted_mid, dec!(100.125));
+        assert_eq!(metrics.imbalance, dec!(2) / dec!(6));
+        assert_eq!(metrics.depth, vec![DepthAtBps { bps: dec!(150), bid_qty: dec!(3), ask_qty: dec!(1) }]);
+    }
+
+    #[test]
+    fn test_skips_updates_outside_window() {
+        let mut book = new_book(BookKind::BTree, None);
+        let mut calculator = MetricsCalculator::new(MetricsConfig { top_n: 2, depth_bps: vec![dec!(150)] });
+
+        let update = snapshot();
+        book.apply(&update);
+        assert!(calculator.on_update(book.as_ref(), &update).is_some());
+
+        let far = OrderBookUpdate::new(vec![PriceLevel::new(dec!(90), dec!(10), QuoteType::BID)]);
+        book.apply(&far);
+        assert!(calculator.on_update(book.as_ref(), &far).is_none());
+
+        let near = OrderBookUpdate::new(vec![PriceLevel::new(dec!(101), dec!(3), QuoteType::ASK)]);
+        book.apply(&near);
+        let metrics = calculator.on_update(book.as_ref(), &near).unwrap();
+        assert_eq!(metrics.microprice, dec!(100));
+    }
+
+    #[test]
+    fn test_thin_side_window() {
+        let mut book = new_book(BookKind::BTree, None);
+        // Two asks for a top 3
+        let mut calculator = MetricsCalculator::new(MetricsConfig { top_n: 3, depth_bps: vec![dec!(150)] });
+        let update = snapshot();
+        book.apply(&update);
+        let before = calculator.on_update(book.as_ref(), &update).unwrap().weighted_mid;
+
+        let third = OrderBookUpdate::new(vec![PriceLevel::new(dec!(110), dec!(1), QuoteType::ASK)]);
+        book.apply(&third);
+        let metrics = calculator.on_update(book.as_ref(), &third).unwrap();
+        assert_ne!(metrics.weighted_mid, before);
+
+        // The top 3 is full now
+        let fourth = OrderBookUpdate::new(vec![PriceLevel::new(dec!(120), dec!(1), QuoteType::ASK)]);
+        book.apply(&fourth);
+        assert!(calculator.on_update(book.as_ref(), &fourth).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+// Scripted exchange websocket server on localhost, for tests that must not reach the venues.
+//
+// Connectors are pointed at it through ConnectorConfig::with_base_url. Each accepted connection
+// plays the next Session: it reads the expected subscribe messages, then runs the steps in order.
+
+use std::io;
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tungstenite::Message;
+
+use crate::quote::Exchange;
+use crate::recorder::RecordedFrame;
+use crate::replay::read_frames;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    // Text frame sent as is, malformed payloads included
+    Send(String),
+    Pause(Duration),
+    // Close handshake
+    Close,
+    // TCP connection dropped without a close frame
+    Drop,
+    // Connection kept open until the client leaves
+    Hold,
+}
+
+/// Script of one connection. Without a final Close, Drop or Hold the connection is closed.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    subscriptions: usize,
+    steps: Vec<Step>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    // Subscribe messages read before the first step, Kraken sends one per subscription
+    pub fn with_subscriptions(mut self, subscriptions: usize) -> Self {
+        self.subscriptions = subscriptions;
+        self
+    }
+
+    pub fn send(mut self, frame: &str) -> Self {
+        self.steps.push(Step::Send(frame.to_string()));
+        self
+    }
+
+    pub fn send_all<I, S>(mut self, frames: I) -> Self
+        where
+            I: IntoIterator<Item = S>,
+            S: Into<String>,
+    {
+        self.steps.extend(frames.into_iter().map(|frame| Step::Send(frame.into())));
+        self
+    }
+
+    // Frames of a recording, without their original pacing
+    pub fn send_recorded(self, frames: &[RecordedFrame]) -> Self {
+        self.send_all(frames.iter().map(|frame| frame.frame.clone()))
+    }
+
+    pub fn pause(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Pause(duration));
+        self
+    }
+
+    pub fn close(mut self) -> Self {
+        self.steps.push(Step::Close);
+        self
+    }
+
+    pub fn drop_connection(mut self) -> Self {
+        self.steps.push(Step::Drop);
+        self
+    }
+
+    pub fn hold(mut self) -> Self {
+        self.steps.push(Step::Hold);
+        self
+    }
+}
+
+// Frames of a recording (see recorder) for one venue and symbol
+pub async fn recorded_frames(path: &str, exchange: Exchange, symbol: &str) -> io::Result<Vec<RecordedFrame>> {
+    let frames = read_frames(path).await?;
+    Ok(frames.into_iter().filter(|frame| frame.exchange == exchange && frame.symbol == symbol).collect())
+}
+
+/// What a client did on a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accepted {
+    // Request path and query, e.g. "/ws/btcusdt@depth5@100ms"
+    pub path: String,
+    pub subscriptions: Vec<String>,
+}
+
+/// Serves the sessions to successive connections, then stops listening.
+pub struct MockExchange {
+    url: String,
+    accepted: mpsc::Receiver<Accepted>,
+    task: JoinHandle<()>,
+}
+
+impl MockExchange {
+    pub async fn start(sessions: Vec<Session>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("ws://{}", listener.local_addr()?);
+        let (accepted_tx, accepted) = mpsc::channel(sessions.len().max(1));
+        let task = tokio::spawn(async move {
+            for session in sessions {
+                let socket = match listener.accept().await {
+                    Ok((socket, _)) => socket,
+                    Err(_) => return,
+                };
+                play(socket, session, &accepted_tx).await;
+            }
+        });
+        Ok(MockExchange { url, accepted, task })
+    }
+
+    // Base URL for the connectors
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Next connection once it subscribed, None once every session was played
+    pub async fn accepted(&mut self) -> Option<Accepted> {
+        self.accepted.recv().await
+    }
+}
+
+impl Drop for MockExchange {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// A connection failing its handshake or leaving before subscribing consumes its session
+async fn play(socket: TcpStream, session: Session, accepted_tx: &mpsc::Sender<Accepted>) {
+    let mut path = String::new();
+    let callback = |request: &Request, response: Response| {
+        path = request.uri().to_string();
+        Ok(response)
+    };
+    let mut ws_stream = match accept_hdr_async(socket, callback).await {
+        Ok(ws_stream) => ws_stream,
+        Err(_) => return,
+    };
+    let mut accepted = Accepted { path, subscriptions: Vec::new() };
+
+    while accepted.subscriptions.len() < session.subscriptions {
+        match ws_stream.next().await {
+            Some(Ok(Message::Text(subscription))) => accepted.subscriptions.push(subscription),
+            Some(Ok(_)) => continue,
+            _ => return,
+        }
+    }
+    let _ = accepted_tx.send(accepted).await;
+
+    for step in session.steps {
+        match step {
+            Step::Send(frame) => {
+                // The client left, nothing more to play
+                if ws_stream.send(Message::Text(frame)).await.is_err() {
+                    return;
+                }
+            },
+            Step::Pause(duration) => tokio::time::sleep(duration).await,
+            Step::Close => break,
+            Step::Drop => return,
+            Step::Hold => {
+                while let Some(Ok(_)) = ws_stream.next().await {}
+                return;
+            },
+        }
+    }
+    let _ = ws_stream.close(None).await;
+}
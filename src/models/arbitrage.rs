@@ -0,0 +1,339 @@
+// Cross-venue arbitrage: buying on one venue's asks and selling on another venue's bids, with
+// the taker fees of both venues paid.
+//
+// The size is found by walking both ladders at once, level by level, for as long as the next
+// unit bought still sells at a profit after fees. The detector follows each opportunity from
+// the update opening it to the one closing it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::Duration;
+use rust_decimal::Decimal;
+
+use crate::models::book::Book;
+use crate::models::book_registry::BookKey;
+use crate::models::liquidity::BPS;
+use crate::models::order_book::{OrderBook, QuoteType};
+use crate::quote::Exchange;
+
+/// Taker fee of each venue in bps, venues without a fee are charged the default one.
+#[derive(Debug, Clone, Default)]
+pub struct FeeSchedule {
+    taker_bps: HashMap<Exchange, Decimal>,
+    default_bps: Decimal,
+}
+
+impl FeeSchedule {
+    pub fn new() -> Self {
+        FeeSchedule::default()
+    }
+
+    pub fn with_taker_bps(mut self, exchange: Exchange, bps: Decimal) -> Self {
+        self.taker_bps.insert(exchange, bps);
+        self
+    }
+
+    pub fn with_default_bps(mut self, bps: Decimal) -> Self {
+        self.default_bps = bps;
+        self
+    }
+
+    pub fn taker_bps(&self, exchange: Exchange) -> Decimal {
+        self.taker_bps.get(&exchange).copied().unwrap_or(self.default_bps)
+    }
+
+    // Fee as a fraction of the notional
+    pub fn taker_rate(&self, exchange: Exchange) -> Decimal {
+        self.taker_bps(exchange) / BPS
+    }
+}
+
+/// Buying `qty` on `buy` and selling it on `sell`, both as taker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opportunity {
+    pub buy: BookKey,
+    pub sell: BookKey,
+    pub qty: Decimal,
+    pub buy_vwap: Decimal,
+    pub sell_vwap: Decimal,
+    // Last levels taken
+    pub buy_worst: Decimal,
+    pub sell_worst: Decimal,
+    // Quote currency spent on the buy leg, fees included
+    pub cost: Decimal,
+    // Quote currency received on the sell leg, fees deducted
+    pub proceeds: Decimal,
+    pub fees: Decimal,
+}
+
+impl Opportunity {
+    pub fn pnl(&self) -> Decimal {
+        self.proceeds - self.cost
+    }
+
+    // PnL against the cost of the buy leg
+    pub fn pnl_bps(&self) -> Decimal {
+        self.pnl() / self.cost * BPS
+    }
+}
+
+impl fmt::Display for Opportunity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "buy {} on {} at {} (up to {}), sell on {} at {} (down to {}), pnl {} ({} bps, fees {})",
+               self.qty, self.buy, self.buy_vwap.round_dp(8), self.buy_worst, self.sell, self.sell_vwap.round_dp(8),
+               self.sell_worst, self.pnl().round_dp(8), self.pnl_bps().round_dp(2), self.fees.round_dp(8))
+    }
+}
+
+// Takes the asks of `buy_book` and the bids of `sell_book` while the bid, net of the sell fee,
+// is above the ask plus the buy fee. None when the first levels already aren't profitable.
+pub fn find<A: Book + ?Sized, B: Book + ?Sized>(buy: &BookKey, buy_book: &A, sell: &BookKey, sell_book: &B,
+                                                fees: &FeeSchedule, max_qty: Option<Decimal>) -> Option<Opportunity> {
+    let buy_rate = fees.taker_rate(buy.exchange);
+    let sell_rate = fees.taker_rate(sell.exchange);
+    let mut asks = buy_book.levels(QuoteType::ASK);
+    let mut bids = sell_book.levels(QuoteType::BID);
+    let (mut ask, mut ask_left) = asks.next()?;
+    let (mut bid, mut bid_left) = bids.next()?;
+
+    let mut qty = Decimal::ZERO;
+    let mut bought = Decimal::ZERO;
+    let mut sold = Decimal::ZERO;
+    let (mut buy_worst, mut sell_worst) = (ask, bid);
+    loop {
+        if bid * (Decimal::ONE - sell_rate) <= ask * (Decimal::ONE + buy_rate) {
+            break;
+        }
+        let mut take = ask_left.min(bid_left);
+        if let Some(max_qty) = max_qty {
+            take = take.min(max_qty - qty);
+        }
+        if take > Decimal::ZERO {
+            qty += take;
+            bought += take * ask;
+            sold += take * bid;
+            buy_worst = ask;
+            sell_worst = bid;
+        }
+        if max_qty.is_some_and(|max_qty| qty >= max_qty) {
+            break;
+        }
+        ask_left -= take;
+        bid_left -= take;
+        if ask_left <= Decimal::ZERO {
+            match asks.next() {
+                Some(level) => (ask, ask_left) = level,
+                None => break,
+            }
+        }
+        if bid_left <= Decimal::ZERO {
+            match bids.next() {
+                Some(level) => (bid, bid_left) = level,
+                None => break,
+            }
+        }
+    }
+
+    if qty.is_zero() {
+        return None;
+    }
+    let buy_fee = bought * buy_rate;
+    let sell_fee = sold * sell_rate;
+    Some(Opportunity {
+        buy: buy.clone(),
+        sell: sell.clone(),
+        qty,
+        buy_vwap: bought / qty,
+        sell_vwap: sold / qty,
+        buy_worst,
+        sell_worst,
+        cost: bought + buy_fee,
+        proceeds: sold - sell_fee,
+        fees: buy_fee + sell_fee,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArbitrageEvent {
+    Opened { opportunity: Opportunity, at_ns: u64 },
+    // The size or the prices changed while the opportunity stayed open
+    Updated { opportunity: Opportunity, duration: Duration },
+    Closed { buy: BookKey, sell: BookKey, duration: Duration, peak_pnl: Decimal },
+}
+
+impl fmt::Display for ArbitrageEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArbitrageEvent::Opened { opportunity, .. } => write!(f, "Arbitrage opened: {}", opportunity),
+            ArbitrageEvent::Updated { opportunity, duration } =>
+                write!(f, "Arbitrage open for {:?}: {}", duration, opportunity),
+            ArbitrageEvent::Closed { buy, sell, duration, peak_pnl } =>
+                write!(f, "Arbitrage closed: buy on {}, sell on {} after {:?}, peak pnl {}", buy, sell, duration, peak_pnl.round_dp(8)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Episode {
+    since_ns: u64,
+    last: Opportunity,
+    peak_pnl: Decimal,
+}
+
+#[derive(Debug, Default)]
+pub struct ArbitrageDetector {
+    fees: FeeSchedule,
+    // Opportunities making less are ignored
+    min_pnl: Decimal,
+    max_qty: Option<Decimal>,
+    // Keyed by (buy, sell)
+    episodes: BTreeMap<(BookKey, BookKey), Episode>,
+}
+
+impl ArbitrageDetector {
+    pub fn new(fees: FeeSchedule) -> Self {
+        ArbitrageDetector { fees, ..ArbitrageDetector::default() }
+    }
+
+    pub fn with_min_pnl(mut self, min_pnl: Decimal) -> Self {
+        self.min_pnl = min_pnl;
+        self
+    }
+
+    // Caps the size of each opportunity, e.g. to the inventory available on the venues
+    pub fn with_max_qty(mut self, max_qty: Decimal) -> Self {
+        self.max_qty = Some(max_qty);
+        self
+    }
+
+    // Checks every pair of books of the same instrument, at each update of one of them. A book
+    // that is not synced closes the opportunities it is part of. Opportunities between books
+    // that are not given are left as they are.
+    pub fn check<'a>(&mut self, books: &[(&'a BookKey, &'a OrderBook)], now_ns: u64) -> Vec<ArbitrageEvent> {
+        let mut found = Vec::new();
+        for (buy, buy_book) in books.iter().filter(|(_, book)| book.is_synced()) {
+            for (sell, sell_book) in books.iter().filter(|(_, book)| book.is_synced()) {
+                if buy == sell {
+                    continue;
+                }
+                let opportunity = find(buy, *buy_book, sell, *sell_book, &self.fees, self.max_qty)
+                    .filter(|opportunity| opportunity.pnl() >= self.min_pnl);
+                found.extend(opportunity);
+            }
+        }
+
+        let mut events = Vec::new();
+        let given = |key: &BookKey| books.iter().any(|(book_key, _)| *book_key == key);
+        let closed: Vec<(BookKey, BookKey)> = self.episodes.keys()
+            .filter(|(buy, sell)| given(buy) || given(sell))
+            .filter(|(buy, sell)| !found.iter().any(|opportunity| opportunity.buy == *buy && opportunity.sell == *sell))
+            .cloned()
+            .collect();
+        for (buy, sell) in closed {
+            let episode = self.episodes.remove(&(buy.clone(), sell.clone())).expect("Episode of a closed pair");
+            let duration = Duration::from_nanos(now_ns.saturating_sub(episode.since_ns));
+            events.push(ArbitrageEvent::Closed { buy, sell, duration, peak_pnl: episode.peak_pnl });
+        }
+
+        for opportunity in found {
+            let pair = (opportunity.buy.clone(), opportunity.sell.clone());
+            match self.episodes.get_mut(&pair) {
+                Some(episode) if episode.last == opportunity => {},
+                Some(episode) => {
+                    episode.peak_pnl = episode.peak_pnl.max(opportunity.pnl());
+                    episode.last = opportunity.clone();
+                    let duration = Duration::from_nanos(now_ns.saturating_sub(episode.since_ns));
+                    events.push(ArbitrageEvent::Updated { opportunity, duration });
+                },
+                None => {
+                    let episode = Episode { since_ns: now_ns, last: opportunity.clone(), peak_pnl: opportunity.pnl() };
+                    self.episodes.insert(pair, episode);
+                    events.push(ArbitrageEvent::Opened { opportunity, at_ns: now_ns });
+                },
+            }
+        }
+        events
+    }
+
+    // Opportunities open, with the time they were first seen
+    pub fn open(&self) -> impl Iterator<Item = (&Opportunity, u64)> {
+        self.episodes.values().map(|episode| (&episode.last, episode.since_ns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn keys() -> (BookKey, BookKey) {
+        (BookKey::new(Exchange::Kraken, "XBT/USD"), BookKey::new(Exchange::Binance, "BTCUSDT"))
+    }
+
+    #[test]
+    fn test_walk_both_ladders() {
+        let (kraken, binance) = keys();
+        let fees = FeeSchedule::new().with_taker_bps(Exchange::Kraken, dec!(10)).with_taker_bps(Exchange::Binance, dec!(10));
+        let cheap = OrderBook::from_levels(&[(dec!(99), dec!(5))], &[(dec!(100), dec!(1)), (dec!(100.5), dec!(2)), (dec!(101), dec!(5))]);
+        let rich = OrderBook::from_levels(&[(dec!(101), dec!(1.5)), (dec!(100.9), dec!(1)), (dec!(100.6), dec!(5))], &[(dec!(102), dec!(5))]);
+
+        // 100.5 * 1.001 = 100.6005 is still below 100.9 * 0.999 = 100.7991, but not below 100.6 * 0.999
+        let opportunity = find(&kraken, &cheap, &binance, &rich, &fees, None).unwrap();
+        assert_eq!(opportunity.qty, dec!(2.5));
+        assert_eq!((opportunity.buy_worst, opportunity.sell_worst), (dec!(100.5), dec!(100.9)));
+        assert_eq!(opportunity.buy_vwap, dec!(100.3));
+        assert_eq!(opportunity.sell_vwap, dec!(100.96));
+        assert_eq!(opportunity.cost, dec!(251.00075));
+        assert_eq!(opportunity.proceeds, dec!(252.1476));
+        assert_eq!(opportunity.pnl(), dec!(1.14685));
+        assert_eq!(opportunity.fees, dec!(0.50315));
+
+        let capped = find(&kraken, &cheap, &binance, &rich, &fees, Some(dec!(0.5))).unwrap();
+        assert_eq!(capped.qty, dec!(0.5));
+        assert!(find(&binance, &rich, &kraken, &cheap, &fees, None).is_none());
+    }
+
+    #[test]
+    fn test_fees_eat_the_spread() {
+        let (kraken, binance) = keys();
+        let cheap = OrderBook::from_levels(&[], &[(dec!(100), dec!(1))]);
+        let rich = OrderBook::from_levels(&[(dec!(100.3), dec!(1))], &[]);
+        assert!(find(&kraken, &cheap, &binance, &rich, &FeeSchedule::new(), None).is_some());
+
+        let fees = FeeSchedule::new().with_default_bps(dec!(10)).with_taker_bps(Exchange::Kraken, dec!(20));
+        assert_eq!(fees.taker_bps(Exchange::Okx), dec!(10));
+        assert!(find(&kraken, &cheap, &binance, &rich, &fees, None).is_none());
+    }
+
+    #[test]
+    fn test_opportunity_lifecycle() {
+        let (kraken, binance) = keys();
+        let mut detector = ArbitrageDetector::new(FeeSchedule::new());
+        let cheap = OrderBook::from_levels(&[(dec!(99), dec!(1))], &[(dec!(100), dec!(1))]);
+        let rich = OrderBook::from_levels(&[(dec!(101), dec!(1))], &[(dec!(102), dec!(1))]);
+
+        let events = detector.check(&[(&kraken, &cheap), (&binance, &rich)], 1_000);
+        assert!(matches!(events.as_slice(), [ArbitrageEvent::Opened { opportunity, at_ns: 1_000 }] if opportunity.pnl() == dec!(1)));
+        // Nothing changed
+        assert!(detector.check(&[(&kraken, &cheap), (&binance, &rich)], 2_000).is_empty());
+
+        let richer = OrderBook::from_levels(&[(dec!(101), dec!(2))], &[(dec!(102), dec!(1))]);
+        let cheap_deeper = OrderBook::from_levels(&[(dec!(99), dec!(1))], &[(dec!(100), dec!(2))]);
+        let events = detector.check(&[(&kraken, &cheap_deeper), (&binance, &richer)], 3_000);
+        assert!(matches!(events.as_slice(), [ArbitrageEvent::Updated { opportunity, duration }]
+            if opportunity.qty == dec!(2) && *duration == Duration::from_nanos(2_000)));
+        assert_eq!(detector.open().count(), 1);
+
+        // Books of another instrument leave it open
+        let eth = BookKey::new(Exchange::Kraken, "ETH/USD");
+        assert!(detector.check(&[(&eth, &cheap)], 4_000).is_empty());
+
+        let mut stale = OrderBook::from_levels(&[(dec!(101), dec!(2))], &[(dec!(102), dec!(1))]);
+        stale.mark_stale();
+        let events = detector.check(&[(&kraken, &cheap_deeper), (&binance, &stale)], 5_000);
+        assert_eq!(events, vec![ArbitrageEvent::Closed {
+            buy: kraken, sell: binance, duration: Duration::from_nanos(4_000), peak_pnl: dec!(2),
+        }]);
+        assert_eq!(detector.open().count(), 0);
+    }
+}
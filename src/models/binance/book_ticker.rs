@@ -0,0 +1,66 @@
+// Binance `<symbol>@bookTicker` stream: best bid and ask in real time
+//
+// https://binance-docs.github.io/apidocs/spot/en/#individual-symbol-book-ticker-streams
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::models::binance::depth::CombinedStreamEvent;
+use crate::models::top_of_book::TopOfBook;
+use crate::quote::Exchange;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookTicker {
+    #[serde(rename = "u")]
+    pub update_id: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "b")]
+    pub bid_price: Decimal,
+    #[serde(rename = "B")]
+    pub bid_qty: Decimal,
+    #[serde(rename = "a")]
+    pub ask_price: Decimal,
+    #[serde(rename = "A")]
+    pub ask_qty: Decimal,
+}
+
+// Parses a book ticker from a raw or a combined stream, anything else is None
+pub fn parse_book_ticker(text: &str) -> Option<BookTicker> {
+    serde_json::from_str::<BookTicker>(text).ok()
+        .or_else(|| serde_json::from_str::<CombinedStreamEvent<BookTicker>>(text).ok().map(|event| event.data))
+}
+
+impl BookTicker {
+    pub fn to_top_of_book(&self) -> TopOfBook {
+        TopOfBook {
+            exchange: Exchange::Binance,
+            symbol: self.symbol.clone(),
+            bid_price: self.bid_price,
+            bid_qty: self.bid_qty,
+            ask_price: self.ask_price,
+            ask_qty: self.ask_qty,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const BOOK_TICKER: &str = include_str!("../../../tests/fixtures/binance/book_ticker.json");
+
+    #[test]
+    fn test_parse_book_ticker() {
+        let ticker = parse_book_ticker(BOOK_TICKER).unwrap();
+        assert_eq!(ticker.update_id, 40090021700);
+        let top = ticker.to_top_of_book();
+        assert_eq!((top.exchange, top.symbol.as_str()), (Exchange::Binance, "BTCUSDT"));
+        assert_eq!((top.bid_price, top.bid_qty), (dec!(64000.1), dec!(0.3)));
+        assert_eq!((top.ask_price, top.ask_qty), (dec!(64000.5), dec!(0.6)));
+
+        let partial_depth = r#"{"lastUpdateId":160,"bids":[],"asks":[]}"#;
+        assert!(parse_book_ticker(partial_depth).is_none());
+    }
+}
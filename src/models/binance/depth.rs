@@ -0,0 +1,328 @@
+// Binance diff-depth stream synchronized with a REST snapshot, following
+// https://binance-docs.github.io/apidocs/spot/en/#how-to-manage-a-local-order-book-correctly
+use std::collections::VecDeque;
+use std::fmt;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use crate::models::order_book::{OrderBook, OrderBookUpdate, PriceLevel, QuoteType};
+
+// Upper bound on events kept while waiting for a snapshot, oldest are dropped first
+const MAX_BUFFERED_EVENTS: usize = 1024;
+
+/// `<symbol>@depth` event, `U` and `u` are the first and last update ids it covers.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthUpdate {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+    #[serde(rename = "u")]
+    pub final_update_id: u64,
+    #[serde(rename = "b")]
+    pub bids: Vec<(Decimal, Decimal)>,
+    #[serde(rename = "a")]
+    pub asks: Vec<(Decimal, Decimal)>,
+}
+
+/// Response of `GET /api/v3/depth`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthSnapshot {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: u64,
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+}
+
+/// Message of a combined stream, `stream` is e.g. "btcusdt@depth5@100ms".
+#[derive(Debug, Clone, Deserialize)]
+pub struct CombinedStreamEvent<T> {
+    pub stream: String,
+    pub data: T,
+}
+
+impl<T> CombinedStreamEvent<T> {
+    // Symbol the stream is for, as used in REST requests
+    pub fn symbol(&self) -> String {
+        self.stream.split('@').next().unwrap_or_default().to_uppercase()
+    }
+}
+
+// Parses a depth event from a raw or a combined stream, anything else is None
+pub fn parse_depth_update(text: &str) -> Option<DepthUpdate> {
+    serde_json::from_str::<DepthUpdate>(text).ok()
+        .or_else(|| serde_json::from_str::<CombinedStreamEvent<DepthUpdate>>(text).ok().map(|event| event.data))
+}
+
+pub fn parse_depth_snapshot(text: &str) -> serde_json::Result<DepthSnapshot> {
+    serde_json::from_str(text)
+}
+
+// A partial book depth message of a combined stream, with the symbol it is for
+pub fn parse_combined_partial_depth(text: &str) -> Option<(String, DepthSnapshot)> {
+    serde_json::from_str::<CombinedStreamEvent<DepthSnapshot>>(text).ok()
+        .map(|event| (event.symbol(), event.data))
+}
+
+fn price_levels(bids: &[(Decimal, Decimal)], asks: &[(Decimal, Decimal)]) -> Vec<PriceLevel> {
+    asks.iter().map(|&(price, qty)| PriceLevel::new(price, qty, QuoteType::ASK))
+        .chain(bids.iter().map(|&(price, qty)| PriceLevel::new(price, qty, QuoteType::BID)))
+        .collect()
+}
+
+impl DepthUpdate {
+    pub fn to_update(&self) -> OrderBookUpdate {
+        OrderBookUpdate::new(price_levels(&self.bids, &self.asks))
+            .with_sequence(self.first_update_id, self.final_update_id)
+    }
+}
+
+impl DepthSnapshot {
+    pub fn to_update(&self) -> OrderBookUpdate {
+        OrderBookUpdate::snapshot(price_levels(&self.bids, &self.asks))
+            .with_sequence(self.last_update_id, self.last_update_id)
+    }
+}
+
+/// An event does not follow the last applied update id, the book has to be re-snapshotted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceGap {
+    pub expected: u64,
+    pub first_update_id: u64,
+    pub final_update_id: u64,
+}
+
+impl fmt::Display for SequenceGap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sequence gap: expected update {}, got {}..={}",
+               self.expected, self.first_update_id, self.final_update_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum DepthOutcome {
+    // Applied to the book, the normalized update can be forwarded
+    Applied(OrderBookUpdate),
+    // Kept until the next snapshot
+    Buffered,
+    // Already covered by the snapshot
+    Ignored,
+    // The sequence broke, the event was buffered and a new snapshot must be fetched
+    Gap(SequenceGap),
+}
+
+/// Keeps an `OrderBook` in sync with the diff-depth stream of one symbol.
+///
+/// Events are buffered until a snapshot is applied, after that each event must continue the
+/// sequence of the previous one.
+#[derive(Debug)]
+pub struct DepthSync {
+    book: OrderBook,
+    buffered: VecDeque<DepthUpdate>,
+    // Last update id reflected in the book, None until a snapshot is applied
+    last_update_id: Option<u64>,
+    // The first event after a snapshot may start before it
+    first_after_snapshot: bool,
+}
+
+impl DepthSync {
+    pub fn new() -> Self {
+        DepthSync {
+            book: OrderBook::new(),
+            buffered: VecDeque::new(),
+            last_update_id: None,
+            first_after_snapshot: false,
+        }
+    }
+
+    pub fn book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    pub fn last_update_id(&self) -> Option<u64> {
+        self.last_update_id
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.book.is_synced()
+    }
+
+    // Kept ordered by first update id, an event put back after a gap goes before the ones that
+    // were buffered behind it
+    fn buffer(&mut self, update: DepthUpdate) {
+        if self.buffered.len() == MAX_BUFFERED_EVENTS {
+            self.buffered.pop_front();
+        }
+        let idx = self.buffered.partition_point(|buffered| buffered.first_update_id <= update.first_update_id);
+        self.buffered.insert(idx, update);
+    }
+
+    // The id the next event must cover
+    fn check_sequence(&self, last_update_id: u64, update: &DepthUpdate) -> Result<(), SequenceGap> {
+        let expected = last_update_id + 1;
+        let in_sequence = if self.first_after_snapshot {
+            update.first_update_id <= expected && expected <= update.final_update_id
+        } else {
+            update.first_update_id == expected
+        };
+        if in_sequence {
+            Ok(())
+        } else {
+            Err(SequenceGap {
+                expected,
+                first_update_id: update.first_update_id,
+                final_update_id: update.final_update_id,
+            })
+        }
+    }
+
+    fn lose_sync(&mut self) {
+        self.book.mark_stale();
+        self.book.begin_resync();
+        self.last_update_id = None;
+    }
+
+    pub fn on_update(&mut self, update: DepthUpdate) -> DepthOutcome {
+        let last_update_id = match self.last_update_id {
+            Some(last_update_id) => last_update_id,
+            None => {
+                self.buffer(update);
+                return DepthOutcome::Buffered;
+            },
+        };
+        if update.final_update_id <= last_update_id {
+            return DepthOutcome::Ignored;
+        }
+        if let Err(gap) = self.check_sequence(last_update_id, &update) {
+            self.lose_sync();
+            self.buffer(update);
+            return DepthOutcome::Gap(gap);
+        }
+
+        let book_update = update.to_update();
+        self.book.update(&book_update);
+        self.last_update_id = Some(update.final_update_id);
+        self.first_after_snapshot = false;
+        DepthOutcome::Applied(book_update)
+    }
+
+    // Applies the snapshot then the buffered events it does not cover. Returns the updates
+    // applied, starting with the snapshot, or the gap if the buffered events do not continue it:
+    // the snapshot is then discarded and a newer one is needed.
+    pub fn on_snapshot(&mut self, snapshot: &DepthSnapshot) -> Result<Vec<OrderBookUpdate>, SequenceGap> {
+        let snapshot_update = snapshot.to_update();
+        self.book.update(&snapshot_update);
+        self.last_update_id = Some(snapshot.last_update_id);
+        self.first_after_snapshot = true;
+
+        let mut applied = vec![snapshot_update];
+        while let Some(update) = self.buffered.pop_front() {
+            match self.on_update(update) {
+                DepthOutcome::Applied(book_update) => applied.push(book_update),
+                DepthOutcome::Gap(gap) => return Err(gap),
+                DepthOutcome::Buffered | DepthOutcome::Ignored => {},
+            }
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const SNAPSHOT: &str = include_str!("../../../tests/fixtures/binance/depth_snapshot.json");
+    const UPDATES: &str = include_str!("../../../tests/fixtures/binance/depth_updates.jsonl");
+
+    fn updates() -> Vec<DepthUpdate> {
+        UPDATES.lines().map(|line| parse_depth_update(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_fixtures() {
+        let snapshot = parse_depth_snapshot(SNAPSHOT).unwrap();
+        assert_eq!(snapshot.last_update_id, 160);
+        assert_eq!(snapshot.bids[0], (dec!(64000.00), dec!(1.5)));
+
+        let combined = format!(r#"{{"stream":"btcusdt@depth","data":{}}}"#, UPDATES.lines().next().unwrap());
+        let update = parse_depth_update(&combined).unwrap();
+        assert_eq!((update.first_update_id, update.final_update_id), (150, 155));
+        assert!(parse_depth_update(r#"{"result":null,"id":1}"#).is_none());
+    }
+
+    #[test]
+    fn test_sync_from_buffered_events() {
+        let mut sync = DepthSync::new();
+        let mut updates = updates().into_iter();
+        for update in updates.by_ref().take(2) {
+            assert!(matches!(sync.on_update(update), DepthOutcome::Buffered));
+        }
+
+        // 150..=155 is covered by the snapshot, 156..=162 straddles it
+        let applied = sync.on_snapshot(&parse_depth_snapshot(SNAPSHOT).unwrap()).unwrap();
+        assert_eq!(applied.len(), 2);
+        assert!(applied[0].is_snapshot());
+        assert_eq!(sync.last_update_id(), Some(162));
+        assert!(sync.is_synced());
+
+        for update in updates {
+            assert!(matches!(sync.on_update(update), DepthOutcome::Applied(_)));
+        }
+        assert_eq!(sync.last_update_id(), Some(170));
+        assert_eq!(sync.book().best_bid(), Some((dec!(64000.50), dec!(0.3))));
+        assert_eq!(sync.book().best_ask(), Some((dec!(64001.00), dec!(0.5))));
+    }
+
+    #[test]
+    fn test_gap_requires_new_snapshot() {
+        let mut sync = DepthSync::new();
+        let updates = updates();
+        sync.on_snapshot(&parse_depth_snapshot(SNAPSHOT).unwrap()).unwrap();
+        sync.on_update(updates[1].clone());
+
+        // 163..=165 is missing
+        let gap = match sync.on_update(updates[3].clone()) {
+            DepthOutcome::Gap(gap) => gap,
+            outcome => panic!("expected a gap, got {:?}", outcome),
+        };
+        assert_eq!(gap, SequenceGap { expected: 163, first_update_id: 166, final_update_id: 170 });
+        assert!(!sync.is_synced());
+        assert!(matches!(sync.on_update(updates[2].clone()), DepthOutcome::Buffered));
+
+        // A snapshot older than the buffered events cannot be used
+        assert!(sync.on_snapshot(&parse_depth_snapshot(SNAPSHOT).unwrap()).is_err());
+        assert!(!sync.is_synced());
+
+        let newer = DepthSnapshot { last_update_id: 170, bids: vec![(dec!(64000), dec!(1))], asks: vec![(dec!(64001), dec!(1))] };
+        assert_eq!(sync.on_snapshot(&newer).unwrap().len(), 1);
+        assert!(sync.is_synced());
+    }
+
+    #[test]
+    fn test_unusable_snapshot_keeps_buffer_ordered() {
+        let mut sync = DepthSync::new();
+        let updates = updates();
+        // 163..=165 arrives after 166..=170
+        sync.on_update(updates[3].clone());
+        sync.on_update(updates[2].clone());
+
+        assert!(sync.on_snapshot(&parse_depth_snapshot(SNAPSHOT).unwrap()).is_err());
+        let ids: Vec<_> = sync.buffered.iter().map(|update| update.first_update_id).collect();
+        assert_eq!(ids, vec![163, 166]);
+
+        let bridged = DepthSnapshot { last_update_id: 162, bids: vec![(dec!(64000), dec!(1))], asks: vec![(dec!(64001), dec!(1))] };
+        assert_eq!(sync.on_snapshot(&bridged).unwrap().len(), 3);
+        assert_eq!(sync.last_update_id(), Some(170));
+    }
+
+    #[test]
+    fn test_first_event_must_cover_snapshot() {
+        let mut sync = DepthSync::new();
+        sync.on_snapshot(&parse_depth_snapshot(SNAPSHOT).unwrap()).unwrap();
+        let updates = updates();
+        assert!(matches!(sync.on_update(updates[0].clone()), DepthOutcome::Ignored));
+        assert!(matches!(sync.on_update(updates[2].clone()), DepthOutcome::Gap(_)));
+    }
+}
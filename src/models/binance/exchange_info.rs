@@ -0,0 +1,135 @@
+// Binance `GET /api/v3/exchangeInfo`: trading rules of the symbols, as filters
+//
+// https://binance-docs.github.io/apidocs/spot/en/#exchange-information
+// https://binance-docs.github.io/apidocs/spot/en/#filters
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::models::instrument::Instrument;
+use crate::models::instrument_registry::InstrumentSpec;
+use crate::quote::Exchange;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "filterType")]
+pub enum Filter {
+    #[serde(rename = "PRICE_FILTER")]
+    Price {
+        #[serde(rename = "minPrice")]
+        min_price: Decimal,
+        #[serde(rename = "maxPrice")]
+        max_price: Decimal,
+        #[serde(rename = "tickSize")]
+        tick_size: Decimal,
+    },
+    #[serde(rename = "LOT_SIZE")]
+    LotSize {
+        #[serde(rename = "minQty")]
+        min_qty: Decimal,
+        #[serde(rename = "maxQty")]
+        max_qty: Decimal,
+        #[serde(rename = "stepSize")]
+        step_size: Decimal,
+    },
+    #[serde(rename = "NOTIONAL")]
+    Notional {
+        #[serde(rename = "minNotional")]
+        min_notional: Decimal,
+    },
+    // Replaced by NOTIONAL, still published for some symbols
+    #[serde(rename = "MIN_NOTIONAL")]
+    MinNotional {
+        #[serde(rename = "minNotional")]
+        min_notional: Decimal,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInfo {
+    pub symbol: String,
+    // "TRADING", "HALT", "BREAK"
+    pub status: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub filters: Vec<Filter>,
+}
+
+impl SymbolInfo {
+    // None without a price or a lot size filter
+    pub fn to_spec(&self) -> Option<InstrumentSpec> {
+        let tick_size = self.filters.iter().find_map(|filter| match filter {
+            Filter::Price { tick_size, .. } => Some(*tick_size),
+            _ => None,
+        })?;
+        let (min_qty, step_size) = self.filters.iter().find_map(|filter| match filter {
+            Filter::LotSize { min_qty, step_size, .. } => Some((*min_qty, *step_size)),
+            _ => None,
+        })?;
+        let min_notional = self.filters.iter().find_map(|filter| match filter {
+            Filter::Notional { min_notional } | Filter::MinNotional { min_notional } => Some(*min_notional),
+            _ => None,
+        });
+        Some(InstrumentSpec::new(Exchange::Binance, &self.symbol, Instrument::new(&self.base_asset, &self.quote_asset),
+                                 tick_size.normalize(), step_size.normalize())
+            .with_min_qty(min_qty.normalize())
+            .with_min_notional(min_notional.unwrap_or_default().normalize())
+            .with_trading(self.status == "TRADING"))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExchangeInfo {
+    pub symbols: Vec<SymbolInfo>,
+}
+
+pub fn parse_exchange_info(text: &str) -> Option<ExchangeInfo> {
+    serde_json::from_str::<ExchangeInfo>(text).ok()
+}
+
+pub fn parse_instruments(text: &str) -> Vec<InstrumentSpec> {
+    parse_exchange_info(text)
+        .map(|info| info.symbols.iter().filter_map(SymbolInfo::to_spec).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const EXCHANGE_INFO: &str = include_str!("../../../tests/fixtures/binance/exchange_info.json");
+
+    #[test]
+    fn test_parse_exchange_info() {
+        let info = parse_exchange_info(EXCHANGE_INFO).unwrap();
+        assert!(info.symbols[0].filters.contains(&Filter::Other));
+
+        let specs = parse_instruments(EXCHANGE_INFO);
+        let btc = &specs[0];
+        assert_eq!((btc.symbol.as_str(), btc.instrument.to_string()), ("BTCUSDT", "BTC/USDT".to_string()));
+        assert_eq!((btc.tick_size, btc.lot_size), (dec!(0.01), dec!(0.00001)));
+        assert_eq!((btc.price_precision, btc.qty_precision), (2, 5));
+        assert_eq!((btc.min_qty, btc.min_notional), (dec!(0.00001), dec!(5)));
+        assert!(btc.trading);
+
+        // Older symbols only have MIN_NOTIONAL
+        assert_eq!(specs[1].min_notional, dec!(0.0001));
+        assert_eq!(specs[1].instrument.to_string(), "ETH/BTC");
+
+        // A zero size disables the filter, every price passes
+        let info = SymbolInfo {
+            filters: vec![
+                Filter::Price { min_price: dec!(0), max_price: dec!(0), tick_size: dec!(0.00000000) },
+                Filter::LotSize { min_qty: dec!(0), max_qty: dec!(0), step_size: dec!(0.00000000) },
+            ],
+            ..info.symbols[0].clone()
+        };
+        let spec = info.to_spec().unwrap();
+        assert_eq!((spec.tick_size, spec.lot_size), (dec!(0), dec!(0)));
+        assert_eq!(spec.check_price(dec!(64000.123)), Ok(dec!(64000.123)));
+        assert_eq!(spec.check_qty(dec!(0.1234567)), Ok(dec!(0.1234567)));
+    }
+}
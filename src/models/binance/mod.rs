@@ -0,0 +1,4 @@
+pub mod depth;
+pub mod trade;
+pub mod book_ticker;
+pub mod exchange_info;
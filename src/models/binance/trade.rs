@@ -0,0 +1,132 @@
+// Binance `<symbol>@trade` and `<symbol>@aggTrade` streams
+//
+// `m` tells whether the buyer is the maker, the aggressor is then the seller. An aggTrade
+// aggregates the fills of one taker order at one price, trade ids `f` to `l`.
+//
+// https://binance-docs.github.io/apidocs/spot/en/#trade-streams
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::models::binance::depth::CombinedStreamEvent;
+use crate::models::trade::{Aggressor, Trade, TradeId};
+use crate::quote::Exchange;
+
+const NS_PER_MS: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "e")]
+pub enum TradeEvent {
+    #[serde(rename = "trade")]
+    Trade {
+        #[serde(rename = "E")]
+        event_time: u64,
+        #[serde(rename = "s")]
+        symbol: String,
+        #[serde(rename = "t")]
+        trade_id: u64,
+        #[serde(rename = "p")]
+        price: Decimal,
+        #[serde(rename = "q")]
+        qty: Decimal,
+        #[serde(rename = "T")]
+        trade_time: u64,
+        #[serde(rename = "m")]
+        buyer_is_maker: bool,
+    },
+    #[serde(rename = "aggTrade")]
+    AggTrade {
+        #[serde(rename = "E")]
+        event_time: u64,
+        #[serde(rename = "s")]
+        symbol: String,
+        #[serde(rename = "a")]
+        agg_trade_id: u64,
+        #[serde(rename = "p")]
+        price: Decimal,
+        #[serde(rename = "q")]
+        qty: Decimal,
+        #[serde(rename = "f")]
+        first_trade_id: u64,
+        #[serde(rename = "l")]
+        last_trade_id: u64,
+        #[serde(rename = "T")]
+        trade_time: u64,
+        #[serde(rename = "m")]
+        buyer_is_maker: bool,
+    },
+}
+
+// Parses a trade event from a raw or a combined stream, anything else is None
+pub fn parse_trade_event(text: &str) -> Option<TradeEvent> {
+    serde_json::from_str::<TradeEvent>(text).ok()
+        .or_else(|| serde_json::from_str::<CombinedStreamEvent<TradeEvent>>(text).ok().map(|event| event.data))
+}
+
+fn aggressor(buyer_is_maker: bool) -> Aggressor {
+    if buyer_is_maker {
+        Aggressor::Sell
+    } else {
+        Aggressor::Buy
+    }
+}
+
+impl TradeEvent {
+    pub fn to_trade(&self, received_ns: u64) -> Trade {
+        let (symbol, trade_id, price, qty, trade_time, buyer_is_maker) = match self {
+            TradeEvent::Trade { symbol, trade_id, price, qty, trade_time, buyer_is_maker, .. } =>
+                (symbol, TradeId::Trade(*trade_id), *price, *qty, *trade_time, *buyer_is_maker),
+            TradeEvent::AggTrade { symbol, agg_trade_id, price, qty, trade_time, buyer_is_maker, .. } =>
+                (symbol, TradeId::Aggregate(*agg_trade_id), *price, *qty, *trade_time, *buyer_is_maker),
+        };
+        Trade {
+            exchange: Exchange::Binance,
+            symbol: symbol.clone(),
+            price,
+            qty,
+            aggressor: aggressor(buyer_is_maker),
+            trade_id,
+            exchange_ns: trade_time * NS_PER_MS,
+            received_ns,
+        }
+    }
+}
+
+pub fn parse_trade(text: &str, received_ns: u64) -> Option<Trade> {
+    parse_trade_event(text).map(|event| event.to_trade(received_ns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const TRADE: &str = include_str!("../../../tests/fixtures/binance/trade.json");
+    const AGG_TRADE: &str = include_str!("../../../tests/fixtures/binance/agg_trade.json");
+
+    #[test]
+    fn test_parse_trade() {
+        let trade = parse_trade(TRADE, 7).unwrap();
+        assert_eq!((trade.exchange, trade.symbol.as_str()), (Exchange::Binance, "BTCUSDT"));
+        assert_eq!((trade.price, trade.qty), (dec!(64000.5), dec!(0.012)));
+        assert_eq!(trade.aggressor, Aggressor::Sell);
+        assert_eq!(trade.trade_id, TradeId::Trade(3512000001));
+        assert_eq!((trade.exchange_ns, trade.received_ns), (1712000000209 * NS_PER_MS, 7));
+    }
+
+    #[test]
+    fn test_parse_agg_trade() {
+        match parse_trade_event(AGG_TRADE) {
+            Some(TradeEvent::AggTrade { first_trade_id, last_trade_id, .. }) =>
+                assert_eq!((first_trade_id, last_trade_id), (3512000002, 3512000004)),
+            event => panic!("expected an aggTrade, got {:?}", event),
+        }
+        let trade = parse_trade(AGG_TRADE, 0).unwrap();
+        assert_eq!(trade.aggressor, Aggressor::Buy);
+        assert_eq!(trade.trade_id, TradeId::Aggregate(2800000001));
+        assert_eq!(trade.qty, dec!(0.25));
+
+        let depth = r#"{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":1,"u":2,"b":[],"a":[]}"#;
+        assert!(parse_trade(depth, 0).is_none());
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+use std::str::FromStr;
+use rust_decimal::Decimal;
+
+use crate::models::order_book::{OrderBookUpdate, QuoteType, UpdateKind};
+use crate::models::liquidity::{self, Capacity, CostEstimate, TradeSize};
+use crate::models::diff::{self, BookDiff};
+use crate::models::types::BuySell;
+use crate::models::order_book;
+use crate::models::order_book_2;
+
+/// Operations shared by every price level book, whatever its internal representation.
+///
+/// Levels are `(price, qty)` pairs. Sides are always iterated from the best price:
+/// descending for bids, ascending for asks.
+pub trait Book {
+    // Replaces the whole content of the book
+    fn apply_snapshot(&mut self, snapshot: &OrderBookUpdate);
+
+    // Sets the quantity of each level, a zero quantity removes the level
+    fn apply_delta(&mut self, delta: &OrderBookUpdate);
+
+    fn best_bid(&self) -> Option<(Decimal, Decimal)>;
+
+    fn best_ask(&self) -> Option<(Decimal, Decimal)>;
+
+    fn levels(&self, side: QuoteType) -> Box<dyn Iterator<Item = (Decimal, Decimal)> + '_>;
+
+    fn clear(&mut self);
+
+    fn apply(&mut self, update: &OrderBookUpdate) {
+        match update.kind() {
+            UpdateKind::Snapshot => self.apply_snapshot(update),
+            UpdateKind::Delta => self.apply_delta(update),
+        }
+    }
+
+    fn top_levels(&self, side: QuoteType, n: usize) -> Vec<(Decimal, Decimal)> {
+        self.levels(side).take(n).collect()
+    }
+
+    fn bids(&self) -> Box<dyn Iterator<Item = (Decimal, Decimal)> + '_> {
+        self.levels(QuoteType::BID)
+    }
+
+    fn asks(&self) -> Box<dyn Iterator<Item = (Decimal, Decimal)> + '_> {
+        self.levels(QuoteType::ASK)
+    }
+
+    fn mid(&self) -> Option<Decimal> {
+        liquidity::mid(self)
+    }
+
+    // VWAP, worst price, slippage and unfilled remainder of trading `size` at once
+    fn cost_to_trade(&self, side: BuySell, size: TradeSize) -> CostEstimate {
+        liquidity::cost_to_trade(self, side, size)
+    }
+
+    // How much can be traded without touching levels further than `max_bps` from mid
+    fn capacity_within_bps(&self, side: BuySell, max_bps: Decimal) -> Capacity {
+        liquidity::capacity_within_bps(self, side, max_bps)
+    }
+
+    // Added, removed and changed levels turning this book into `target`
+    fn diff(&self, target: &dyn Book) -> BookDiff {
+        diff::diff(self, target)
+    }
+}
+
+/// Book implementation to instantiate, e.g. from a command line or config value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookKind {
+    // models::order_book::OrderBook, BTreeMap of Decimal
+    BTree,
+    // models::order_book_2::OrderBook, pooled levels in sorted vectors
+    Pooled,
+}
+
+impl fmt::Display for BookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookKind::BTree => write!(f, "btree"),
+            BookKind::Pooled => write!(f, "pooled"),
+        }
+    }
+}
+
+impl FromStr for BookKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "btree" => Ok(BookKind::BTree),
+            "pooled" => Ok(BookKind::Pooled),
+            other => Err(format!("unknown book kind: {}", other)),
+        }
+    }
+}
+
+pub fn new_book(kind: BookKind, max_depth: Option<usize>) -> Box<dyn Book + Send> {
+    match (kind, max_depth) {
+        (BookKind::BTree, Some(depth)) => Box::new(order_book::OrderBook::new().with_max_depth(depth)),
+        (BookKind::BTree, None) => Box::new(order_book::OrderBook::new()),
+        (BookKind::Pooled, Some(depth)) => Box::new(order_book_2::OrderBook::new().with_max_depth(depth)),
+        (BookKind::Pooled, None) => Box::new(order_book_2::OrderBook::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::order_book::PriceLevel;
+
+    fn check_book(mut book: Box<dyn Book + Send>) {
+        book.apply(&OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(dec!(99), dec!(2), QuoteType::BID),
+            PriceLevel::new(dec!(100), dec!(1), QuoteType::BID),
+            PriceLevel::new(dec!(98), dec!(3), QuoteType::BID),
+            PriceLevel::new(dec!(102), dec!(5), QuoteType::ASK),
+            PriceLevel::new(dec!(101), dec!(4), QuoteType::ASK),
+        ]));
+        assert_eq!(book.best_bid(), Some((dec!(100), dec!(1))));
+        assert_eq!(book.best_ask(), Some((dec!(101), dec!(4))));
+        assert_eq!(book.top_levels(QuoteType::BID, 2), vec![(dec!(100), dec!(1)), (dec!(99), dec!(2))]);
+
+        book.apply(&OrderBookUpdate::new(vec![
+            PriceLevel::new(dec!(100), Decimal::ZERO, QuoteType::BID),
+            PriceLevel::new(dec!(101), dec!(7), QuoteType::ASK),
+        ]));
+        assert_eq!(book.best_bid(), Some((dec!(99), dec!(2))));
+        assert_eq!(book.asks().collect::<Vec<_>>(), vec![(dec!(101), dec!(7)), (dec!(102), dec!(5))]);
+
+        // a new snapshot replaces everything
+        book.apply(&OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(dec!(50), dec!(1), QuoteType::BID),
+        ]));
+        assert_eq!(book.bids().collect::<Vec<_>>(), vec![(dec!(50), dec!(1))]);
+        assert_eq!(book.best_ask(), None);
+
+        book.clear();
+        assert_eq!(book.best_bid(), None);
+    }
+
+    // Deltas are applied as sent, without a snapshot first and even when they cross
+    fn check_deltas(mut book: Box<dyn Book + Send>) {
+        book.apply(&OrderBookUpdate::new(vec![
+            PriceLevel::new(dec!(100), dec!(1), QuoteType::BID),
+            PriceLevel::new(dec!(101), dec!(2), QuoteType::ASK),
+            PriceLevel::new(dec!(102), dec!(3), QuoteType::ASK),
+        ]));
+        assert_eq!(book.best_bid(), Some((dec!(100), dec!(1))));
+        assert_eq!(book.best_ask(), Some((dec!(101), dec!(2))));
+
+        book.apply(&OrderBookUpdate::new(vec![PriceLevel::new(dec!(101), dec!(4), QuoteType::BID)]));
+        assert_eq!(book.bids().collect::<Vec<_>>(), vec![(dec!(101), dec!(4)), (dec!(100), dec!(1))]);
+        assert_eq!(book.asks().collect::<Vec<_>>(), vec![(dec!(101), dec!(2)), (dec!(102), dec!(3))]);
+
+        book.clear();
+        assert_eq!((book.best_bid(), book.best_ask()), (None, None));
+        book.apply(&OrderBookUpdate::new(vec![PriceLevel::new(dec!(99), dec!(5), QuoteType::BID)]));
+        assert_eq!(book.best_bid(), Some((dec!(99), dec!(5))));
+    }
+
+    #[test]
+    fn test_btree_book() {
+        check_book(new_book(BookKind::BTree, None));
+        check_deltas(new_book(BookKind::BTree, None));
+    }
+
+    #[test]
+    fn test_pooled_book() {
+        check_book(new_book("pooled".parse().unwrap(), None));
+        check_deltas(new_book(BookKind::Pooled, None));
+    }
+}
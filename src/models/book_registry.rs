@@ -0,0 +1,196 @@
+// Books of several venues and symbols, each incoming message is routed to the book it is for
+use std::collections::BTreeMap;
+use std::fmt;
+use kraken_ws_client::api::BookData;
+
+use crate::models::binance::depth::parse_combined_partial_depth;
+use crate::models::kraken::translate;
+use crate::models::order_book::{OrderBook, OrderBookUpdate, UpdateOutcome};
+use crate::quote::Exchange;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BookKey {
+    pub exchange: Exchange,
+    pub symbol: String,
+}
+
+impl BookKey {
+    pub fn new(exchange: Exchange, symbol: &str) -> Self {
+        BookKey { exchange, symbol: symbol.to_string() }
+    }
+}
+
+impl fmt::Display for BookKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}:{}", self.exchange, self.symbol)
+    }
+}
+
+/// An update routed to the book of `key`.
+#[derive(Debug, Clone)]
+pub struct Routed {
+    pub key: BookKey,
+    pub update: OrderBookUpdate,
+    pub outcome: UpdateOutcome,
+}
+
+/// Books keyed by (venue, symbol), in venue then symbol order.
+///
+/// A book exists from its subscription on, messages for symbols that are not subscribed are
+/// not routed.
+#[derive(Debug, Default)]
+pub struct BookRegistry {
+    books: BTreeMap<BookKey, OrderBook>,
+}
+
+impl BookRegistry {
+    pub fn new() -> Self {
+        BookRegistry { books: BTreeMap::new() }
+    }
+
+    // Adds the book, an already subscribed book is kept as is
+    pub fn subscribe(&mut self, exchange: Exchange, symbol: &str, book: OrderBook) -> &mut OrderBook {
+        self.books.entry(BookKey::new(exchange, symbol)).or_insert(book)
+    }
+
+    pub fn unsubscribe(&mut self, exchange: Exchange, symbol: &str) -> Option<OrderBook> {
+        self.books.remove(&BookKey::new(exchange, symbol))
+    }
+
+    pub fn get(&self, exchange: Exchange, symbol: &str) -> Option<&OrderBook> {
+        self.books.get(&BookKey::new(exchange, symbol))
+    }
+
+    pub fn get_mut(&mut self, exchange: Exchange, symbol: &str) -> Option<&mut OrderBook> {
+        self.books.get_mut(&BookKey::new(exchange, symbol))
+    }
+
+    pub fn len(&self) -> usize {
+        self.books.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&BookKey, &OrderBook)> {
+        self.books.iter()
+    }
+
+    // Books of the venue
+    pub fn by_exchange(&self, exchange: Exchange) -> impl Iterator<Item = (&str, &OrderBook)> {
+        self.books.iter()
+            .filter(move |(key, _)| key.exchange == exchange)
+            .map(|(key, book)| (key.symbol.as_str(), book))
+    }
+
+    // Books of the symbol across venues
+    pub fn by_symbol<'a>(&'a self, symbol: &'a str) -> impl Iterator<Item = (Exchange, &'a OrderBook)> {
+        self.books.iter()
+            .filter(move |(key, _)| key.symbol == symbol)
+            .map(|(key, book)| (key.exchange, book))
+    }
+
+    // Every venue marks its books stale on disconnect
+    pub fn mark_stale(&mut self, exchange: Exchange) {
+        self.books.iter_mut()
+            .filter(|(key, _)| key.exchange == exchange)
+            .for_each(|(_, book)| book.mark_stale());
+    }
+
+    // The book of a single feed, the other feeds of the venue have their own connections.
+    // False when the symbol is not subscribed.
+    pub fn mark_stale_key(&mut self, key: &BookKey) -> bool {
+        match self.books.get_mut(key) {
+            Some(book) => {
+                book.mark_stale();
+                true
+            },
+            None => false,
+        }
+    }
+
+    // None when the symbol is not subscribed
+    pub fn route(&mut self, exchange: Exchange, symbol: &str, update: OrderBookUpdate) -> Option<Routed> {
+        let key = BookKey::new(exchange, symbol);
+        let outcome = self.books.get_mut(&key)?.update(&update);
+        Some(Routed { key, update, outcome })
+    }
+
+    // A Kraken v2 BookEvent carries one BookData per symbol
+    pub fn route_kraken(&mut self, book_data: &BookData, is_snapshot: bool) -> Option<Routed> {
+        let update = translate::from_book_data(book_data, is_snapshot);
+        self.route(Exchange::Kraken, &book_data.symbol, update)
+    }
+
+    // Binance combined stream of partial book depths, the symbol comes from the stream name
+    pub fn route_binance(&mut self, text: &str) -> Option<Routed> {
+        let (symbol, snapshot) = parse_combined_partial_depth(text)?;
+        self.route(Exchange::Binance, &symbol, snapshot.to_update())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::order_book::{PriceLevel, QuoteType};
+
+    fn snapshot(bid: rust_decimal::Decimal, ask: rust_decimal::Decimal) -> OrderBookUpdate {
+        OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(bid, dec!(1), QuoteType::BID),
+            PriceLevel::new(ask, dec!(1), QuoteType::ASK),
+        ])
+    }
+
+    #[test]
+    fn test_route_by_venue_and_symbol() {
+        let mut registry = BookRegistry::new();
+        registry.subscribe(Exchange::Kraken, "BTC/USD", OrderBook::new());
+        registry.subscribe(Exchange::Kraken, "ETH/USD", OrderBook::new());
+        registry.subscribe(Exchange::Binance, "BTCUSDT", OrderBook::new());
+
+        registry.route(Exchange::Kraken, "ETH/USD", snapshot(dec!(3000), dec!(3001))).unwrap();
+        assert!(registry.route(Exchange::Kraken, "SOL/USD", snapshot(dec!(150), dec!(151))).is_none());
+        assert_eq!(registry.get(Exchange::Kraken, "ETH/USD").unwrap().best_bid(), Some((dec!(3000), dec!(1))));
+        assert_eq!(registry.get(Exchange::Kraken, "BTC/USD").unwrap().best_bid(), None);
+
+        let text = r#"{"stream":"btcusdt@depth5@100ms","data":{"lastUpdateId":1,"bids":[["64000.00","1"]],"asks":[["64001.00","1"]]}}"#;
+        let routed = registry.route_binance(text).unwrap();
+        assert_eq!(routed.key, BookKey::new(Exchange::Binance, "BTCUSDT"));
+        assert_eq!(routed.outcome, UpdateOutcome::Applied);
+        assert!(registry.get(Exchange::Binance, "BTCUSDT").unwrap().is_synced());
+
+        let symbols: Vec<&str> = registry.by_exchange(Exchange::Kraken).map(|(symbol, _)| symbol).collect();
+        assert_eq!(symbols, vec!["BTC/USD", "ETH/USD"]);
+        assert_eq!(registry.by_symbol("BTCUSDT").count(), 1);
+
+        registry.mark_stale(Exchange::Kraken);
+        assert!(!registry.get(Exchange::Kraken, "ETH/USD").unwrap().is_synced());
+        assert!(registry.unsubscribe(Exchange::Kraken, "ETH/USD").is_some());
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn test_mark_stale_one_feed_of_the_venue() {
+        let mut registry = BookRegistry::new();
+        registry.subscribe(Exchange::Kraken, "XBT/USD", OrderBook::new());
+        registry.subscribe(Exchange::Kraken, "USDT/USD", OrderBook::new());
+        registry.route(Exchange::Kraken, "XBT/USD", snapshot(dec!(64000), dec!(64001)));
+        registry.route(Exchange::Kraken, "USDT/USD", snapshot(dec!(0.9998), dec!(1.0000)));
+
+        assert!(registry.mark_stale_key(&BookKey::new(Exchange::Kraken, "XBT/USD")));
+        assert!(!registry.get(Exchange::Kraken, "XBT/USD").unwrap().is_synced());
+        assert!(registry.get(Exchange::Kraken, "USDT/USD").unwrap().is_synced());
+        assert!(!registry.mark_stale_key(&BookKey::new(Exchange::Kraken, "ETH/USD")));
+    }
+
+    #[test]
+    fn test_subscribe_keeps_existing_book() {
+        let mut registry = BookRegistry::new();
+        registry.subscribe(Exchange::Kraken, "BTC/USD", OrderBook::new());
+        registry.route(Exchange::Kraken, "BTC/USD", snapshot(dec!(100), dec!(101)));
+        registry.subscribe(Exchange::Kraken, "BTC/USD", OrderBook::new());
+        assert!(registry.get(Exchange::Kraken, "BTC/USD").unwrap().is_synced());
+    }
+}
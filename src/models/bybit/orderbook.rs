@@ -0,0 +1,85 @@
+// Bybit v5 public `orderbook.{depth}.{symbol}` topic
+//
+// `u` increases by one with each delta of the topic, a snapshot restarts it (u = 1 after a
+// service restart). `seq` is shared across depths of the symbol and is not contiguous.
+//
+// https://bybit-exchange.github.io/docs/v5/websocket/public/orderbook
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use crate::models::order_book::{OrderBookUpdate, PriceLevel, QuoteType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Snapshot,
+    Delta,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrderbookData {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "b")]
+    pub bids: Vec<(Decimal, Decimal)>,
+    #[serde(rename = "a")]
+    pub asks: Vec<(Decimal, Decimal)>,
+    #[serde(rename = "u")]
+    pub update_id: u64,
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrderbookMessage {
+    pub topic: String,
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    pub ts: u64,
+    pub data: OrderbookData,
+}
+
+pub fn parse_orderbook(text: &str) -> Option<OrderbookMessage> {
+    serde_json::from_str(text).ok()
+}
+
+pub fn topic(depth: usize, symbol: &str) -> String {
+    format!("orderbook.{}.{}", depth, symbol)
+}
+
+impl OrderbookMessage {
+    pub fn to_update(&self) -> OrderBookUpdate {
+        let price_levels = self.data.asks.iter()
+            .map(|&(price, size)| PriceLevel::new(price, size, QuoteType::ASK))
+            .chain(self.data.bids.iter().map(|&(price, size)| PriceLevel::new(price, size, QuoteType::BID)))
+            .collect();
+        match self.kind {
+            Kind::Snapshot => OrderBookUpdate::snapshot(price_levels),
+            Kind::Delta => OrderBookUpdate::new(price_levels),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::order_book::OrderBook;
+
+    const SNAPSHOT: &str = include_str!("../../../tests/fixtures/bybit/orderbook_snapshot.json");
+    const DELTA: &str = include_str!("../../../tests/fixtures/bybit/orderbook_delta.json");
+
+    #[test]
+    fn test_snapshot_then_delta() {
+        let mut book = OrderBook::new();
+        let mut update_ids = Vec::new();
+        for text in [SNAPSHOT, DELTA] {
+            let message = parse_orderbook(text).unwrap();
+            assert_eq!(message.topic, topic(50, "BTCUSDT"));
+            update_ids.push(message.data.update_id);
+            book.update(&message.to_update());
+        }
+        assert_eq!(update_ids, vec![500, 501]);
+        assert_eq!(book.best_bid(), Some((dec!(66010.00), dec!(1.25))));
+        assert_eq!(book.best_ask(), Some((dec!(66010.15), dec!(0.1))));
+    }
+}
@@ -0,0 +1,154 @@
+// Coinbase Advanced Trade websocket: level2 and heartbeats channels
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use crate::models::order_book::{OrderBookUpdate, PriceLevel, QuoteType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Bid,
+    Offer,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Level2Update {
+    pub side: Side,
+    pub event_time: String,
+    pub price_level: Decimal,
+    // Quantity now at the level, zero removes it
+    pub new_quantity: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level2EventKind {
+    Snapshot,
+    Update,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Level2Event {
+    #[serde(rename = "type")]
+    pub kind: Level2EventKind,
+    pub product_id: String,
+    pub updates: Vec<Level2Update>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeartbeatEvent {
+    pub current_time: String,
+    pub heartbeat_counter: u64,
+}
+
+/// Messages of the subscribed channels. `sequence_num` counts every message of the
+/// connection, across channels, so a missing number means a lost message.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "channel")]
+pub enum Message {
+    #[serde(rename = "l2_data")]
+    Level2 { sequence_num: u64, timestamp: String, events: Vec<Level2Event> },
+    #[serde(rename = "heartbeats")]
+    Heartbeats { sequence_num: u64, events: Vec<HeartbeatEvent> },
+    #[serde(rename = "subscriptions")]
+    Subscriptions { sequence_num: u64 },
+}
+
+impl Message {
+    pub fn sequence_num(&self) -> u64 {
+        match self {
+            Message::Level2 { sequence_num, .. }
+            | Message::Heartbeats { sequence_num, .. }
+            | Message::Subscriptions { sequence_num } => *sequence_num,
+        }
+    }
+}
+
+// Anything else (errors, other channels) is None
+pub fn parse_message(text: &str) -> Option<Message> {
+    serde_json::from_str(text).ok()
+}
+
+// Only the sequence number, for any channel
+pub fn parse_sequence_num(text: &str) -> Option<u64> {
+    #[derive(Deserialize)]
+    struct Sequenced {
+        sequence_num: u64,
+    }
+    serde_json::from_str::<Sequenced>(text).ok().map(|message| message.sequence_num)
+}
+
+impl Level2Event {
+    pub fn to_update(&self) -> OrderBookUpdate {
+        let price_levels = self.updates.iter()
+            .map(|update| {
+                let quote_type = match update.side {
+                    Side::Bid => QuoteType::BID,
+                    Side::Offer => QuoteType::ASK,
+                };
+                PriceLevel::new(update.price_level, update.new_quantity, quote_type)
+            })
+            .collect();
+        match self.kind {
+            Level2EventKind::Snapshot => OrderBookUpdate::snapshot(price_levels),
+            Level2EventKind::Update => OrderBookUpdate::new(price_levels),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::order_book::OrderBook;
+
+    const SNAPSHOT: &str = include_str!("../../../tests/fixtures/coinbase/level2_snapshot.json");
+    const UPDATE: &str = include_str!("../../../tests/fixtures/coinbase/level2_update.json");
+    const HEARTBEATS: &str = include_str!("../../../tests/fixtures/coinbase/heartbeats.json");
+    const SUBSCRIPTIONS: &str = include_str!("../../../tests/fixtures/coinbase/subscriptions.json");
+
+    fn level2_events(text: &str) -> Vec<Level2Event> {
+        match parse_message(text) {
+            Some(Message::Level2 { events, .. }) => events,
+            message => panic!("expected l2_data, got {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_then_update() {
+        let mut book = OrderBook::new();
+        for text in [SNAPSHOT, UPDATE] {
+            for event in level2_events(text) {
+                assert_eq!(event.product_id, "BTC-USD");
+                book.update(&event.to_update());
+            }
+        }
+        assert!(book.is_synced());
+        assert_eq!(book.best_bid(), Some((dec!(66010.15), dec!(0.52))));
+        assert_eq!(book.best_ask(), Some((dec!(66011.00), dec!(2))));
+    }
+
+    #[test]
+    fn test_update_before_snapshot_is_dropped() {
+        let mut book = OrderBook::new();
+        for event in level2_events(UPDATE) {
+            assert_eq!(event.kind, Level2EventKind::Update);
+            book.update(&event.to_update());
+        }
+        assert_eq!(book.best_bid(), None);
+    }
+
+    #[test]
+    fn test_sequence_numbers() {
+        let sequences: Vec<u64> = [SUBSCRIPTIONS, SNAPSHOT, UPDATE, HEARTBEATS].iter()
+            .map(|text| parse_message(text).unwrap().sequence_num())
+            .collect();
+        assert_eq!(sequences, vec![0, 1, 2, 3]);
+        assert_eq!(parse_sequence_num(HEARTBEATS), Some(3));
+
+        match parse_message(HEARTBEATS) {
+            Some(Message::Heartbeats { events, .. }) => assert_eq!(events[0].heartbeat_counter, 92),
+            message => panic!("expected heartbeats, got {:?}", message),
+        }
+        assert!(parse_message(r#"{"type":"error","message":"failure to subscribe"}"#).is_none());
+    }
+}
@@ -0,0 +1,287 @@
+// Books of the same instrument on several venues merged into one ladder. Each price keeps the
+// quantity of every venue quoting it, and the best levels across venues give a cross-venue
+// best bid and offer.
+//
+// The venues' books are merged as they are at the time, the consolidated book is rebuilt from
+// them rather than updated. Books quoted in another currency are merged with their prices
+// converted by the FX rates, when given.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use rust_decimal::Decimal;
+
+use crate::models::book::Book;
+use crate::models::book_registry::{BookKey, BookRegistry};
+use crate::models::fx::{convert_price, FxRates};
+use crate::models::instrument::{canonical_asset, Instrument};
+use crate::models::order_book::{OrderBook, QuoteType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VenueQty {
+    pub key: BookKey,
+    pub qty: Decimal,
+}
+
+/// A price of the consolidated book and the venues quoting it, in the order their books were added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsolidatedLevel {
+    pub price: Decimal,
+    pub venues: Vec<VenueQty>,
+}
+
+impl ConsolidatedLevel {
+    pub fn total(&self) -> Decimal {
+        self.venues.iter().map(|venue| venue.qty).sum()
+    }
+
+    pub fn qty_of(&self, key: &BookKey) -> Option<Decimal> {
+        self.venues.iter().find(|venue| venue.key == *key).map(|venue| venue.qty)
+    }
+}
+
+impl fmt::Display for ConsolidatedLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{} (", self.total(), self.price)?;
+        for (i, venue) in self.venues.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", venue.key, venue.qty)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Best bid and offer across venues. Crossed when a venue bids above another one's offer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossVenueBbo {
+    pub bid: ConsolidatedLevel,
+    pub ask: ConsolidatedLevel,
+}
+
+impl CrossVenueBbo {
+    pub fn spread(&self) -> Decimal {
+        self.ask.price - self.bid.price
+    }
+
+    pub fn mid(&self) -> Decimal {
+        (self.bid.price + self.ask.price) / Decimal::TWO
+    }
+
+    pub fn is_crossed(&self) -> bool {
+        self.bid.price >= self.ask.price
+    }
+}
+
+impl fmt::Display for CrossVenueBbo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bid {} / ask {}", self.bid, self.ask)?;
+        if self.is_crossed() {
+            write!(f, " crossed")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsolidatedBook {
+    instrument: Instrument,
+    // Quote assets counted as the instrument's quote, e.g. USDT for USD
+    at_par: Vec<String>,
+    fx: Option<FxRates>,
+    venues: Vec<BookKey>,
+    bids: BTreeMap<Decimal, Vec<VenueQty>>,
+    asks: BTreeMap<Decimal, Vec<VenueQty>>,
+}
+
+impl ConsolidatedBook {
+    pub fn new(instrument: Instrument) -> Self {
+        ConsolidatedBook {
+            instrument,
+            at_par: Vec::new(),
+            fx: None,
+            venues: Vec::new(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        }
+    }
+
+    // Books quoted in `asset` are merged as if quoted in the instrument's quote asset
+    pub fn with_quote_at_par(mut self, asset: &str) -> Self {
+        self.at_par.push(canonical_asset(asset));
+        self
+    }
+
+    // Books quoted in other currencies are converted to the instrument's quote asset
+    pub fn with_fx(mut self, fx: FxRates) -> Self {
+        self.fx = Some(fx);
+        self
+    }
+
+    pub fn instrument(&self) -> &Instrument {
+        &self.instrument
+    }
+
+    // Rate converting the prices of the venue symbol, None when it is another instrument or
+    // its quote currency has no rate. Prices at par are not converted.
+    fn rate(&self, key: &BookKey) -> Option<Option<Decimal>> {
+        let instrument = Instrument::from_venue(key.exchange, &key.symbol)
+            .filter(|instrument| instrument.base == self.instrument.base)?;
+        if instrument.quote == self.instrument.quote || self.at_par.contains(&instrument.quote) {
+            return Some(None);
+        }
+        self.fx.as_ref()?.rate(&instrument.quote, &self.instrument.quote).map(Some)
+    }
+
+    // Whether the venue symbol stands for the instrument
+    pub fn matches(&self, key: &BookKey) -> bool {
+        self.rate(key).is_some()
+    }
+
+    // Merges the book, false when it is for another instrument or is not synced
+    pub fn add_book(&mut self, key: &BookKey, book: &OrderBook) -> bool {
+        if !book.is_synced() || self.venues.contains(key) {
+            return false;
+        }
+        let rate = match self.rate(key) {
+            Some(rate) => rate,
+            None => return false,
+        };
+        let convert = |price: Decimal| rate.map_or(price, |rate| convert_price(price, rate));
+        for (price, qty) in book.bids() {
+            self.bids.entry(convert(price)).or_default().push(VenueQty { key: key.clone(), qty });
+        }
+        for (price, qty) in book.asks() {
+            self.asks.entry(convert(price)).or_default().push(VenueQty { key: key.clone(), qty });
+        }
+        self.venues.push(key.clone());
+        true
+    }
+
+    // Merges every book of the instrument, returns how many were
+    pub fn add_registry(&mut self, registry: &BookRegistry) -> usize {
+        registry.iter()
+            .filter(|(key, book)| self.add_book(key, book))
+            .count()
+    }
+
+    pub fn clear(&mut self) {
+        self.venues.clear();
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    // Books merged, in order of addition
+    pub fn venues(&self) -> &[BookKey] {
+        &self.venues
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    // Best price first, like Book::levels
+    pub fn levels(&self, side: QuoteType) -> Box<dyn Iterator<Item = ConsolidatedLevel> + '_> {
+        let level = |(&price, venues): (&Decimal, &Vec<VenueQty>)| ConsolidatedLevel { price, venues: venues.clone() };
+        match side {
+            QuoteType::BID => Box::new(self.bids.iter().rev().map(level)),
+            QuoteType::ASK => Box::new(self.asks.iter().map(level)),
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<ConsolidatedLevel> {
+        self.levels(QuoteType::BID).next()
+    }
+
+    pub fn best_ask(&self) -> Option<ConsolidatedLevel> {
+        self.levels(QuoteType::ASK).next()
+    }
+
+    pub fn bbo(&self) -> Option<CrossVenueBbo> {
+        Some(CrossVenueBbo { bid: self.best_bid()?, ask: self.best_ask()? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::quote::Exchange;
+
+    fn registry() -> BookRegistry {
+        let mut registry = BookRegistry::new();
+        registry.subscribe(Exchange::Kraken, "XBT/USD", OrderBook::from_levels(
+            &[(dec!(64000), dec!(1)), (dec!(63999), dec!(2))],
+            &[(dec!(64002), dec!(1.5))]));
+        registry.subscribe(Exchange::Binance, "BTCUSDT", OrderBook::from_levels(
+            &[(dec!(64000), dec!(0.5)), (dec!(63998), dec!(3))],
+            &[(dec!(64001), dec!(0.2)), (dec!(64002), dec!(0.7))]));
+        registry.subscribe(Exchange::Kraken, "ETH/USD", OrderBook::from_levels(&[(dec!(3000), dec!(1))], &[(dec!(3001), dec!(1))]));
+        registry
+    }
+
+    #[test]
+    fn test_merge_venues() {
+        let kraken = BookKey::new(Exchange::Kraken, "XBT/USD");
+        let binance = BookKey::new(Exchange::Binance, "BTCUSDT");
+        let mut consolidated = ConsolidatedBook::new(Instrument::new("BTC", "USD")).with_quote_at_par("USDT");
+        assert_eq!(consolidated.add_registry(&registry()), 2);
+        assert_eq!(consolidated.venues(), &[kraken.clone(), binance.clone()]);
+
+        let bids: Vec<(Decimal, Decimal)> = consolidated.levels(QuoteType::BID).map(|level| (level.price, level.total())).collect();
+        assert_eq!(bids, vec![(dec!(64000), dec!(1.5)), (dec!(63999), dec!(2)), (dec!(63998), dec!(3))]);
+
+        let best_bid = consolidated.best_bid().unwrap();
+        assert_eq!(best_bid.qty_of(&kraken), Some(dec!(1)));
+        assert_eq!(best_bid.qty_of(&binance), Some(dec!(0.5)));
+
+        let ask = consolidated.levels(QuoteType::ASK).nth(1).unwrap();
+        assert_eq!(ask.venues, vec![
+            VenueQty { key: kraken.clone(), qty: dec!(1.5) },
+            VenueQty { key: binance.clone(), qty: dec!(0.7) },
+        ]);
+
+        let bbo = consolidated.bbo().unwrap();
+        assert_eq!((bbo.bid.price, bbo.ask.price, bbo.spread()), (dec!(64000), dec!(64001), dec!(1)));
+        assert_eq!(bbo.ask.venues, vec![VenueQty { key: binance, qty: dec!(0.2) }]);
+        assert!(!bbo.is_crossed());
+    }
+
+    #[test]
+    fn test_skips_other_books() {
+        let mut consolidated = ConsolidatedBook::new(Instrument::new("BTC", "USD"));
+        let mut registry = registry();
+        // USDT is not counted as USD unless asked for
+        assert_eq!(consolidated.add_registry(&registry), 1);
+
+        consolidated.clear();
+        registry.mark_stale(Exchange::Kraken);
+        assert_eq!(consolidated.add_registry(&registry), 0);
+        assert!(consolidated.is_empty());
+        assert_eq!(consolidated.bbo(), None);
+    }
+
+    #[test]
+    fn test_converted_venues() {
+        let fx = FxRates::new("USD").with_fixed_rate("USDT", "USD", dec!(0.9999));
+        let mut consolidated = ConsolidatedBook::new(Instrument::new("BTC", "USD")).with_fx(fx);
+        assert_eq!(consolidated.add_registry(&registry()), 2);
+
+        let binance = BookKey::new(Exchange::Binance, "BTCUSDT");
+        let bids: Vec<(Decimal, Decimal)> = consolidated.levels(QuoteType::BID).map(|level| (level.price, level.total())).collect();
+        assert_eq!(bids, vec![(dec!(64000), dec!(1)), (dec!(63999), dec!(2)), (dec!(63993.6), dec!(0.5)), (dec!(63991.6002), dec!(3))]);
+        assert_eq!(consolidated.best_ask().unwrap().venues, vec![VenueQty { key: binance, qty: dec!(0.2) }]);
+        assert_eq!(consolidated.best_ask().unwrap().price, dec!(63994.5999));
+    }
+
+    #[test]
+    fn test_crossed_venues() {
+        let mut consolidated = ConsolidatedBook::new(Instrument::new("BTC", "USDT"));
+        consolidated.add_book(&BookKey::new(Exchange::Binance, "BTCUSDT"), &OrderBook::from_levels(&[(dec!(100), dec!(1))], &[(dec!(102), dec!(1))]));
+        consolidated.add_book(&BookKey::new(Exchange::Okx, "BTC-USDT"), &OrderBook::from_levels(&[(dec!(103), dec!(2))], &[(dec!(104), dec!(1))]));
+        let bbo = consolidated.bbo().unwrap();
+        assert!(bbo.is_crossed());
+        assert_eq!(bbo.spread(), dec!(-1));
+        assert_eq!(bbo.to_string(), "bid 2@103 (Okx:BTC-USDT 2) / ask 1@102 (Binance:BTCUSDT 1) crossed");
+    }
+}
@@ -0,0 +1,171 @@
+// Difference between two books, as the minimal delta turning one into the other.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use rust_decimal::Decimal;
+
+use crate::models::book::Book;
+use crate::models::order_book::{OrderBookUpdate, PriceLevel, QuoteType};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SideDiff {
+    // (price, qty) only present in the target book
+    pub added: Vec<(Decimal, Decimal)>,
+    // prices only present in the source book
+    pub removed: Vec<Decimal>,
+    // (price, qty in source, qty in target)
+    pub changed: Vec<(Decimal, Decimal, Decimal)>,
+}
+
+impl SideDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    fn push_levels(&self, quote_type: QuoteType, levels: &mut Vec<PriceLevel>) {
+        for &price in &self.removed {
+            levels.push(PriceLevel::new(price, Decimal::ZERO, quote_type));
+        }
+        for &(price, _, qty) in &self.changed {
+            levels.push(PriceLevel::new(price, qty, quote_type));
+        }
+        for &(price, qty) in &self.added {
+            levels.push(PriceLevel::new(price, qty, quote_type));
+        }
+    }
+}
+
+/// Levels are listed best price first on both sides.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BookDiff {
+    pub bids: SideDiff,
+    pub asks: SideDiff,
+}
+
+impl BookDiff {
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    // Delta that, applied to the source book, gives the target book
+    pub fn to_update(&self) -> OrderBookUpdate {
+        let mut levels = Vec::new();
+        self.bids.push_levels(QuoteType::BID, &mut levels);
+        self.asks.push_levels(QuoteType::ASK, &mut levels);
+        OrderBookUpdate::new(levels)
+    }
+}
+
+// Compact one line form, e.g. `BID +100@1 -99 ~98:2>3 | ASK +101@4`
+impl fmt::Display for BookDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sides = [(QuoteType::BID, &self.bids), (QuoteType::ASK, &self.asks)];
+        let mut first = true;
+        for (quote_type, side) in sides.iter() {
+            if side.is_empty() {
+                continue;
+            }
+            if !first {
+                write!(f, " | ")?;
+            }
+            first = false;
+            write!(f, "{}", quote_type)?;
+            for (price, qty) in &side.added {
+                write!(f, " +{}@{}", price, qty)?;
+            }
+            for price in &side.removed {
+                write!(f, " -{}", price)?;
+            }
+            for (price, before, after) in &side.changed {
+                write!(f, " ~{}:{}>{}", price, before, after)?;
+            }
+        }
+        if first {
+            write!(f, "no change")?;
+        }
+        Ok(())
+    }
+}
+
+fn diff_side<I, J>(source: I, target: J, best_first_descending: bool) -> SideDiff
+    where
+        I: Iterator<Item = (Decimal, Decimal)>,
+        J: Iterator<Item = (Decimal, Decimal)>,
+{
+    let before: BTreeMap<Decimal, Decimal> = source.collect();
+    let after: BTreeMap<Decimal, Decimal> = target.collect();
+    let mut diff = SideDiff::default();
+
+    for (&price, &qty) in after.iter() {
+        match before.get(&price) {
+            None => diff.added.push((price, qty)),
+            Some(&previous) if previous != qty => diff.changed.push((price, previous, qty)),
+            _ => (),
+        }
+    }
+    for &price in before.keys() {
+        if !after.contains_key(&price) {
+            diff.removed.push(price);
+        }
+    }
+
+    if best_first_descending {
+        diff.added.reverse();
+        diff.removed.reverse();
+        diff.changed.reverse();
+    }
+    diff
+}
+
+pub fn diff<S: Book + ?Sized, T: Book + ?Sized>(source: &S, target: &T) -> BookDiff {
+    BookDiff {
+        bids: diff_side(source.bids(), target.bids(), true),
+        asks: diff_side(source.asks(), target.asks(), false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::book::{new_book, BookKind};
+
+    fn snapshot(levels: Vec<(Decimal, Decimal, QuoteType)>) -> OrderBookUpdate {
+        OrderBookUpdate::snapshot(levels.into_iter().map(|(p, q, t)| PriceLevel::new(p, q, t)).collect())
+    }
+
+    #[test]
+    fn test_diff_and_apply() {
+        let mut source = new_book(BookKind::BTree, None);
+        source.apply(&snapshot(vec![
+            (dec!(100), dec!(1), QuoteType::BID),
+            (dec!(99), dec!(2), QuoteType::BID),
+            (dec!(101), dec!(1), QuoteType::ASK),
+        ]));
+        let mut target = new_book(BookKind::Pooled, None);
+        target.apply(&snapshot(vec![
+            (dec!(100), dec!(3), QuoteType::BID),
+            (dec!(98), dec!(2), QuoteType::BID),
+            (dec!(101), dec!(1), QuoteType::ASK),
+            (dec!(102), dec!(5), QuoteType::ASK),
+        ]));
+
+        let book_diff = source.diff(target.as_ref());
+        assert_eq!(book_diff.bids.added, vec![(dec!(98), dec!(2))]);
+        assert_eq!(book_diff.bids.removed, vec![dec!(99)]);
+        assert_eq!(book_diff.bids.changed, vec![(dec!(100), dec!(1), dec!(3))]);
+        assert_eq!(book_diff.asks, SideDiff { added: vec![(dec!(102), dec!(5))], ..SideDiff::default() });
+        assert_eq!(book_diff.to_string(), "BID +98@2 -99 ~100:1>3 | ASK +102@5");
+
+        source.apply(&book_diff.to_update());
+        assert!(diff(source.as_ref(), target.as_ref()).is_empty(), "Applying the diff should give the target book");
+    }
+
+    #[test]
+    fn test_no_change() {
+        let book = new_book(BookKind::BTree, None);
+        let book_diff = book.diff(book.as_ref());
+        assert!(book_diff.is_empty());
+        assert_eq!(book_diff.to_string(), "no change");
+    }
+}
@@ -0,0 +1,256 @@
+// Conversion between quote currencies, so that books quoted in USD and in USDT can be compared.
+//
+// Rates come from the mid of configured books, e.g. Kraken's USDT/USD, or are fixed. An asset
+// without a rate to the target can still be converted through the reference currency. A rate
+// whose book is not synced is unknown, nothing is converted with it.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use rust_decimal::Decimal;
+
+use crate::models::book::Book;
+use crate::models::book_registry::{BookKey, BookRegistry};
+use crate::models::instrument::{canonical_asset, Instrument};
+use crate::models::order_book::{OrderBook, OrderBookUpdate, PriceLevel, QuoteType};
+use crate::quote::{Exchange, Quote};
+
+// Decimal places of converted prices, a rate and its inverse have up to 28 of them
+pub const CONVERTED_DP: u32 = 8;
+
+pub fn convert_price(price: Decimal, rate: Decimal) -> Decimal {
+    (price * rate).round_dp(CONVERTED_DP)
+}
+
+/// Price of one unit of `base` in `quote`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FxRate {
+    pub bid: Decimal,
+    pub ask: Decimal,
+    // Nanoseconds since epoch, 0 for a fixed rate
+    pub updated_ns: u64,
+}
+
+impl FxRate {
+    pub fn fixed(rate: Decimal) -> Self {
+        FxRate { bid: rate, ask: rate, updated_ns: 0 }
+    }
+
+    pub fn mid(&self) -> Decimal {
+        (self.bid + self.ask) / Decimal::TWO
+    }
+}
+
+impl fmt::Display for FxRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} / {}", self.bid, self.ask)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FxRates {
+    reference: String,
+    // Books giving rates, with the pair they quote
+    sources: Vec<(BookKey, Instrument)>,
+    // Keyed by (base, quote)
+    rates: BTreeMap<(String, String), FxRate>,
+}
+
+impl FxRates {
+    pub fn new(reference: &str) -> Self {
+        FxRates { reference: canonical_asset(reference), sources: Vec::new(), rates: BTreeMap::new() }
+    }
+
+    // The mid of the book gives the rate of its pair
+    pub fn with_source(mut self, exchange: Exchange, symbol: &str) -> Self {
+        let instrument = Instrument::from_venue(exchange, symbol)
+            .unwrap_or_else(|| panic!("Unknown FX pair {} on {:?}", symbol, exchange));
+        self.sources.push((BookKey::new(exchange, symbol), instrument));
+        self
+    }
+
+    // Overridden by a source of the same pair once its book is synced
+    pub fn with_fixed_rate(mut self, base: &str, quote: &str, rate: Decimal) -> Self {
+        self.rates.insert((canonical_asset(base), canonical_asset(quote)), FxRate::fixed(rate));
+        self
+    }
+
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = &BookKey> {
+        self.sources.iter().map(|(key, _)| key)
+    }
+
+    pub fn is_source(&self, key: &BookKey) -> bool {
+        self.sources.iter().any(|(source, _)| source == key)
+    }
+
+    // Takes the rate from the book when it is a source, a book without both sides or not
+    // synced forgets its rate. Returns the rate of the pair.
+    pub fn update(&mut self, key: &BookKey, book: &OrderBook, now_ns: u64) -> Option<FxRate> {
+        let (_, instrument) = self.sources.iter().find(|(source, _)| source == key)?;
+        let pair = (instrument.base.clone(), instrument.quote.clone());
+        match (book.is_synced(), book.best_bid(), book.best_ask()) {
+            (true, Some((bid, _)), Some((ask, _))) => {
+                let rate = FxRate { bid, ask, updated_ns: now_ns };
+                self.rates.insert(pair, rate);
+                Some(rate)
+            },
+            _ => {
+                self.rates.remove(&pair);
+                None
+            },
+        }
+    }
+
+    // Every source subscribed in the registry
+    pub fn update_registry(&mut self, registry: &BookRegistry, now_ns: u64) {
+        let sources: Vec<BookKey> = self.sources().cloned().collect();
+        for key in sources {
+            if let Some(book) = registry.get(key.exchange, &key.symbol) {
+                self.update(&key, book, now_ns);
+            }
+        }
+    }
+
+    fn direct_rate(&self, from: &str, to: &str) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+        if let Some(rate) = self.rates.get(&(from.to_string(), to.to_string())) {
+            return Some(rate.mid());
+        }
+        self.rates.get(&(to.to_string(), from.to_string()))
+            .filter(|rate| !rate.mid().is_zero())
+            .map(|rate| Decimal::ONE / rate.mid())
+    }
+
+    // Units of `to` one unit of `from` is worth, at mid
+    pub fn rate(&self, from: &str, to: &str) -> Option<Decimal> {
+        let (from, to) = (canonical_asset(from), canonical_asset(to));
+        self.direct_rate(&from, &to)
+            .or_else(|| Some(self.direct_rate(&from, &self.reference)? * self.direct_rate(&self.reference, &to)?))
+    }
+
+    pub fn to_reference(&self, amount: Decimal, asset: &str) -> Option<Decimal> {
+        Some(convert_price(amount, self.rate(asset, &self.reference)?))
+    }
+
+    // Same book with prices in `to`, quantities are in base asset and unchanged. None when
+    // the book is not synced or there is no rate.
+    pub fn convert_book(&self, book: &OrderBook, from: &str, to: &str) -> Option<OrderBook> {
+        if !book.is_synced() {
+            return None;
+        }
+        let rate = self.rate(from, to)?;
+        let levels = [QuoteType::BID, QuoteType::ASK].into_iter()
+            .flat_map(|side| book.levels(side).map(move |(price, qty)| PriceLevel::new(convert_price(price, rate), qty, side)))
+            .collect();
+        let mut converted = OrderBook::new();
+        converted.update(&OrderBookUpdate::snapshot(levels));
+        Some(converted)
+    }
+
+    // The book of a venue symbol with prices in `to`
+    pub fn convert_venue_book(&self, key: &BookKey, book: &OrderBook, to: &str) -> Option<OrderBook> {
+        let instrument = Instrument::from_venue(key.exchange, &key.symbol)?;
+        self.convert_book(book, &instrument.quote, to)
+    }
+
+    // Prices in `to`, sizes and times unchanged
+    pub fn convert_quote(&self, quote: &Quote, to: &str) -> Option<Quote> {
+        let instrument = Instrument::from_venue(quote.exchange, &quote.symbol)?;
+        let rate = self.rate(&instrument.quote, to)?;
+        Some(Quote {
+            best_bid: convert_price(quote.best_bid, rate),
+            best_ask: convert_price(quote.best_ask, rate),
+            ..quote.clone()
+        })
+    }
+}
+
+impl fmt::Display for FxRates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rates to {}:", self.reference)?;
+        for ((base, quote), rate) in &self.rates {
+            write!(f, " {}/{} {};", base, quote, rate)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn book(bid: Decimal, ask: Decimal) -> OrderBook {
+        OrderBook::from_levels(&[(bid, dec!(2))], &[(ask, dec!(3))])
+    }
+
+    #[test]
+    fn test_rates_from_source_books() {
+        let usdt_usd = BookKey::new(Exchange::Kraken, "USDT/USD");
+        let mut fx = FxRates::new("USD").with_source(Exchange::Kraken, "USDT/USD");
+        assert_eq!(fx.rate("USDT", "USD"), None);
+        assert_eq!(fx.rate("usd", "USD"), Some(Decimal::ONE));
+
+        let rate = fx.update(&usdt_usd, &book(dec!(0.9998), dec!(1.0000)), 1_000).unwrap();
+        assert_eq!((rate.mid(), rate.updated_ns), (dec!(0.9999), 1_000));
+        assert_eq!(fx.rate("USDT", "USD"), Some(dec!(0.9999)));
+        assert_eq!(fx.rate("USD", "USDT").unwrap().round_dp(8), dec!(1.00010001));
+        assert_eq!(fx.to_reference(dec!(64000), "USDT"), Some(dec!(63993.6)));
+        // Other books are not sources
+        assert!(fx.update(&BookKey::new(Exchange::Kraken, "XBT/USD"), &book(dec!(1), dec!(2)), 2_000).is_none());
+
+        let mut stale = book(dec!(0.9998), dec!(1.0000));
+        stale.mark_stale();
+        assert!(fx.update(&usdt_usd, &stale, 3_000).is_none());
+        assert_eq!(fx.rate("USDT", "USD"), None);
+    }
+
+    #[test]
+    fn test_rate_kept_when_another_feed_of_the_venue_drops() {
+        let mut registry = BookRegistry::new();
+        registry.subscribe(Exchange::Kraken, "XBT/USD", book(dec!(64000), dec!(64001)));
+        registry.subscribe(Exchange::Kraken, "USDT/USD", book(dec!(0.9998), dec!(1.0000)));
+        let mut fx = FxRates::new("USD").with_source(Exchange::Kraken, "USDT/USD");
+        fx.update_registry(&registry, 1_000);
+
+        registry.mark_stale_key(&BookKey::new(Exchange::Kraken, "XBT/USD"));
+        fx.update_registry(&registry, 2_000);
+        assert_eq!(fx.rate("USDT", "USD"), Some(dec!(0.9999)));
+
+        registry.mark_stale_key(&BookKey::new(Exchange::Kraken, "USDT/USD"));
+        fx.update_registry(&registry, 3_000);
+        assert_eq!(fx.rate("USDT", "USD"), None);
+    }
+
+    #[test]
+    fn test_cross_through_reference() {
+        let fx = FxRates::new("USD")
+            .with_fixed_rate("USDT", "USD", dec!(0.9999))
+            .with_fixed_rate("EUR", "USD", dec!(1.08));
+        assert_eq!(fx.rate("EUR", "USDT").unwrap().round_dp(6), dec!(1.080108));
+        assert_eq!(fx.rate("GBP", "USD"), None);
+    }
+
+    #[test]
+    fn test_convert_book_and_quote() {
+        let fx = FxRates::new("USD").with_fixed_rate("USDT", "USD", dec!(0.9999));
+        let binance = BookKey::new(Exchange::Binance, "BTCUSDT");
+        let converted = fx.convert_venue_book(&binance, &book(dec!(64000), dec!(64001)), "USD").unwrap();
+        assert_eq!(converted.best_bid(), Some((dec!(63993.6), dec!(2))));
+        assert_eq!(converted.best_ask(), Some((dec!(63994.5999), dec!(3))));
+
+        let mut stale = book(dec!(64000), dec!(64001));
+        stale.mark_stale();
+        assert!(fx.convert_book(&stale, "USDT", "USD").is_none());
+
+        let quote = Quote::from_book(Exchange::Binance, "BTCUSDT", &book(dec!(64000), dec!(64001)), 5).unwrap();
+        let converted = fx.convert_quote(&quote, "USD").unwrap();
+        assert_eq!((converted.best_bid, converted.best_bid_qty, converted.received_ns), (dec!(63993.6), dec!(2), 5));
+        assert!(fx.convert_quote(&quote, "EUR").is_none());
+    }
+}
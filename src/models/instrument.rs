@@ -0,0 +1,114 @@
+// Canonical identity of the instruments, whatever the venue's spelling: Kraken "XBT/USD" and
+// "BTC/USD", Binance "BTCUSDT", Coinbase "BTC-USD", OKX "BTC-USDT" and Bybit "BTCUSDT".
+
+use std::fmt;
+
+use crate::quote::Exchange;
+
+// Quote assets of the venues gluing base and quote together, longest first so that "USDT"
+// is tried before "USD"
+const QUOTE_ASSETS: [&str; 14] = [
+    "FDUSD", "USDT", "USDC", "BUSD", "TUSD", "USDE", "DAI", "USD", "EUR", "GBP", "TRY", "BTC", "ETH", "BNB",
+];
+
+// Kraken's legacy asset codes
+const ALIASES: [(&str, &str); 6] = [
+    ("XBT", "BTC"),
+    ("XXBT", "BTC"),
+    ("XETH", "ETH"),
+    ("XDG", "DOGE"),
+    ("ZUSD", "USD"),
+    ("ZEUR", "EUR"),
+];
+
+// Upper case asset code with Kraken's aliases resolved
+pub fn canonical_asset(asset: &str) -> String {
+    let asset = asset.trim().to_uppercase();
+    ALIASES.iter()
+        .find(|(alias, _)| *alias == asset)
+        .map(|(_, canonical)| canonical.to_string())
+        .unwrap_or(asset)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instrument {
+    pub base: String,
+    pub quote: String,
+}
+
+impl Instrument {
+    pub fn new(base: &str, quote: &str) -> Self {
+        Instrument { base: canonical_asset(base), quote: canonical_asset(quote) }
+    }
+
+    // "BTC/USD", "BTC-USD" or "BTCUSDT", None when base and quote can't be told apart
+    pub fn parse(symbol: &str) -> Option<Self> {
+        if let Some((base, quote)) = symbol.split_once(['/', '-', '_']) {
+            return match (base.is_empty(), quote.is_empty()) {
+                (false, false) => Some(Instrument::new(base, quote)),
+                _ => None,
+            };
+        }
+        let symbol = symbol.to_uppercase();
+        QUOTE_ASSETS.iter()
+            .filter(|quote| symbol.len() > quote.len() && symbol.ends_with(*quote))
+            .map(|quote| Instrument::new(&symbol[..symbol.len() - quote.len()], quote))
+            .next()
+    }
+
+    // Instrument a venue symbol stands for
+    pub fn from_venue(exchange: Exchange, symbol: &str) -> Option<Self> {
+        match exchange {
+            Exchange::Kraken | Exchange::Coinbase | Exchange::Okx => Instrument::parse(symbol),
+            // Only glued symbols
+            Exchange::Binance | Exchange::Bybit if symbol.chars().all(char::is_alphanumeric) => Instrument::parse(symbol),
+            Exchange::Binance | Exchange::Bybit => None,
+        }
+    }
+
+    // Venue spelling, e.g. to subscribe
+    pub fn venue_symbol(&self, exchange: Exchange) -> String {
+        match exchange {
+            Exchange::Kraken => format!("{}/{}", self.base, self.quote),
+            Exchange::Coinbase | Exchange::Okx => format!("{}-{}", self.base, self.quote),
+            Exchange::Binance | Exchange::Bybit => format!("{}{}", self.base, self.quote),
+        }
+    }
+}
+
+impl fmt::Display for Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.base, self.quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_venue_spellings() {
+        let btc_usd = Instrument::new("BTC", "USD");
+        assert_eq!(Instrument::from_venue(Exchange::Kraken, "XBT/USD"), Some(btc_usd.clone()));
+        assert_eq!(Instrument::from_venue(Exchange::Kraken, "BTC/USD"), Some(btc_usd.clone()));
+        assert_eq!(Instrument::from_venue(Exchange::Coinbase, "BTC-USD"), Some(btc_usd.clone()));
+
+        let btc_usdt = Instrument::new("BTC", "USDT");
+        assert_eq!(Instrument::from_venue(Exchange::Binance, "BTCUSDT"), Some(btc_usdt.clone()));
+        assert_eq!(Instrument::from_venue(Exchange::Bybit, "btcusdt"), Some(btc_usdt.clone()));
+        assert_eq!(Instrument::from_venue(Exchange::Okx, "BTC-USDT"), Some(btc_usdt.clone()));
+        assert_eq!(Instrument::from_venue(Exchange::Binance, "ETHBTC"), Some(Instrument::new("ETH", "BTC")));
+
+        assert_eq!(Instrument::from_venue(Exchange::Binance, "USDT"), None);
+        assert_eq!(Instrument::from_venue(Exchange::Binance, "BTC/USDT"), None);
+        assert_eq!(Instrument::parse("BTC/"), None);
+    }
+
+    #[test]
+    fn test_venue_symbol() {
+        let instrument = Instrument::from_venue(Exchange::Kraken, "XBT/USDT").unwrap();
+        assert_eq!(instrument.to_string(), "BTC/USDT");
+        assert_eq!(instrument.venue_symbol(Exchange::Binance), "BTCUSDT");
+        assert_eq!(instrument.venue_symbol(Exchange::Okx), "BTC-USDT");
+    }
+}
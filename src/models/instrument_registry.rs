@@ -0,0 +1,381 @@
+// Trading rules of the instruments on each venue: tick size, lot size, precisions and minimum
+// sizes, as published by Kraken's instrument channel or Binance's exchangeInfo.
+//
+// Levels received from a venue must already follow its rules, they are rejected otherwise.
+// Orders sent to it are rounded onto them, never to a more aggressive price.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use rust_decimal::Decimal;
+
+use crate::models::book_registry::BookKey;
+use crate::models::instrument::Instrument;
+use crate::models::matching_engine::{NewOrder, OrderType};
+use crate::models::order_book::{OrderBookUpdate, PriceLevel};
+use crate::models::types::{BuySell, Price, Qty};
+use crate::quote::Exchange;
+
+/// Why a level or an order breaks the rules of its instrument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecViolation {
+    NonPositivePrice(Decimal),
+    NegativeQty(Decimal),
+    OffTick { price: Decimal, tick_size: Decimal },
+    OffLot { qty: Decimal, lot_size: Decimal },
+    BelowMinQty { qty: Decimal, min_qty: Decimal },
+    BelowMinNotional { notional: Decimal, min_notional: Decimal },
+    // Only cancels are accepted, or the instrument is halted
+    NotTrading,
+}
+
+impl fmt::Display for SpecViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecViolation::NonPositivePrice(price) => write!(f, "price {} is not positive", price),
+            SpecViolation::NegativeQty(qty) => write!(f, "quantity {} is negative", qty),
+            SpecViolation::OffTick { price, tick_size } => write!(f, "price {} is not a multiple of the tick size {}", price, tick_size),
+            SpecViolation::OffLot { qty, lot_size } => write!(f, "quantity {} is not a multiple of the lot size {}", qty, lot_size),
+            SpecViolation::BelowMinQty { qty, min_qty } => write!(f, "quantity {} is below the minimum {}", qty, min_qty),
+            SpecViolation::BelowMinNotional { notional, min_notional } =>
+                write!(f, "notional {} is below the minimum {}", notional, min_notional),
+            SpecViolation::NotTrading => write!(f, "the instrument does not accept new orders"),
+        }
+    }
+}
+
+impl Error for SpecViolation {}
+
+// Decimal places of a tick or lot size, e.g. 2 for 0.01000000
+fn decimal_places(size: Decimal) -> u32 {
+    size.normalize().scale()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstrumentSpec {
+    pub exchange: Exchange,
+    // Venue spelling, e.g. "BTC/USD" or "BTCUSDT"
+    pub symbol: String,
+    pub instrument: Instrument,
+    pub tick_size: Decimal,
+    pub lot_size: Decimal,
+    // Decimal places prices and quantities are written with
+    pub price_precision: u32,
+    pub qty_precision: u32,
+    pub min_qty: Decimal,
+    // In quote currency, price times quantity
+    pub min_notional: Decimal,
+    // False when the venue only accepts cancels, or has halted the instrument
+    pub trading: bool,
+}
+
+impl InstrumentSpec {
+    // Precisions are those of the tick and lot sizes, without minimum sizes
+    pub fn new(exchange: Exchange, symbol: &str, instrument: Instrument, tick_size: Decimal, lot_size: Decimal) -> Self {
+        InstrumentSpec {
+            exchange,
+            symbol: symbol.to_string(),
+            instrument,
+            tick_size,
+            lot_size,
+            price_precision: decimal_places(tick_size),
+            qty_precision: decimal_places(lot_size),
+            min_qty: Decimal::ZERO,
+            min_notional: Decimal::ZERO,
+            trading: true,
+        }
+    }
+
+    pub fn with_precision(mut self, price_precision: u32, qty_precision: u32) -> Self {
+        self.price_precision = price_precision;
+        self.qty_precision = qty_precision;
+        self
+    }
+
+    pub fn with_min_qty(mut self, min_qty: Decimal) -> Self {
+        self.min_qty = min_qty;
+        self
+    }
+
+    pub fn with_min_notional(mut self, min_notional: Decimal) -> Self {
+        self.min_notional = min_notional;
+        self
+    }
+
+    pub fn with_trading(mut self, trading: bool) -> Self {
+        self.trading = trading;
+        self
+    }
+
+    pub fn key(&self) -> BookKey {
+        BookKey::new(self.exchange, &self.symbol)
+    }
+
+    // The price written with the instrument's precision, an error when it is off tick. A zero
+    // tick size is a disabled filter (Binance), the price is kept as written.
+    pub fn check_price(&self, price: Decimal) -> Result<Decimal, SpecViolation> {
+        if price <= Decimal::ZERO {
+            return Err(SpecViolation::NonPositivePrice(price));
+        }
+        if self.tick_size.is_zero() {
+            return Ok(price);
+        }
+        if !(price % self.tick_size).is_zero() {
+            return Err(SpecViolation::OffTick { price, tick_size: self.tick_size });
+        }
+        let mut price = price;
+        price.rescale(self.price_precision);
+        Ok(price)
+    }
+
+    // The quantity written with the instrument's precision, an error when it is off lot.
+    // Zero is valid, it removes a level. A zero lot size is a disabled filter.
+    pub fn check_qty(&self, qty: Decimal) -> Result<Decimal, SpecViolation> {
+        if qty < Decimal::ZERO {
+            return Err(SpecViolation::NegativeQty(qty));
+        }
+        if self.lot_size.is_zero() {
+            return Ok(qty);
+        }
+        if !(qty % self.lot_size).is_zero() {
+            return Err(SpecViolation::OffLot { qty, lot_size: self.lot_size });
+        }
+        let mut qty = qty;
+        qty.rescale(self.qty_precision);
+        Ok(qty)
+    }
+
+    pub fn quantize_level(&self, level: &PriceLevel) -> Result<PriceLevel, SpecViolation> {
+        Ok(PriceLevel::new(self.check_price(level.price())?, self.check_qty(level.quantity())?, level.quote_type()))
+    }
+
+    // Levels as the venue wrote them, whose scale its checksum may depend on
+    pub fn check_update(&self, update: &OrderBookUpdate) -> Result<(), SpecViolation> {
+        update.price_levels().iter().try_for_each(|level| {
+            self.check_price(level.price())?;
+            self.check_qty(level.quantity())?;
+            Ok(())
+        })
+    }
+
+    // Every level of the update, rejected as a whole when one of them is invalid
+    pub fn quantize_update(&self, update: &OrderBookUpdate) -> Result<OrderBookUpdate, SpecViolation> {
+        let levels = update.price_levels().iter()
+            .map(|level| self.quantize_level(level))
+            .collect::<Result<Vec<_>, _>>()?;
+        let quantized = match update.is_snapshot() {
+            true => OrderBookUpdate::snapshot(levels),
+            false => OrderBookUpdate::new(levels),
+        };
+        // The book checks buffered deltas against the snapshot with it
+        Ok(match update.sequence() {
+            Some((first, last)) => quantized.with_sequence(first, last),
+            None => quantized,
+        })
+    }
+
+    // Buys are rounded down to the tick and sells up, quantities down to the lot. An order
+    // left below the minimum quantity or notional is rejected, as is any order of an instrument
+    // not trading. Market orders have no price to check the notional against.
+    pub fn quantize_order(&self, order: &NewOrder) -> Result<NewOrder, SpecViolation> {
+        if !self.trading {
+            return Err(SpecViolation::NotTrading);
+        }
+        let round_price = |price: Price| -> Result<Price, SpecViolation> {
+            if self.tick_size.is_zero() {
+                return Ok(Price(self.check_price(price.value())?));
+            }
+            let ticks = price.value() / self.tick_size;
+            let ticks = match order.side {
+                BuySell::Buy => ticks.floor(),
+                BuySell::Sell => ticks.ceil(),
+            };
+            Ok(Price(self.check_price(ticks * self.tick_size)?))
+        };
+        let order_type = match order.order_type {
+            OrderType::Limit { price, time_in_force } => OrderType::Limit { price: round_price(price)?, time_in_force },
+            OrderType::PostOnly { price } => OrderType::PostOnly { price: round_price(price)? },
+            OrderType::Market => OrderType::Market,
+        };
+        let qty = match self.lot_size.is_zero() {
+            true => self.check_qty(order.qty.value())?,
+            false => self.check_qty((order.qty.value() / self.lot_size).floor() * self.lot_size)?,
+        };
+        if qty.is_zero() || qty < self.min_qty {
+            return Err(SpecViolation::BelowMinQty { qty, min_qty: self.min_qty });
+        }
+        if let OrderType::Limit { price, .. } | OrderType::PostOnly { price } = order_type {
+            let notional = price.value() * qty;
+            if notional < self.min_notional {
+                return Err(SpecViolation::BelowMinNotional { notional, min_notional: self.min_notional });
+            }
+        }
+        Ok(NewOrder { qty: Qty(qty), order_type, ..*order })
+    }
+}
+
+impl fmt::Display for InstrumentSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}) tick {} lot {} min qty {} min notional {}",
+               self.key(), self.instrument, self.tick_size, self.lot_size, self.min_qty, self.min_notional)
+    }
+}
+
+/// Specs keyed by (venue, symbol), in venue then symbol order.
+#[derive(Debug, Default)]
+pub struct InstrumentRegistry {
+    specs: BTreeMap<BookKey, InstrumentSpec>,
+}
+
+impl InstrumentRegistry {
+    pub fn new() -> Self {
+        InstrumentRegistry { specs: BTreeMap::new() }
+    }
+
+    // Replaces the spec of the symbol, if any
+    pub fn insert(&mut self, spec: InstrumentSpec) -> Option<InstrumentSpec> {
+        self.specs.insert(spec.key(), spec)
+    }
+
+    // Returns how many specs were added or replaced
+    pub fn extend(&mut self, specs: impl IntoIterator<Item = InstrumentSpec>) -> usize {
+        let mut count = 0;
+        for spec in specs {
+            self.insert(spec);
+            count += 1;
+        }
+        count
+    }
+
+    pub fn len(&self) -> usize {
+        self.specs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    // By the venue's spelling, or another spelling of the same instrument on the venue, e.g.
+    // the "XBT/USD" of Kraken's v1 feeds for the "BTC/USD" of its instrument channel
+    pub fn get(&self, exchange: Exchange, symbol: &str) -> Option<&InstrumentSpec> {
+        if let Some(spec) = self.specs.get(&BookKey::new(exchange, symbol)) {
+            return Some(spec);
+        }
+        let instrument = Instrument::from_venue(exchange, symbol)?;
+        self.specs.values().find(|spec| spec.exchange == exchange && spec.instrument == instrument)
+    }
+
+    // Specs of the instrument across venues
+    pub fn by_instrument<'a>(&'a self, instrument: &'a Instrument) -> impl Iterator<Item = &'a InstrumentSpec> {
+        self.specs.values().filter(move |spec| spec.instrument == *instrument)
+    }
+
+    // Updates of symbols without a spec are valid
+    pub fn check_update(&self, exchange: Exchange, symbol: &str, update: &OrderBookUpdate) -> Result<(), SpecViolation> {
+        match self.get(exchange, symbol) {
+            Some(spec) => spec.check_update(update),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::matching_engine::TimeInForce;
+    use crate::models::order_book::QuoteType;
+
+    fn spec() -> InstrumentSpec {
+        InstrumentSpec::new(Exchange::Binance, "BTCUSDT", Instrument::new("BTC", "USDT"), dec!(0.01000000), dec!(0.00001000))
+            .with_min_qty(dec!(0.00001))
+            .with_min_notional(dec!(5))
+    }
+
+    #[test]
+    fn test_quantize_level() {
+        let spec = spec();
+        assert_eq!((spec.price_precision, spec.qty_precision), (2, 5));
+
+        let level = spec.quantize_level(&PriceLevel::new(dec!(64000.10000000), dec!(0.50000000), QuoteType::BID)).unwrap();
+        assert_eq!((level.price().to_string(), level.quantity().to_string()), ("64000.10".to_string(), "0.50000".to_string()));
+        assert!(spec.quantize_level(&PriceLevel::new(dec!(64000.1), dec!(0), QuoteType::ASK)).is_ok());
+
+        assert_eq!(spec.quantize_level(&PriceLevel::new(dec!(64000.105), dec!(1), QuoteType::BID)).unwrap_err(),
+                   SpecViolation::OffTick { price: dec!(64000.105), tick_size: dec!(0.01) });
+        assert_eq!(spec.quantize_level(&PriceLevel::new(dec!(64000.1), dec!(0.000001), QuoteType::BID)).unwrap_err(),
+                   SpecViolation::OffLot { qty: dec!(0.000001), lot_size: dec!(0.00001) });
+        assert_eq!(spec.check_price(dec!(0)), Err(SpecViolation::NonPositivePrice(dec!(0))));
+
+        let update = OrderBookUpdate::new(vec![
+            PriceLevel::new(dec!(64000.1), dec!(1), QuoteType::BID),
+            PriceLevel::new(dec!(64000.111), dec!(1), QuoteType::ASK),
+        ]);
+        assert!(spec.quantize_update(&update).is_err());
+        assert_eq!(spec.check_update(&update), Err(SpecViolation::OffTick { price: dec!(64000.111), tick_size: dec!(0.01) }));
+
+        let delta = OrderBookUpdate::new(vec![PriceLevel::new(dec!(64000.1), dec!(1), QuoteType::BID)]).with_sequence(157, 160);
+        let quantized = spec.quantize_update(&delta).unwrap();
+        assert_eq!((quantized.is_snapshot(), quantized.sequence()), (false, Some((157, 160))));
+        assert_eq!(spec.quantize_update(&OrderBookUpdate::snapshot(vec![])).unwrap().sequence(), None);
+    }
+
+    #[test]
+    fn test_quantize_order() {
+        let spec = spec();
+        let buy = spec.quantize_order(&NewOrder::limit(1, BuySell::Buy, Price(dec!(64000.129)), Qty(dec!(0.123456)))).unwrap();
+        assert_eq!(buy.order_type, OrderType::Limit { price: Price(dec!(64000.12)), time_in_force: TimeInForce::GTC });
+        assert_eq!(buy.qty, Qty(dec!(0.12345)));
+
+        let sell = spec.quantize_order(&NewOrder::post_only(2, BuySell::Sell, Price(dec!(64000.121)), Qty(dec!(1)))).unwrap();
+        assert_eq!(sell.order_type, OrderType::PostOnly { price: Price(dec!(64000.13)) });
+
+        assert_eq!(spec.quantize_order(&NewOrder::market(3, BuySell::Buy, Qty(dec!(0.000009)))),
+                   Err(SpecViolation::BelowMinQty { qty: dec!(0), min_qty: dec!(0.00001) }));
+        assert_eq!(spec.quantize_order(&NewOrder::limit(4, BuySell::Buy, Price(dec!(64000)), Qty(dec!(0.00007)))),
+                   Err(SpecViolation::BelowMinNotional { notional: dec!(4.48), min_notional: dec!(5) }));
+        // A market order has no notional to check
+        assert!(spec.quantize_order(&NewOrder::market(5, BuySell::Sell, Qty(dec!(0.00007)))).is_ok());
+    }
+
+    #[test]
+    fn test_order_of_instrument_not_trading() {
+        let order = NewOrder::limit(1, BuySell::Buy, Price(dec!(3000)), Qty(dec!(1)));
+        let specs = crate::models::kraken::instrument::parse_instruments(
+            include_str!("../../tests/fixtures/kraken/instrument_snapshot.json"));
+        let eth = specs.iter().find(|spec| spec.symbol == "ETH/USD").unwrap();
+        assert_eq!(eth.quantize_order(&order), Err(SpecViolation::NotTrading));
+
+        let halted = spec().with_trading(false);
+        assert_eq!(halted.quantize_order(&NewOrder::market(2, BuySell::Sell, Qty(dec!(1)))), Err(SpecViolation::NotTrading));
+        // Levels of the venue are still checked
+        assert!(halted.check_price(dec!(64000.1)).is_ok());
+    }
+
+    #[test]
+    fn test_disabled_filters() {
+        // Binance publishes a zero tick or step size when the filter is disabled
+        let spec = InstrumentSpec::new(Exchange::Binance, "BTCUSDT", Instrument::new("BTC", "USDT"), dec!(0), dec!(0));
+        assert_eq!(spec.check_price(dec!(64000.123)), Ok(dec!(64000.123)));
+        assert_eq!(spec.check_qty(dec!(0.1234567)), Ok(dec!(0.1234567)));
+        assert_eq!(spec.check_price(dec!(0)), Err(SpecViolation::NonPositivePrice(dec!(0))));
+
+        let order = spec.quantize_order(&NewOrder::limit(1, BuySell::Buy, Price(dec!(64000.129)), Qty(dec!(0.123456)))).unwrap();
+        assert_eq!(order.order_type, OrderType::Limit { price: Price(dec!(64000.129)), time_in_force: TimeInForce::GTC });
+        assert_eq!(order.qty, Qty(dec!(0.123456)));
+    }
+
+    #[test]
+    fn test_registry_lookup() {
+        let mut registry = InstrumentRegistry::new();
+        registry.insert(spec());
+        registry.insert(InstrumentSpec::new(Exchange::Kraken, "BTC/USD", Instrument::new("BTC", "USD"), dec!(0.1), dec!(0.00000001)));
+        assert_eq!(registry.get(Exchange::Kraken, "XBT/USD").unwrap().symbol, "BTC/USD");
+        assert!(registry.get(Exchange::Binance, "BTCUSDC").is_none());
+        assert_eq!(registry.by_instrument(&Instrument::new("BTC", "USDT")).count(), 1);
+
+        let update = OrderBookUpdate::snapshot(vec![PriceLevel::new(dec!(64000.15), dec!(1), QuoteType::BID)]);
+        assert!(registry.check_update(Exchange::Kraken, "XBT/USD", &update).is_err());
+        assert!(registry.check_update(Exchange::Okx, "BTC-USDT", &update).is_ok());
+    }
+}
@@ -1,347 +1,451 @@
-//
-//
-// use std::collections::HashMap;
-// use std::error::Error;
-// use serde::{Deserialize, Serialize};
-// use std::{fmt, result};
-// use serde_json::Result;
-//
-// // use crate::messages::IncomingMsg;
-//
-// use crate::models::kraken::book::parse::parse_message;
-//
-//
-// #[derive(Debug)]
-// struct ParseError(String);
-//
-// impl fmt::Display for ParseError {
-//     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-//         write!(f, "{}", self.0)
-//     }
-// }
-//
-// impl Error for ParseError {}
-//
-//
-// use serde_json::{Error as SerdeError, Value};
-// #[derive(Debug)]
-// pub enum MyError {
-//     ParseError(String),
-//     SerdeError(SerdeError),
-// }
-//
-//
-//
-// impl fmt::Display for MyError {
-//     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-//         match self {
-//             MyError::ParseError(e) => write!(f, "Parse error: {}", e),
-//             MyError::SerdeError(e) => write!(f, "Serde JSON error: {}", e),
-//         }
-//     }
-// }
-//
-// // // Implement From for ParseError and SerdeError to convert them into MyError
-// // impl From<ParseError> for MyError {
-// //     fn from(error: ParseError) -> Self {
-// //         MyError::ParseError(error)
-// //     }
-// // }
-//
-// impl From<SerdeError> for MyError {
-//     fn from(error: SerdeError) -> Self {
-//         MyError::SerdeError(error)
-//     }
-// }
-//
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub struct PriceLevel {
-//     pub price: String,
-//     pub volume: String,
-//     pub timestamp: String,
-// }
-//
-// impl fmt::Display for PriceLevel {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         write!(f, "Price: {}, Volume: {}, Timestamp: {}", self.price, self.volume, self.timestamp)
-//     }
-// }
-//
-//
-//
-// // #[derive(Debug, Clone, Serialize, Deserialize)]
-// // pub struct OrderBookUpdate {
-// //     #[serde(rename = "a")]
-// //     asks: Option<Vec<PriceLevel>>,
-// //
-// //     #[serde(rename = "b")]
-// //     bids: Option<Vec<PriceLevel>>,
-// //
-// //     c: Option<String>, // Assuming 'c' is present in updates for checksum.
-// // }
-//
-// pub struct OrderBookUpdate{
-//     updates: Vec<PriceLevel>
-// }
-//
-// impl OrderBookUpdate {
-//     pub fn new(updates: Vec<PriceLevel>) -> Self {
-//         OrderBookUpdate{ updates }
-//     }
-// }
-//
-//
-// #[derive(Debug, Clone)]
-// pub struct OrderBook {
-//     pub bids: HashMap<String, PriceLevel>,
-//     pub asks: HashMap<String, PriceLevel>,
-// }
-//
-// impl fmt::Display for OrderBook {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         writeln!(f, "OrderBook ",)?;
-//         writeln!(f, "Bids: ")?;
-//         for (key, bid) in &self.bids {
-//             write!(f, "{}: {} ", key, bid)?;
-//         }
-//         writeln!(f, "Asks: ")?;
-//         for (key, ask) in &self.asks {
-//             write!(f, "{}: {} ", key, ask)?;
-//         }
-//         Ok(())
-//     }
-// }
-//
-//
-// impl OrderBook {
-//     pub fn new() -> OrderBook {
-//         OrderBook {
-//             bids: HashMap::new(),
-//             asks: HashMap::new(),
-//         }
-//     }
-//
-//     pub fn from_snapshot(snapshot: String){
-//         let initial_update = snapshot.replace("bs", "b");
-//         let order_book_update = parse_message(&initial_update).unwrap();
-//         OrderBook::init(order_book_update);
-//     }
-//
-//     pub fn init(update: OrderBookUpdate) -> result::Result<OrderBook, MyError>{
-//         let mut initialized = OrderBook::new();
-//
-//         match update.asks {
-//             Some(asks) => {
-//                 asks.iter().for_each(|ask| {
-//                     initialized.update_level(ask.clone(), false);
-//                 });
-//             },
-//             None => return Err(MyError::ParseError("MissingAsks".to_string())),
-//         }
-//
-//         match update.bids {
-//             Some(bids) => {
-//                 bids.iter().for_each(|bid| {
-//                     initialized.update_level(bid.clone(), true);
-//                 });
-//             },
-//             None => return Err(MyError::ParseError("MissingBids".to_string())),
-//         }
-//
-//         Ok(initialized)
-//     }
-//
-//
-//
-//     fn update_from_message(&mut self, message: String) {
-//         let update: serde_json::Result<OrderBookUpdate> = serde_json::from_str(&message);
-//
-//         match update {
-//             Ok(update) => {
-//                 if let Some(asks) = update.asks {
-//                     for ask in asks {
-//                         self.update_level(ask, false);
-//                     }
-//                 }
-//                 if let Some(bids) = update.bids {
-//                     for bid in bids {
-//                         self.update_level(bid, true);
-//                     }
-//                 }
-//             },
-//             Err(e) => println!("Error parsing update message: {}", e),
-//         }
-//     }
-//
-//
-//     pub fn update_level(&mut self, level: PriceLevel, is_bid: bool) {
-//         let price = level.price.parse::<f64>().expect("Error parsing price to f64");
-//         let volume = level.volume.parse::<f64>().expect("Error parsing volume to f64");
-//         let timestamp = level.timestamp.parse::<f64>().expect("Error parsing timestamp to f64");
-//
-//         let level_key = format!("{:.10}", price);
-//
-//         if is_bid {
-//             if volume == 0.0 {
-//                 self.bids.remove(&level_key);
-//             } else {
-//                 self.bids.insert(level_key, level);
-//             }
-//         } else {
-//             if volume == 0.0 {
-//                 self.asks.remove(&level_key);
-//             } else {
-//                 self.asks.insert(level_key, level);
-//             }
-//         }
-//     }
-//
-// }
-//
-// pub mod parse {
-//     use super::*;
-//     use regex::Regex;
-//
-//     // use std::error::Error;
-//     use std::result;
-//     use futures::TryFutureExt;
-//
-//
-//
-//
-//     pub fn parse_message(msg: &String) -> result::Result<OrderBookUpdate, MyError> {
-//         let re = Regex::new(r#"\[\s*(\d+)\s*,\s*(\{.*?\})\s*,\s*"(.*?)"\s*,\s*"(.*?)"\s*\]"#).unwrap();
-//
-//         match re.captures(msg) {
-//             Some(caps) => {
-//                 let channel_id = &caps[1];
-//                 let payload = &caps[2];
-//                 let channel_name = &caps[3];
-//                 let pair = &caps[4];
-//                 println!("payload: {}", payload);
-//
-//                 let parsed_json: Value = serde_json::from_str(payload)?;
-//
-//                 if let Some((first_key, _first_value)) = parsed_json.as_object().and_then(|obj| obj.iter().next()) {
-//                     if first_key == "as" {   // initial orderbook
-//                         let initial_update = payload.replace("bs", "b");
-//                         serde_json::from_str::<OrderBookUpdate>(&initial_update).map_err(MyError::from)
-//
-//                     } else if first_key == "a" {
-//                         serde_json::from_str::<OrderBookUpdate>(payload).map_err(MyError::from)
-//                     } else {  Err(MyError::ParseError("cant parse".to_string()))  }
-//                 } else {  Err(MyError::ParseError("cant parse".to_string()))  }
-//
-//
-//             },
-//             None => {
-//                 let error_msg = "Message format does not match expected pattern".to_string();
-//                 Err(MyError::ParseError(error_msg))
-//             }
-//         }
-//
-//     }
-//
-//
-//     ///
-//     ///
-//     /// # Arguments
-//     ///
-//     /// * `json_str`:
-//     ///
-//     /// returns: Result<<unknown>, Error>
-//     ///
-//     /// # Examples
-//     ///
-//     /// ```
-//     ///
-//     /// ```
-//     fn parse_json(json_str: &str) -> Result<OrderBookUpdate> {
-//         serde_json::from_str::<OrderBookUpdate>(json_str)
-//     }
-// }
-//
-// // #[derive(Debug, Clone)]
-// // struct IncomingMsg {
-// //     as: Option<Vec<Vec<String>>>,
-// //     bs: Option<Vec<Vec<String>>>,
-// //     // Additional fields as needed
-// // }
-// //
-// // // Example usage
-// // fn main() {
-// //     let mut book = OrderBook::new("book-10".to_string(), "XBT/USD".to_string());
-// //
-// //     // Example message update
-// //     let msg = IncomingMsg {
-// //         as: Some(vec![vec!["51612.20000".to_string(), "5.51312925".to_string(), "1708447713.035616".to_string()]]),
-// //         bs: None,
-// //         // fill in other fields as needed
-// //     };
-// //
-// //     book.update_from_message(msg);
-// //     println!("{:?}", book);
-// // }
-//
-//
-//
-// // Snapshot payload
-// //
-// // Name	Type	  Description
-// // channelID	  integer	Channel ID of subscription - deprecated, use channelName and pair
-// // (Anonymous)    object
-// // as	array	  Array of price levels, ascending from best ask
-// // Array	array	Anonymous array of level values
-// // price	decimal	Price level
-// // volume	decimal	Price level volume, for updates volume = 0 for level removal/deletion
-// // timestamp	decimal	Price level last updated, seconds since epoch
-// // bs	array	Array of price levels, descending from best bid
-// // Array	array	Anonymous array of level values
-// // price	decimal	Price level
-// // volume	decimal	Price level volume, for updates volume = 0 for level removal/deletion
-// // timestamp	decimal	Price level last updated, seconds since epoch
-// // channelName	string	Channel Name of subscription
-// // pair	string	Asset pair
-// // Example of snapshot payload
-// //
-// // [
-// //   0,
-// //   {
-// //     "as": [
-// //       [
-// //         "5541.30000",
-// //         "2.50700000",
-// //         "1534614248.123678"
-// //       ],
-// //       [
-// //         "5541.80000",
-// //         "0.33000000",
-// //         "1534614098.345543"
-// //       ],
-// //       [
-// //         "5542.70000",
-// //         "0.64700000",
-// //         "1534614244.654432"
-// //       ]
-// //     ],
-// //     "bs": [
-// //       [
-// //         "5541.20000",
-// //         "1.52900000",
-// //         "1534614248.765567"
-// //       ],
-// //       [
-// //         "5539.90000",
-// //         "0.30000000",
-// //         "1534614241.769870"
-// //       ],
-// //       [
-// //         "5539.50000",
-// //         "5.00000000",
-// //         "1534613831.243486"
-// //       ]
-// //     ]
-// //   },
-// //   "book-100",
-// //   "XBT/USD"
-// // ]
\ No newline at end of file
+// Kraken websocket v1 (wss://ws.kraken.com) book
+//
+// Snapshot payload: [channelID, {"as": [...], "bs": [...]}, channelName, pair]
+// Update payload:   [channelID, {"a": [...]}, {"b": [...], "c": checksum}, channelName, pair]
+// with one or two objects for updates and the checksum in the last one.
+//
+// Each level is [price, volume, timestamp], updates may add a 4th "r" element for levels
+// republished because they came back into the subscribed depth. A zero volume removes the
+// level.
+//
+// https://docs.kraken.com/websockets/#message-book
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use rust_decimal::Decimal;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+
+use crate::models::kraken::checksum;
+use crate::models::order_book::{self, ChecksumMismatch, OrderBookUpdate, QuoteType};
+
+const REPUBLISH: &str = "r";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceLevel {
+    pub price: String,
+    pub volume: String,
+    pub timestamp: String,
+}
+
+impl fmt::Display for PriceLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Price: {}, Volume: {}, Timestamp: {}", self.price, self.volume, self.timestamp)
+    }
+}
+
+/// A level of a frame, prices and volumes are checked to be decimals when parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct LevelUpdate {
+    pub level: PriceLevel,
+    pub republish: bool,
+}
+
+impl TryFrom<Vec<String>> for LevelUpdate {
+    type Error = String;
+
+    fn try_from(fields: Vec<String>) -> Result<Self, Self::Error> {
+        let mut fields = fields.into_iter();
+        let (price, volume, timestamp) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(price), Some(volume), Some(timestamp)) => (price, volume, timestamp),
+            _ => return Err("a level needs a price, a volume and a timestamp".to_string()),
+        };
+        for value in [&price, &volume] {
+            Decimal::from_str(value).map_err(|e| format!("{}: {}", value, e))?;
+        }
+        let republish = match fields.next() {
+            None => false,
+            Some(flag) if flag == REPUBLISH => true,
+            Some(flag) => return Err(format!("unknown level flag {}", flag)),
+        };
+        Ok(LevelUpdate { level: PriceLevel { price, volume, timestamp }, republish })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Payload {
+    #[serde(rename = "as")]
+    snapshot_asks: Option<Vec<LevelUpdate>>,
+    #[serde(rename = "bs")]
+    snapshot_bids: Option<Vec<LevelUpdate>>,
+    #[serde(rename = "a")]
+    asks: Option<Vec<LevelUpdate>>,
+    #[serde(rename = "b")]
+    bids: Option<Vec<LevelUpdate>>,
+    #[serde(rename = "c")]
+    checksum: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Element {
+    Payload(Payload),
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookMessage {
+    Snapshot { asks: Vec<LevelUpdate>, bids: Vec<LevelUpdate> },
+    Update { asks: Vec<LevelUpdate>, bids: Vec<LevelUpdate>, checksum: Option<u32> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookFrame {
+    pub channel_id: u64,
+    pub message: BookMessage,
+    pub channel_name: String,
+    pub pair: String,
+}
+
+impl<'de> Deserialize<'de> for BookFrame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FrameVisitor;
+
+        impl<'de> Visitor<'de> for FrameVisitor {
+            type Value = BookFrame;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "[channelID, payload, (payload,) channelName, pair]")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BookFrame, A::Error> {
+                let channel_id: u64 = seq.next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let mut payloads = Vec::new();
+                let mut names = Vec::new();
+                while let Some(element) = seq.next_element::<Element>()? {
+                    match element {
+                        Element::Payload(payload) if names.is_empty() => payloads.push(payload),
+                        Element::Payload(_) => return Err(de::Error::custom("payload after the channel name")),
+                        Element::Name(name) => names.push(name),
+                    }
+                }
+                let (channel_name, pair) = match (payloads.len(), <[String; 2]>::try_from(names)) {
+                    (1 | 2, Ok([channel_name, pair])) => (channel_name, pair),
+                    _ => return Err(de::Error::custom("expected one or two payloads, a channel name and a pair")),
+                };
+
+                let is_snapshot = payloads.iter().any(|p| p.snapshot_asks.is_some() || p.snapshot_bids.is_some());
+                let mut merged = Payload::default();
+                for payload in payloads {
+                    let levels = [
+                        (payload.snapshot_asks, &mut merged.snapshot_asks),
+                        (payload.snapshot_bids, &mut merged.snapshot_bids),
+                        (payload.asks, &mut merged.asks),
+                        (payload.bids, &mut merged.bids),
+                    ];
+                    for (levels, merged) in levels {
+                        if let Some(levels) = levels {
+                            merged.get_or_insert_with(Vec::new).extend(levels);
+                        }
+                    }
+                    merged.checksum = payload.checksum.or(merged.checksum);
+                }
+
+                let message = if is_snapshot {
+                    BookMessage::Snapshot {
+                        asks: merged.snapshot_asks.unwrap_or_default(),
+                        bids: merged.snapshot_bids.unwrap_or_default(),
+                    }
+                } else {
+                    let checksum = match merged.checksum {
+                        Some(checksum) => Some(checksum.parse::<u32>().map_err(de::Error::custom)?),
+                        None => None,
+                    };
+                    BookMessage::Update {
+                        asks: merged.asks.unwrap_or_default(),
+                        bids: merged.bids.unwrap_or_default(),
+                        checksum,
+                    }
+                };
+                Ok(BookFrame { channel_id, message, channel_name, pair })
+            }
+        }
+
+        deserializer.deserialize_seq(FrameVisitor)
+    }
+}
+
+// Book frames only, events ({"event": "heartbeat"}, ...) and other channels are errors
+pub fn parse_frame(text: &str) -> serde_json::Result<BookFrame> {
+    serde_json::from_str(text)
+}
+
+// "book-10" subscribes to 10 levels per side
+pub fn channel_depth(channel_name: &str) -> Option<usize> {
+    channel_name.strip_prefix("book-")?.parse().ok()
+}
+
+fn to_price_levels(levels: &[LevelUpdate], quote_type: QuoteType, price_levels: &mut Vec<order_book::PriceLevel>) {
+    for update in levels {
+        let price = Decimal::from_str(&update.level.price);
+        let volume = Decimal::from_str(&update.level.volume);
+        if let (Ok(price), Ok(volume)) = (price, volume) {
+            price_levels.push(order_book::PriceLevel::new(price, volume, quote_type));
+        }
+    }
+}
+
+impl BookFrame {
+    // Normalized update for order_book::OrderBook
+    pub fn to_update(&self) -> OrderBookUpdate {
+        let mut price_levels = Vec::new();
+        match &self.message {
+            BookMessage::Snapshot { asks, bids } => {
+                to_price_levels(asks, QuoteType::ASK, &mut price_levels);
+                to_price_levels(bids, QuoteType::BID, &mut price_levels);
+                OrderBookUpdate::snapshot(price_levels)
+            },
+            BookMessage::Update { asks, bids, .. } => {
+                to_price_levels(asks, QuoteType::ASK, &mut price_levels);
+                to_price_levels(bids, QuoteType::BID, &mut price_levels);
+                OrderBookUpdate::new(price_levels)
+            },
+        }
+    }
+
+    pub fn checksum(&self) -> Option<u32> {
+        match &self.message {
+            BookMessage::Update { checksum, .. } => *checksum,
+            BookMessage::Snapshot { .. } => None,
+        }
+    }
+}
+
+/// One side of the book keyed by exact decimal price, "10000", "10000.0" and "10000.00000"
+/// are the same level.
+#[derive(Debug, Clone, Default)]
+pub struct BookSide {
+    levels: BTreeMap<Decimal, PriceLevel>,
+}
+
+impl BookSide {
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    // False for a string that is not a price
+    pub fn contains_key(&self, price: &str) -> bool {
+        self.get(price).is_some()
+    }
+
+    pub fn get(&self, price: &str) -> Option<&PriceLevel> {
+        Decimal::from_str(price).ok().and_then(|price| self.levels.get(&price))
+    }
+
+    // Ascending prices
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Decimal, &PriceLevel)> {
+        self.levels.iter()
+    }
+
+    fn clear(&mut self) {
+        self.levels.clear();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    pub bids: BookSide,
+    pub asks: BookSide,
+    pub channel_name: String,
+    pub pair: String,
+    // From the channel name, levels pushed out of it are discarded
+    depth: Option<usize>,
+}
+
+impl fmt::Display for OrderBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "OrderBook {} {}", self.channel_name, self.pair)?;
+        writeln!(f, "Bids: ")?;
+        for (key, bid) in self.bids.iter().rev() {
+            write!(f, "{}: {} ", key, bid)?;
+        }
+        writeln!(f, "Asks: ")?;
+        for (key, ask) in self.asks.iter() {
+            write!(f, "{}: {} ", key, ask)?;
+        }
+        Ok(())
+    }
+}
+
+impl OrderBook {
+    pub fn new(channel_name: String, pair: String) -> OrderBook {
+        let depth = channel_depth(&channel_name);
+        OrderBook {
+            bids: BookSide::default(),
+            asks: BookSide::default(),
+            channel_name,
+            pair,
+            depth,
+        }
+    }
+
+    // Levels with a price or a volume that is not a decimal are ignored
+    pub fn update_level(&mut self, level: PriceLevel, is_bid: bool) {
+        let (price, volume) = match (Decimal::from_str(&level.price), Decimal::from_str(&level.volume)) {
+            (Ok(price), Ok(volume)) => (price, volume),
+            _ => return,
+        };
+        let side = if is_bid { &mut self.bids } else { &mut self.asks };
+        if volume.is_zero() {
+            side.levels.remove(&price);
+        } else {
+            side.levels.insert(price, level);
+        }
+    }
+
+    fn apply_levels(&mut self, levels: &[LevelUpdate], is_bid: bool) {
+        for update in levels {
+            self.update_level(update.level.clone(), is_bid);
+        }
+    }
+
+    fn truncate(&mut self) {
+        if let Some(depth) = self.depth {
+            while self.bids.len() > depth {
+                self.bids.levels.pop_first();
+            }
+            while self.asks.len() > depth {
+                self.asks.levels.pop_last();
+            }
+        }
+    }
+
+    // Applies a frame of this book's channel, updates carrying a checksum are verified
+    pub fn apply(&mut self, frame: &BookFrame) -> Result<(), ChecksumMismatch> {
+        match &frame.message {
+            BookMessage::Snapshot { asks, bids } => {
+                self.bids.clear();
+                self.asks.clear();
+                self.apply_levels(asks, false);
+                self.apply_levels(bids, true);
+            },
+            BookMessage::Update { asks, bids, .. } => {
+                self.apply_levels(asks, false);
+                self.apply_levels(bids, true);
+            },
+        }
+        self.truncate();
+
+        match frame.checksum() {
+            Some(expected) => {
+                let computed = self.checksum();
+                if computed == expected {
+                    Ok(())
+                } else {
+                    Err(ChecksumMismatch { expected, computed })
+                }
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<&PriceLevel> {
+        self.bids.iter().next_back().map(|(_, level)| level)
+    }
+
+    pub fn best_ask(&self) -> Option<&PriceLevel> {
+        self.asks.iter().next().map(|(_, level)| level)
+    }
+
+    // CRC32 over the top 10 asks ascending then the top 10 bids descending
+    pub fn checksum(&self) -> u32 {
+        let volumes = |levels: &BTreeMap<Decimal, PriceLevel>| -> Vec<(Decimal, Decimal)> {
+            levels.iter()
+                .filter_map(|(price, level)| Decimal::from_str(&level.volume).ok().map(|volume| (*price, volume)))
+                .collect()
+        };
+        let asks = volumes(&self.asks.levels);
+        let bids = volumes(&self.bids.levels);
+        checksum::compute_exact(
+            asks.iter().map(|(price, volume)| (price, volume)),
+            bids.iter().rev().map(|(price, volume)| (price, volume)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = r#"[0,{"as":[["5541.30000","2.50700000","1534614248.123678"],["5541.80000","0.33000000","1534614098.345543"]],"bs":[["5541.20000","1.52900000","1534614248.765567"],["5539.90000","0.30000000","1534614241.769870"]]},"book-2","XBT/USD"]"#;
+
+    fn level(price: &str, volume: &str) -> PriceLevel {
+        PriceLevel { price: price.to_string(), volume: volume.to_string(), timestamp: "1534614335.345903".to_string() }
+    }
+
+    fn update(asks: &str, bids: &str, checksum: u32) -> String {
+        format!(r#"[0,{{"a":[{}]}},{{"b":[{}],"c":"{}"}},"book-2","XBT/USD"]"#, asks, bids, checksum)
+    }
+
+    #[test]
+    fn test_parse_frames() {
+        let snapshot = parse_frame(SNAPSHOT).unwrap();
+        assert_eq!((snapshot.channel_name.as_str(), snapshot.pair.as_str()), ("book-2", "XBT/USD"));
+        assert!(matches!(&snapshot.message, BookMessage::Snapshot { asks, bids } if asks.len() == 2 && bids.len() == 2));
+        assert!(snapshot.to_update().is_snapshot());
+
+        let text = r#"[1234,{"a":[["5541.30000","2.50700000","1534614248.456738","r"]],"c":"974942666"},"book-10","XBT/USD"]"#;
+        let frame = parse_frame(text).unwrap();
+        match &frame.message {
+            BookMessage::Update { asks, bids, checksum } => {
+                assert!(asks[0].republish);
+                assert!(bids.is_empty());
+                assert_eq!(*checksum, Some(974942666));
+            },
+            message => panic!("expected an update, got {:?}", message),
+        }
+
+        assert!(parse_frame(r#"{"event":"heartbeat"}"#).is_err());
+        assert!(parse_frame(r#"[0,{"a":[["abc","1","1"]]},"book-10","XBT/USD"]"#).is_err());
+        assert!(parse_frame(r#"[0,{"a":[["1","1","1","x"]]},"book-10","XBT/USD"]"#).is_err());
+    }
+
+    #[test]
+    fn test_exact_decimal_keys() {
+        let mut book = OrderBook::new("book-10".to_string(), "XBT/USD".to_string());
+        book.update_level(level("10000", "1.0"), true);
+        book.update_level(level("10000.00", "2.0"), true);
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.bids.get("10000.0").unwrap().volume, "2.0");
+        assert!(!book.bids.contains_key("not a price"));
+
+        book.update_level(level("10000.000", "0.00000000"), true);
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn test_apply_with_checksum() {
+        let mut book = OrderBook::new("book-2".to_string(), "XBT/USD".to_string());
+        book.apply(&parse_frame(SNAPSHOT).unwrap()).unwrap();
+
+        // A better bid pushes the worst one out of the 2 levels
+        let mut expected = book.clone();
+        expected.update_level(level("5541.25000", "1.00000000"), true);
+        expected.truncate();
+        let frame = update("", r#"["5541.25000","1.00000000","1534614335.345903"]"#, expected.checksum());
+        book.apply(&parse_frame(&frame).unwrap()).unwrap();
+        assert_eq!(book.bids.len(), 2);
+        assert!(!book.bids.contains_key("5539.9"));
+        assert_eq!(book.best_bid().unwrap().price, "5541.25000");
+
+        let frame = update(r#"["5541.30000","0.00000000","1534614335.345903"]"#, "", 1);
+        let mismatch = book.apply(&parse_frame(&frame).unwrap()).unwrap_err();
+        assert_eq!(mismatch.expected, 1);
+        assert_eq!(book.best_ask().unwrap().price, "5541.80000");
+    }
+
+    #[test]
+    fn test_checksum_input() {
+        let mut book = OrderBook::new("book-10".to_string(), "XBT/USD".to_string());
+        book.apply(&parse_frame(SNAPSHOT).unwrap()).unwrap();
+        let input = ["554130000", "250700000", "554180000", "33000000", "554120000", "152900000", "553990000", "30000000"].concat();
+        assert_eq!(book.checksum(), crc32fast::hash(input.as_bytes()));
+    }
+}
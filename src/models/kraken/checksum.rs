@@ -0,0 +1,113 @@
+// Kraken v2 book checksum
+//
+// The checksum is a CRC32 over the top 10 asks (ascending) followed by the top 10 bids
+// (descending). For each level the price and the quantity are formatted with the pair's
+// precision, the decimal point is removed, leading zeros are stripped and both strings
+// are concatenated.
+//
+// https://docs.kraken.com/websockets-v2/#calculate-book-checksum
+
+use rust_decimal::Decimal;
+
+pub const CHECKSUM_DEPTH: usize = 10;
+
+/// Formats a decimal the way Kraken does before feeding it to the CRC:
+/// fixed precision, no decimal point, no leading zeros.
+pub fn normalize(value: Decimal, precision: u32) -> String {
+    let mut scaled = value;
+    scaled.rescale(precision);
+    let digits: String = scaled.to_string().chars().filter(|c| *c != '.').collect();
+    let trimmed = digits.trim_start_matches('0');
+    if trimmed.is_empty() {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn push_levels<'a, I>(out: &mut String, levels: I, price_precision: u32, qty_precision: u32)
+    where
+        I: Iterator<Item = (&'a Decimal, &'a Decimal)>,
+{
+    for (price, qty) in levels.take(CHECKSUM_DEPTH) {
+        out.push_str(&normalize(*price, price_precision));
+        out.push_str(&normalize(*qty, qty_precision));
+    }
+}
+
+/// Builds the string the CRC is computed over. Asks must be iterated from the best (lowest)
+/// price, bids from the best (highest) price.
+pub fn checksum_input<'a, A, B>(asks: A, bids: B, price_precision: u32, qty_precision: u32) -> String
+    where
+        A: Iterator<Item = (&'a Decimal, &'a Decimal)>,
+        B: Iterator<Item = (&'a Decimal, &'a Decimal)>,
+{
+    let mut out = String::new();
+    push_levels(&mut out, asks, price_precision, qty_precision);
+    push_levels(&mut out, bids, price_precision, qty_precision);
+    out
+}
+
+pub fn compute<'a, A, B>(asks: A, bids: B, price_precision: u32, qty_precision: u32) -> u32
+    where
+        A: Iterator<Item = (&'a Decimal, &'a Decimal)>,
+        B: Iterator<Item = (&'a Decimal, &'a Decimal)>,
+{
+    crc32fast::hash(checksum_input(asks, bids, price_precision, qty_precision).as_bytes())
+}
+
+/// Same as `compute` for websocket v1, whose levels are strings already formatted with the
+/// pair's precision: each decimal is normalized with the scale it was parsed with.
+pub fn compute_exact<'a, A, B>(asks: A, bids: B) -> u32
+    where
+        A: Iterator<Item = (&'a Decimal, &'a Decimal)>,
+        B: Iterator<Item = (&'a Decimal, &'a Decimal)>,
+{
+    let mut input = String::new();
+    for (price, qty) in asks.take(CHECKSUM_DEPTH).chain(bids.take(CHECKSUM_DEPTH)) {
+        input.push_str(&normalize(*price, price.scale()));
+        input.push_str(&normalize(*qty, qty.scale()));
+    }
+    crc32fast::hash(input.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(dec!(45285.2), 1), "452852");
+        assert_eq!(normalize(dec!(0.00100000), 8), "100000");
+        assert_eq!(normalize(dec!(0.001), 8), "100000");
+        assert_eq!(normalize(dec!(12.5), 8), "1250000000");
+        assert_eq!(normalize(Decimal::ZERO, 8), "0");
+    }
+
+    #[test]
+    fn test_checksum_input_order() {
+        let asks = [(dec!(101.0), dec!(1)), (dec!(102.0), dec!(2))];
+        let bids = [(dec!(100.0), dec!(3)), (dec!(99.0), dec!(4))];
+        let input = checksum_input(
+            asks.iter().map(|(p, q)| (p, q)),
+            bids.iter().map(|(p, q)| (p, q)),
+            1, 2,
+        );
+        assert_eq!(input, "101010010202001000300990400");
+    }
+
+    #[test]
+    fn test_compute_exact() {
+        let asks = [(dec!(5541.30000), dec!(2.50700000))];
+        let bids = [(dec!(5541.20000), dec!(1.52900000))];
+        let expected = crc32fast::hash(b"554130000250700000554120000152900000");
+        assert_eq!(compute_exact(asks.iter().map(|(p, q)| (p, q)), bids.iter().map(|(p, q)| (p, q))), expected);
+    }
+
+    #[test]
+    fn test_crc32() {
+        // Standard CRC-32 check value
+        assert_eq!(crc32fast::hash(b"123456789"), 0xCBF43926);
+    }
+}
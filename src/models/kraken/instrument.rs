@@ -0,0 +1,92 @@
+// Kraken websocket v2 (wss://ws.kraken.com/v2) instrument channel: assets and trading rules of
+// every pair, sent as a snapshot on subscription then as updates.
+//
+// Sizes are JSON numbers, the precisions are those of the book checksum.
+//
+// https://docs.kraken.com/api/docs/websocket-v2/instrument
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::models::instrument::Instrument;
+use crate::models::instrument_registry::InstrumentSpec;
+use crate::models::kraken::trade::Kind;
+use crate::quote::Exchange;
+
+pub const CHANNEL: &str = "instrument";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PairInfo {
+    pub symbol: String,
+    pub base: String,
+    pub quote: String,
+    // "online", "cancel_only", "post_only", "limit_only", "reduce_only", "maintenance" ...
+    pub status: String,
+    pub price_precision: u32,
+    pub price_increment: Decimal,
+    pub qty_precision: u32,
+    pub qty_increment: Decimal,
+    pub qty_min: Decimal,
+    pub cost_min: Decimal,
+}
+
+impl PairInfo {
+    pub fn to_spec(&self) -> InstrumentSpec {
+        InstrumentSpec::new(Exchange::Kraken, &self.symbol, Instrument::new(&self.base, &self.quote),
+                            self.price_increment, self.qty_increment)
+            .with_precision(self.price_precision, self.qty_precision)
+            .with_min_qty(self.qty_min)
+            .with_min_notional(self.cost_min)
+            .with_trading(self.status == "online")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InstrumentData {
+    pub pairs: Vec<PairInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InstrumentMessage {
+    pub channel: String,
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    pub data: InstrumentData,
+}
+
+// Instrument messages only, anything else is None
+pub fn parse_instrument_message(text: &str) -> Option<InstrumentMessage> {
+    serde_json::from_str::<InstrumentMessage>(text).ok()
+        .filter(|message| message.channel == CHANNEL)
+}
+
+pub fn parse_instruments(text: &str) -> Vec<InstrumentSpec> {
+    parse_instrument_message(text)
+        .map(|message| message.data.pairs.iter().map(PairInfo::to_spec).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const SNAPSHOT: &str = include_str!("../../../tests/fixtures/kraken/instrument_snapshot.json");
+
+    #[test]
+    fn test_parse_instruments() {
+        assert_eq!(parse_instrument_message(SNAPSHOT).unwrap().kind, Kind::Snapshot);
+        let specs = parse_instruments(SNAPSHOT);
+        assert_eq!(specs.len(), 3);
+
+        let btc = &specs[0];
+        assert_eq!((btc.symbol.as_str(), btc.instrument.to_string()), ("BTC/USD", "BTC/USD".to_string()));
+        assert_eq!((btc.tick_size, btc.lot_size), (dec!(0.1), dec!(0.00000001)));
+        assert_eq!((btc.price_precision, btc.qty_precision), (1, 8));
+        assert_eq!((btc.min_qty, btc.min_notional), (dec!(0.0001), dec!(0.5)));
+        assert!(btc.trading);
+        assert!(!specs[2].trading);
+
+        assert!(parse_instruments(r#"{"channel":"heartbeat"}"#).is_empty());
+    }
+}
@@ -1,2 +1,6 @@
 pub mod book;
 pub mod translate;
+pub mod checksum;
+pub mod trade;
+pub mod ticker;
+pub mod instrument;
@@ -0,0 +1,76 @@
+// Kraken websocket v1 (wss://ws.kraken.com) ticker channel
+//
+// [channelID, {"a": [price, wholeLotVolume, lotVolume], "b": [...], "c": ..., ...}, "ticker", pair]
+// The lot volume is the quantity at the best level.
+//
+// https://docs.kraken.com/websockets/#message-ticker
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::models::top_of_book::TopOfBook;
+use crate::quote::Exchange;
+
+pub const CHANNEL: &str = "ticker";
+
+// [price, whole lot volume, lot volume]
+pub type BestLevel = (Decimal, u64, Decimal);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TickerPayload {
+    #[serde(rename = "a")]
+    pub ask: BestLevel,
+    #[serde(rename = "b")]
+    pub bid: BestLevel,
+    // Last trade [price, lot volume]
+    #[serde(rename = "c")]
+    pub close: (Decimal, Decimal),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TickerFrame(pub u64, pub TickerPayload, pub String, pub String);
+
+impl TickerFrame {
+    pub fn pair(&self) -> &str {
+        &self.3
+    }
+
+    pub fn to_top_of_book(&self) -> TopOfBook {
+        let TickerPayload { ask, bid, .. } = &self.1;
+        TopOfBook {
+            exchange: Exchange::Kraken,
+            symbol: self.pair().to_string(),
+            bid_price: bid.0,
+            bid_qty: bid.2,
+            ask_price: ask.0,
+            ask_qty: ask.2,
+        }
+    }
+}
+
+// Ticker frames only, anything else is None
+pub fn parse_ticker(text: &str) -> Option<TickerFrame> {
+    serde_json::from_str::<TickerFrame>(text).ok()
+        .filter(|frame| frame.2 == CHANNEL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const TICKER: &str = include_str!("../../../tests/fixtures/kraken/ticker.json");
+
+    #[test]
+    fn test_parse_ticker() {
+        let frame = parse_ticker(TICKER).unwrap();
+        assert_eq!(frame.1.close, (dec!(64010.1), dec!(0.3)));
+        let top = frame.to_top_of_book();
+        assert_eq!((top.exchange, top.symbol.as_str()), (Exchange::Kraken, "XBT/USD"));
+        assert_eq!((top.bid_price, top.bid_qty), (dec!(64009.95), dec!(1.1)));
+        assert_eq!((top.ask_price, top.ask_qty), (dec!(64010.4), dec!(0.3)));
+
+        let book = r#"[336,{"a":[["64010.10000","0.00000000","1712000000.200000"]],"c":"967950501"},"book-10","XBT/USD"]"#;
+        assert!(parse_ticker(book).is_none());
+    }
+}
@@ -0,0 +1,105 @@
+// Kraken websocket v2 (wss://ws.kraken.com/v2) trade channel
+//
+// Prices and quantities are JSON numbers, a snapshot carries the last trades of the symbol.
+//
+// https://docs.kraken.com/api/docs/websocket-v2/trade
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::messages::parse_rfc3339_ns;
+use crate::models::trade::{Aggressor, Trade, TradeId};
+use crate::quote::Exchange;
+
+pub const CHANNEL: &str = "trade";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Snapshot,
+    Update,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TradeData {
+    pub symbol: String,
+    // Side of the taker
+    pub side: Side,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub ord_type: String,
+    pub trade_id: u64,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TradeMessage {
+    pub channel: String,
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    pub data: Vec<TradeData>,
+}
+
+// Trade channel messages only, anything else is None
+pub fn parse_trade_message(text: &str) -> Option<TradeMessage> {
+    serde_json::from_str::<TradeMessage>(text).ok()
+        .filter(|message| message.channel == CHANNEL)
+}
+
+impl TradeData {
+    // None when the timestamp is not RFC 3339
+    pub fn to_trade(&self, received_ns: u64) -> Option<Trade> {
+        Some(Trade {
+            exchange: Exchange::Kraken,
+            symbol: self.symbol.clone(),
+            price: self.price,
+            qty: self.qty,
+            aggressor: match self.side {
+                Side::Buy => Aggressor::Buy,
+                Side::Sell => Aggressor::Sell,
+            },
+            trade_id: TradeId::Trade(self.trade_id),
+            exchange_ns: parse_rfc3339_ns(&self.timestamp)?,
+            received_ns,
+        })
+    }
+}
+
+pub fn parse_trades(text: &str, received_ns: u64) -> Vec<Trade> {
+    parse_trade_message(text)
+        .map(|message| message.data.iter().filter_map(|data| data.to_trade(received_ns)).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const SNAPSHOT: &str = include_str!("../../../tests/fixtures/kraken/trade_snapshot.json");
+    const UPDATE: &str = include_str!("../../../tests/fixtures/kraken/trade_update.json");
+
+    #[test]
+    fn test_parse_trades() {
+        assert_eq!(parse_trade_message(SNAPSHOT).unwrap().kind, Kind::Snapshot);
+        let trades = parse_trades(UPDATE, 42);
+        assert_eq!(trades.len(), 2);
+        let trade = &trades[0];
+        assert_eq!((trade.exchange, trade.symbol.as_str()), (Exchange::Kraken, "BTC/USD"));
+        assert_eq!((trade.price, trade.qty), (dec!(64010.1), dec!(0.3)));
+        assert_eq!(trade.aggressor, Aggressor::Buy);
+        assert_eq!(trade.trade_id, TradeId::Trade(71298023));
+        assert_eq!(trade.exchange_ns, 1_712_000_000_250_000_000);
+        assert_eq!(trade.received_ns, 42);
+
+        assert_eq!(parse_trades(SNAPSHOT, 0)[0].aggressor, Aggressor::Sell);
+        assert!(parse_trades(r#"{"channel":"heartbeat"}"#, 0).is_empty());
+    }
+}
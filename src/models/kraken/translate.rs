@@ -1,42 +1,71 @@
-
 // OrderBook
-use kraken_ws_client::api::BookEvent;
+use kraken_ws_client::api::{BookData, BookEvent};
+use kraken_ws_client::types::Depth;
 use BookEvent as KrakenBookEvent;
 // use kraken_ws_client::types;
 // use rust_decimal::Decimal;
 use crate::models::order_book::{PriceLevel, QuoteType};
 
 use crate::models::order_book::OrderBookUpdate;
-pub fn from_kraken(book_event: &KrakenBookEvent) -> OrderBookUpdate {
-    let data = &book_event.data;
-    let mut updates: Vec<PriceLevel> = Vec::new();
 
-    data.iter().for_each(|data| {
-        data.asks.iter().for_each(|level_data| {
-            let price_level =
-                PriceLevel::new(
-                    level_data.price,
-                    level_data.qty,
-                    QuoteType::ASK
-                );
-            updates.push(price_level);
-        });
-        data.bids.iter().for_each(|level_data| {
-            let price_level =
-                PriceLevel::new(
-                    level_data.price,
-                    level_data.qty,
-                    QuoteType::BID);
-            updates.push(price_level);
-        });
+pub const SNAPSHOT: &str = "snapshot";
+
+fn push_levels(book_data: &BookData, updates: &mut Vec<PriceLevel>) {
+    book_data.asks.iter().for_each(|level_data| {
+        let price_level =
+            PriceLevel::new(
+                level_data.price,
+                level_data.qty,
+                QuoteType::ASK
+            );
+        updates.push(price_level);
+    });
+    book_data.bids.iter().for_each(|level_data| {
+        let price_level =
+            PriceLevel::new(
+                level_data.price,
+                level_data.qty,
+                QuoteType::BID);
+        updates.push(price_level);
+    });
+}
+
+// Number of levels per side Kraken maintains for a subscription depth
+pub fn depth_levels(depth: Depth) -> usize {
+    match depth {
+        Depth::D10 => 10,
+        Depth::D25 => 25,
+        Depth::D100 => 100,
+        Depth::D500 => 500,
+        Depth::D1000 => 1000,
     }
-    );
+}
 
+pub fn is_snapshot(book_event: &KrakenBookEvent) -> bool {
+    book_event.tx_type == SNAPSHOT
+}
 
-    data.iter().for_each(|book_data| {
+// Translates a single symbol's data, Kraken publishes one checksum per BookData
+pub fn from_book_data(book_data: &BookData, is_snapshot: bool) -> OrderBookUpdate {
+    let mut updates: Vec<PriceLevel> = Vec::new();
+    push_levels(book_data, &mut updates);
 
+    if is_snapshot {
+        OrderBookUpdate::snapshot(updates)
+    } else {
+        OrderBookUpdate::new(updates)
     }
-    );
+}
 
-    OrderBookUpdate::new(updates)
-}
\ No newline at end of file
+pub fn from_kraken(book_event: &KrakenBookEvent) -> OrderBookUpdate {
+    let data = &book_event.data;
+    let mut updates: Vec<PriceLevel> = Vec::new();
+
+    data.iter().for_each(|book_data| push_levels(book_data, &mut updates));
+
+    if is_snapshot(book_event) {
+        OrderBookUpdate::snapshot(updates)
+    } else {
+        OrderBookUpdate::new(updates)
+    }
+}
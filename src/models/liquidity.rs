@@ -0,0 +1,199 @@
+// Cost to trade a size against a book: walks the levels of the side an order would take.
+
+use rust_decimal::Decimal;
+
+use crate::models::book::Book;
+use crate::models::order_book::QuoteType;
+use crate::models::types::BuySell;
+
+pub const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+/// Size of an order, in base asset or in quote currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeSize {
+    Base(Decimal),
+    Notional(Decimal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostEstimate {
+    pub side: BuySell,
+    // Base quantity that can be filled
+    pub filled_qty: Decimal,
+    // Quote currency spent or received
+    pub notional: Decimal,
+    pub vwap: Option<Decimal>,
+    // Price of the last level touched
+    pub worst_price: Option<Decimal>,
+    // VWAP against mid, positive when the trade costs
+    pub slippage_bps: Option<Decimal>,
+    // What the book could not fill, in the unit of the requested size
+    pub unfilled: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    pub qty: Decimal,
+    pub notional: Decimal,
+}
+
+// A buy takes the asks, a sell takes the bids
+fn taken_side(side: BuySell) -> QuoteType {
+    match side {
+        BuySell::Buy => QuoteType::ASK,
+        BuySell::Sell => QuoteType::BID,
+    }
+}
+
+pub fn mid<B: Book + ?Sized>(book: &B) -> Option<Decimal> {
+    match (book.best_bid(), book.best_ask()) {
+        (Some((bid, _)), Some((ask, _))) => Some((bid + ask) / Decimal::TWO),
+        _ => None,
+    }
+}
+
+// Signed distance from mid in bps, positive when the price is worse than mid for the taker
+pub fn bps_from_mid(side: BuySell, price: Decimal, mid: Decimal) -> Decimal {
+    let diff = match side {
+        BuySell::Buy => price - mid,
+        BuySell::Sell => mid - price,
+    };
+    diff / mid * BPS
+}
+
+pub fn cost_to_trade<B: Book + ?Sized>(book: &B, side: BuySell, size: TradeSize) -> CostEstimate {
+    let mut remaining = match size {
+        TradeSize::Base(qty) => qty,
+        TradeSize::Notional(notional) => notional,
+    };
+    let mut filled_qty = Decimal::ZERO;
+    let mut notional = Decimal::ZERO;
+    let mut worst_price = None;
+
+    for (price, qty) in book.levels(taken_side(side)) {
+        if remaining <= Decimal::ZERO {
+            break;
+        }
+        // A notional is only divided by the price on the last level, whose fill spends all
+        // of it, so that no dust of an inexact division is left unfilled
+        let (take, spent) = match size {
+            TradeSize::Base(_) => {
+                let take = qty.min(remaining);
+                (take, take * price)
+            },
+            TradeSize::Notional(_) if qty * price <= remaining => (qty, qty * price),
+            TradeSize::Notional(_) => (remaining / price, remaining),
+        };
+        filled_qty += take;
+        notional += spent;
+        worst_price = Some(price);
+        remaining = match size {
+            TradeSize::Base(_) => remaining - take,
+            TradeSize::Notional(_) => remaining - spent,
+        };
+    }
+
+    let vwap = if filled_qty.is_zero() { None } else { Some(notional / filled_qty) };
+    let slippage_bps = match (vwap, mid(book)) {
+        (Some(vwap), Some(mid)) => Some(bps_from_mid(side, vwap, mid)),
+        _ => None,
+    };
+
+    CostEstimate {
+        side,
+        filled_qty,
+        notional,
+        vwap,
+        worst_price,
+        slippage_bps,
+        unfilled: remaining.max(Decimal::ZERO),
+    }
+}
+
+// Liquidity available on levels priced within `max_bps` of mid
+pub fn capacity_within_bps<B: Book + ?Sized>(book: &B, side: BuySell, max_bps: Decimal) -> Capacity {
+    let mut capacity = Capacity { qty: Decimal::ZERO, notional: Decimal::ZERO };
+    let mid = match mid(book) {
+        Some(mid) => mid,
+        None => return capacity,
+    };
+
+    for (price, qty) in book.levels(taken_side(side)) {
+        if bps_from_mid(side, price, mid) > max_bps {
+            break;
+        }
+        capacity.qty += qty;
+        capacity.notional += qty * price;
+    }
+    capacity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::book::{new_book, BookKind};
+    use crate::models::order_book::{OrderBookUpdate, PriceLevel};
+
+    fn book() -> Box<dyn Book + Send> {
+        let mut book = new_book(BookKind::BTree, None);
+        book.apply(&OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(dec!(99), dec!(1), QuoteType::BID),
+            PriceLevel::new(dec!(98), dec!(2), QuoteType::BID),
+            PriceLevel::new(dec!(101), dec!(1), QuoteType::ASK),
+            PriceLevel::new(dec!(102), dec!(1), QuoteType::ASK),
+            PriceLevel::new(dec!(110), dec!(5), QuoteType::ASK),
+        ]));
+        book
+    }
+
+    #[test]
+    fn test_buy_base_quantity() {
+        let estimate = book().cost_to_trade(BuySell::Buy, TradeSize::Base(dec!(3)));
+
+        assert_eq!(estimate.filled_qty, dec!(3));
+        assert_eq!(estimate.notional, dec!(313));
+        assert_eq!(estimate.worst_price, Some(dec!(110)));
+        assert_eq!(estimate.unfilled, Decimal::ZERO);
+        // vwap 104.333.., mid 100
+        assert_eq!(estimate.slippage_bps.unwrap().round_dp(2), dec!(433.33));
+    }
+
+    #[test]
+    fn test_sell_more_than_available() {
+        let estimate = book().cost_to_trade(BuySell::Sell, TradeSize::Base(dec!(5)));
+
+        assert_eq!(estimate.filled_qty, dec!(3));
+        assert_eq!(estimate.vwap.unwrap().round_dp(4), dec!(98.3333));
+        assert_eq!(estimate.unfilled, dec!(2));
+    }
+
+    #[test]
+    fn test_buy_notional() {
+        let estimate = book().cost_to_trade(BuySell::Buy, TradeSize::Notional(dec!(152)));
+
+        assert_eq!(estimate.filled_qty, dec!(1.5));
+        assert_eq!(estimate.notional, dec!(152));
+        assert_eq!(estimate.worst_price, Some(dec!(102)));
+        assert_eq!(estimate.unfilled, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_sell_notional_inexact_division() {
+        // 99 on the first level, the last 1 buys 1/98 of the second
+        let estimate = book().cost_to_trade(BuySell::Sell, TradeSize::Notional(dec!(100)));
+
+        assert_eq!(estimate.notional, dec!(100));
+        assert_eq!(estimate.unfilled, Decimal::ZERO);
+        assert_eq!(estimate.filled_qty.round_dp(8), dec!(1.01020408));
+        assert_eq!(estimate.worst_price, Some(dec!(98)));
+    }
+
+    #[test]
+    fn test_capacity_within_bps() {
+        let book = book();
+        assert_eq!(book.capacity_within_bps(BuySell::Buy, dec!(200)), Capacity { qty: dec!(2), notional: dec!(203) });
+        assert_eq!(book.capacity_within_bps(BuySell::Sell, dec!(50)), Capacity { qty: Decimal::ZERO, notional: Decimal::ZERO });
+        assert_eq!(book.capacity_within_bps(BuySell::Sell, dec!(100)).qty, dec!(1));
+    }
+}
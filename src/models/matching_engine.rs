@@ -0,0 +1,315 @@
+// Price-time priority matching engine
+//
+// Incoming orders are matched against the resting orders of an order_book_l3::OrderBook,
+// best price first and oldest order first within a price. Used as a local exchange
+// simulator.
+
+use std::fmt;
+use rust_decimal::Decimal;
+
+use crate::models::order_book_l3::{OrderBook, OrderError};
+use crate::models::types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInForce {
+    // Good till cancelled: the remainder rests in the book
+    GTC,
+    // Immediate or cancel: the remainder is cancelled
+    IOC,
+    // Fill or kill: either fully filled at once or cancelled without any fill
+    FOK,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderType {
+    Limit { price: Price, time_in_force: TimeInForce },
+    // Rests at its price only if it doesn't take liquidity, rejected otherwise
+    PostOnly { price: Price },
+    // Takes whatever is available, the remainder is cancelled
+    Market,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NewOrder {
+    pub oid: usize,
+    pub side: BuySell,
+    pub qty: Qty,
+    pub order_type: OrderType,
+}
+
+impl NewOrder {
+    pub fn limit(oid: usize, side: BuySell, price: Price, qty: Qty) -> Self {
+        NewOrder { oid, side, qty, order_type: OrderType::Limit { price, time_in_force: TimeInForce::GTC } }
+    }
+
+    pub fn ioc(oid: usize, side: BuySell, price: Price, qty: Qty) -> Self {
+        NewOrder { oid, side, qty, order_type: OrderType::Limit { price, time_in_force: TimeInForce::IOC } }
+    }
+
+    pub fn fok(oid: usize, side: BuySell, price: Price, qty: Qty) -> Self {
+        NewOrder { oid, side, qty, order_type: OrderType::Limit { price, time_in_force: TimeInForce::FOK } }
+    }
+
+    pub fn post_only(oid: usize, side: BuySell, price: Price, qty: Qty) -> Self {
+        NewOrder { oid, side, qty, order_type: OrderType::PostOnly { price } }
+    }
+
+    pub fn market(oid: usize, side: BuySell, qty: Qty) -> Self {
+        NewOrder { oid, side, qty, order_type: OrderType::Market }
+    }
+
+    fn limit_price(&self) -> Option<Price> {
+        match self.order_type {
+            OrderType::Limit { price, .. } | OrderType::PostOnly { price } => Some(price),
+            OrderType::Market => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fill {
+    pub maker_oid: usize,
+    pub taker_oid: usize,
+    // Taker side, the maker is on the other side
+    pub side: BuySell,
+    pub price: Price,
+    pub qty: Qty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    // Cancelled on request, see MatchingEngine::cancel
+    Requested,
+    ImmediateOrCancel,
+    FillOrKill,
+    // Market order remainder once the other side is exhausted
+    NoLiquidity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    Order(OrderError),
+    // Post-only order that would have taken liquidity
+    WouldCross,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RejectReason::Order(error) => write!(f, "{}", error),
+            RejectReason::WouldCross => write!(f, "post-only order would cross"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExecutionEvent {
+    Fill(Fill),
+    Rested { oid: usize, side: BuySell, price: Price, qty: Qty },
+    Cancelled { oid: usize, remaining: Qty, reason: CancelReason },
+    Rejected { oid: usize, reason: RejectReason },
+}
+
+#[derive(Debug)]
+pub struct MatchingEngine {
+    book: OrderBook,
+}
+
+impl MatchingEngine {
+    pub fn new() -> Self {
+        MatchingEngine { book: OrderBook::new() }
+    }
+
+    pub fn book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    // Whether a taker at `limit` can trade with a maker resting at `price`
+    fn crosses(side: BuySell, limit: Option<Price>, price: Price) -> bool {
+        match (side, limit) {
+            (_, None) => true,
+            (BuySell::Buy, Some(limit)) => price.value() <= limit.value(),
+            (BuySell::Sell, Some(limit)) => price.value() >= limit.value(),
+        }
+    }
+
+    // Quantity resting on the other side that an order could take
+    fn available(&self, order: &NewOrder) -> Decimal {
+        let limit = order.limit_price();
+        self.book.levels(order.side.other_side())
+            .take_while(|level| Self::crosses(order.side, limit, level.price))
+            .map(|level| level.qty.value())
+            .sum()
+    }
+
+    pub fn submit(&mut self, order: NewOrder) -> Vec<ExecutionEvent> {
+        let oid = order.oid;
+        if order.qty.value() <= Decimal::ZERO {
+            return vec![ExecutionEvent::Rejected { oid, reason: RejectReason::Order(OrderError::InvalidQty(oid)) }];
+        }
+        if self.book.order(oid).is_some() {
+            return vec![ExecutionEvent::Rejected { oid, reason: RejectReason::Order(OrderError::DuplicateOrderId(oid)) }];
+        }
+
+        match order.order_type {
+            OrderType::PostOnly { .. } if self.available(&order) > Decimal::ZERO => {
+                return vec![ExecutionEvent::Rejected { oid, reason: RejectReason::WouldCross }];
+            },
+            OrderType::Limit { time_in_force: TimeInForce::FOK, .. } if self.available(&order) < order.qty.value() => {
+                return vec![ExecutionEvent::Cancelled { oid, remaining: order.qty, reason: CancelReason::FillOrKill }];
+            },
+            _ => (),
+        }
+
+        let mut events = Vec::new();
+        let remaining = self.match_order(&order, &mut events);
+
+        if remaining > Decimal::ZERO {
+            let remaining = Qty(remaining);
+            let event = match order.order_type {
+                OrderType::Limit { price, time_in_force: TimeInForce::GTC } | OrderType::PostOnly { price } => {
+                    match self.book.add(oid, order.side, price, remaining) {
+                        Ok(()) => ExecutionEvent::Rested { oid, side: order.side, price, qty: remaining },
+                        Err(error) => ExecutionEvent::Rejected { oid, reason: RejectReason::Order(error) },
+                    }
+                },
+                OrderType::Limit { time_in_force, .. } => {
+                    let reason = match time_in_force {
+                        TimeInForce::FOK => CancelReason::FillOrKill,
+                        _ => CancelReason::ImmediateOrCancel,
+                    };
+                    ExecutionEvent::Cancelled { oid, remaining, reason }
+                },
+                OrderType::Market => ExecutionEvent::Cancelled { oid, remaining, reason: CancelReason::NoLiquidity },
+            };
+            events.push(event);
+        }
+        events
+    }
+
+    // Takes liquidity from the other side and returns the quantity left to fill
+    fn match_order(&mut self, order: &NewOrder, events: &mut Vec<ExecutionEvent>) -> Decimal {
+        let limit = order.limit_price();
+        let mut remaining = order.qty.value();
+
+        while remaining > Decimal::ZERO {
+            let maker_oid = match self.book.best_order(order.side.other_side()) {
+                Some(maker_oid) => maker_oid,
+                None => break,
+            };
+            let maker_qty = self.book.order(maker_oid).map(|maker| maker.qty.value()).unwrap_or_default();
+            let price = match self.book.level_of(maker_oid) {
+                Some(level) if Self::crosses(order.side, limit, level.price) => level.price,
+                _ => break,
+            };
+
+            let qty = remaining.min(maker_qty);
+            if self.book.execute(maker_oid, Qty(qty)).is_err() {
+                break;
+            }
+            remaining -= qty;
+            events.push(ExecutionEvent::Fill(Fill {
+                maker_oid,
+                taker_oid: order.oid,
+                side: order.side,
+                price,
+                qty: Qty(qty),
+            }));
+        }
+        remaining
+    }
+
+    pub fn cancel(&mut self, oid: usize) -> ExecutionEvent {
+        match self.book.cancel(oid) {
+            Ok(order) => ExecutionEvent::Cancelled { oid, remaining: order.qty, reason: CancelReason::Requested },
+            Err(error) => ExecutionEvent::Rejected { oid, reason: RejectReason::Order(error) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn engine_with_asks() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        engine.submit(NewOrder::limit(1, BuySell::Sell, Price(dec!(101)), Qty(dec!(1))));
+        engine.submit(NewOrder::limit(2, BuySell::Sell, Price(dec!(101)), Qty(dec!(2))));
+        engine.submit(NewOrder::limit(3, BuySell::Sell, Price(dec!(102)), Qty(dec!(5))));
+        engine
+    }
+
+    fn fills(events: &[ExecutionEvent]) -> Vec<(usize, Decimal, Decimal)> {
+        events.iter().filter_map(|event| match event {
+            ExecutionEvent::Fill(fill) => Some((fill.maker_oid, fill.price.value(), fill.qty.value())),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_limit_matches_in_price_time_priority_and_rests_remainder() {
+        let mut engine = engine_with_asks();
+        let events = engine.submit(NewOrder::limit(10, BuySell::Buy, Price(dec!(101)), Qty(dec!(4))));
+
+        assert_eq!(fills(&events), vec![(1, dec!(101), dec!(1)), (2, dec!(101), dec!(2))]);
+        assert_eq!(
+            events.last(),
+            Some(&ExecutionEvent::Rested { oid: 10, side: BuySell::Buy, price: Price(dec!(101)), qty: Qty(dec!(1)) })
+        );
+        assert_eq!(engine.book().best(BuySell::Buy).unwrap().price, Price(dec!(101)));
+        assert_eq!(engine.book().best(BuySell::Sell).unwrap().price, Price(dec!(102)));
+    }
+
+    #[test]
+    fn test_market_and_ioc_cancel_remainder() {
+        let mut engine = engine_with_asks();
+        let events = engine.submit(NewOrder::ioc(10, BuySell::Buy, Price(dec!(101)), Qty(dec!(5))));
+        assert_eq!(fills(&events).len(), 2);
+        assert_eq!(
+            events.last(),
+            Some(&ExecutionEvent::Cancelled { oid: 10, remaining: Qty(dec!(2)), reason: CancelReason::ImmediateOrCancel })
+        );
+        assert!(engine.book().best(BuySell::Buy).is_none());
+
+        let events = engine.submit(NewOrder::market(11, BuySell::Buy, Qty(dec!(6))));
+        assert_eq!(fills(&events), vec![(3, dec!(102), dec!(5))]);
+        assert_eq!(
+            events.last(),
+            Some(&ExecutionEvent::Cancelled { oid: 11, remaining: Qty(dec!(1)), reason: CancelReason::NoLiquidity })
+        );
+    }
+
+    #[test]
+    fn test_cancel_resting_order() {
+        let mut engine = engine_with_asks();
+        engine.submit(NewOrder::limit(10, BuySell::Buy, Price(dec!(100)), Qty(dec!(2))));
+        assert_eq!(
+            engine.cancel(10),
+            ExecutionEvent::Cancelled { oid: 10, remaining: Qty(dec!(2)), reason: CancelReason::Requested }
+        );
+        assert!(engine.book().best(BuySell::Buy).is_none());
+        assert!(matches!(engine.cancel(10), ExecutionEvent::Rejected { oid: 10, .. }));
+    }
+
+    #[test]
+    fn test_fok_is_all_or_nothing() {
+        let mut engine = engine_with_asks();
+        let events = engine.submit(NewOrder::fok(10, BuySell::Buy, Price(dec!(101)), Qty(dec!(4))));
+        assert_eq!(events, vec![ExecutionEvent::Cancelled { oid: 10, remaining: Qty(dec!(4)), reason: CancelReason::FillOrKill }]);
+        assert_eq!(engine.book().best(BuySell::Sell).unwrap().qty, Qty(dec!(3)));
+
+        let events = engine.submit(NewOrder::fok(11, BuySell::Buy, Price(dec!(102)), Qty(dec!(4))));
+        assert_eq!(fills(&events), vec![(1, dec!(101), dec!(1)), (2, dec!(101), dec!(2)), (3, dec!(102), dec!(1))]);
+    }
+
+    #[test]
+    fn test_post_only_rejected_when_crossing() {
+        let mut engine = engine_with_asks();
+        let events = engine.submit(NewOrder::post_only(10, BuySell::Buy, Price(dec!(101)), Qty(dec!(1))));
+        assert_eq!(events, vec![ExecutionEvent::Rejected { oid: 10, reason: RejectReason::WouldCross }]);
+
+        let events = engine.submit(NewOrder::post_only(11, BuySell::Buy, Price(dec!(100)), Qty(dec!(1))));
+        assert!(matches!(events[..], [ExecutionEvent::Rested { oid: 11, .. }]));
+    }
+}
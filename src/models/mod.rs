@@ -1,4 +1,21 @@
 pub mod kraken;
+pub mod binance;
+pub mod coinbase;
+pub mod okx;
+pub mod bybit;
+pub mod arbitrage;
+pub mod book;
+pub mod book_registry;
+pub mod consolidated_book;
+pub mod diff;
+pub mod fx;
+pub mod instrument;
+pub mod instrument_registry;
+pub mod liquidity;
+pub mod matching_engine;
 pub mod order_book;
-mod order_book_2;
-mod types;
\ No newline at end of file
+pub mod order_book_2;
+pub mod order_book_l3;
+pub mod top_of_book;
+pub mod trade;
+pub mod types;
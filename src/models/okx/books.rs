@@ -0,0 +1,125 @@
+// OKX v5 public `books` channel
+//
+// The checksum is a signed CRC32 over the top 25 bids and asks interleaved as
+// bid1 price:bid1 size:ask1 price:ask1 size:..., a side running out of levels is skipped. Prices
+// and sizes are the exchange strings, which Decimal keeps as received.
+//
+// https://www.okx.com/docs-v5/en/#order-book-trading-market-data-ws-order-book-channel
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use crate::models::book::Book;
+use crate::models::order_book::{OrderBook, OrderBookUpdate, PriceLevel, QuoteType};
+
+pub const CHECKSUM_DEPTH: usize = 25;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BooksArg {
+    pub channel: String,
+    #[serde(rename = "instId")]
+    pub inst_id: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Snapshot,
+    Update,
+}
+
+// [price, size, deprecated, number of orders]
+pub type Level = (Decimal, Decimal, String, String);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BooksData {
+    pub asks: Vec<Level>,
+    pub bids: Vec<Level>,
+    pub ts: String,
+    pub checksum: i32,
+    // -1 for a snapshot
+    #[serde(rename = "prevSeqId")]
+    pub prev_seq_id: i64,
+    // Equal to prevSeqId when nothing changed
+    #[serde(rename = "seqId")]
+    pub seq_id: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BooksMessage {
+    pub arg: BooksArg,
+    pub action: Action,
+    pub data: Vec<BooksData>,
+}
+
+pub fn parse_books(text: &str) -> Option<BooksMessage> {
+    serde_json::from_str(text).ok()
+}
+
+impl BooksData {
+    pub fn to_update(&self, action: Action) -> OrderBookUpdate {
+        let price_levels = self.asks.iter()
+            .map(|(price, size, _, _)| PriceLevel::new(*price, *size, QuoteType::ASK))
+            .chain(self.bids.iter().map(|(price, size, _, _)| PriceLevel::new(*price, *size, QuoteType::BID)))
+            .collect();
+        match action {
+            Action::Snapshot => OrderBookUpdate::snapshot(price_levels),
+            Action::Update => OrderBookUpdate::new(price_levels),
+        }
+    }
+}
+
+pub fn checksum_input(bids: &[(Decimal, Decimal)], asks: &[(Decimal, Decimal)]) -> String {
+    let mut fields = Vec::new();
+    for i in 0..CHECKSUM_DEPTH {
+        for side in [bids, asks] {
+            if let Some((price, size)) = side.get(i) {
+                fields.push(price.to_string());
+                fields.push(size.to_string());
+            }
+        }
+    }
+    fields.join(":")
+}
+
+pub fn checksum(book: &OrderBook) -> i32 {
+    let bids = book.top_levels(QuoteType::BID, CHECKSUM_DEPTH);
+    let asks = book.top_levels(QuoteType::ASK, CHECKSUM_DEPTH);
+    crc32fast::hash(checksum_input(&bids, &asks).as_bytes()) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const SNAPSHOT: &str = include_str!("../../../tests/fixtures/okx/books_snapshot.json");
+    const UPDATE: &str = include_str!("../../../tests/fixtures/okx/books_update.json");
+
+    #[test]
+    fn test_checksum_input() {
+        // Example of the OKX documentation
+        let bids = [(dec!(3366.1), dec!(7)), (dec!(3366), dec!(6))];
+        let asks = [(dec!(3366.8), dec!(9)), (dec!(3368), dec!(8))];
+        let input = checksum_input(&bids, &asks);
+        assert_eq!(input, "3366.1:7:3366.8:9:3366:6:3368:8");
+        assert_eq!(crc32fast::hash(input.as_bytes()) as i32, -1881014294);
+
+        // The longer side goes on alone
+        assert_eq!(checksum_input(&bids, &asks[..1]), "3366.1:7:3366.8:9:3366:6");
+    }
+
+    #[test]
+    fn test_snapshot_then_update() {
+        let mut book = OrderBook::new();
+        for text in [SNAPSHOT, UPDATE] {
+            let message = parse_books(text).unwrap();
+            assert_eq!(message.arg.inst_id, "BTC-USDT");
+            for data in &message.data {
+                book.update(&data.to_update(message.action));
+                assert_eq!(checksum(&book), data.checksum);
+            }
+        }
+        assert_eq!(book.best_bid(), Some((dec!(66010.1), dec!(0.4))));
+        assert_eq!(book.best_ask(), Some((dec!(66011), dec!(2))));
+    }
+}
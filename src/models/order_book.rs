@@ -1,9 +1,12 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt;
-use kraken_ws_client::api::{BookData};
+use kraken_ws_client::api::BookEvent;
 use rust_decimal::Decimal;
 use std::string::String;
 use crate::models::order_book::QuoteType::{ASK, BID};
+use crate::models::kraken::checksum;
+use crate::models::kraken::translate::from_kraken;
+use crate::models::book::Book;
 
 type Bids = BTreeMap<Price, Qty>;
 type Asks = BTreeMap<Price, Qty>;
@@ -12,11 +15,90 @@ type Price = Decimal;
 type Qty = Decimal;
 
 
+// Kraken's BTC/USD precisions, used for the checksum unless configured otherwise
+const DEFAULT_PRICE_PRECISION: u32 = 1;
+const DEFAULT_QTY_PRECISION: u32 = 8;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumStats {
+    pub passed: u64,
+    pub failed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub expected: u32,
+    pub computed: u32,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "checksum mismatch: expected {}, computed {}", self.expected, self.computed)
+    }
+}
+
+// Upper bound on deltas kept while waiting for a snapshot, oldest are dropped first
+const MAX_BUFFERED_UPDATES: usize = 1024;
+
+/// Lifecycle of a book fed by a snapshot followed by deltas.
+///
+/// Only a `Synced` book can be trusted; in every other state best bid/ask may be missing
+/// or out of date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookState {
+    // No snapshot received yet
+    AwaitingSnapshot,
+    // Snapshot applied and every delta since then applied in order
+    Synced,
+    // The book is known to have drifted (checksum mismatch, disconnect, ...)
+    Stale,
+    // A new snapshot has been requested and is expected
+    Resyncing,
+}
+
+impl fmt::Display for BookState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookState::AwaitingSnapshot => write!(f, "AWAITING_SNAPSHOT"),
+            BookState::Synced => write!(f, "SYNCED"),
+            BookState::Stale => write!(f, "STALE"),
+            BookState::Resyncing => write!(f, "RESYNCING"),
+        }
+    }
+}
+
+/// What to do with deltas received while the book is not synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingDeltas {
+    // Discard them: the snapshot already reflects them (Kraken)
+    Drop,
+    // Keep them and apply them on top of the next snapshot (REST snapshot + stream feeds)
+    Buffer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    Applied,
+    Buffered,
+    Dropped,
+    // A numbered delta is missing, between the snapshot and the buffered deltas or between two
+    // live deltas: the book is stale
+    Gap,
+}
+
 #[derive(Debug)]
 pub struct OrderBook {
     bids: Bids,
     asks: Asks,
-    is_empty: bool
+    state: BookState,
+    pending_deltas: PendingDeltas,
+    buffered: VecDeque<OrderBookUpdate>,
+    // First update id the next numbered delta must cover
+    next_sequence: Option<u64>,
+    max_depth: Option<usize>,
+    price_precision: u32,
+    qty_precision: u32,
+    checksum_stats: ChecksumStats,
 }
 
 impl Clone for OrderBook {
@@ -24,7 +106,14 @@ impl Clone for OrderBook {
         OrderBook {
             bids: self.bids.clone(),
             asks:self.asks.clone(),
-            is_empty: self.is_empty
+            state: self.state,
+            pending_deltas: self.pending_deltas,
+            buffered: self.buffered.clone(),
+            next_sequence: self.next_sequence,
+            max_depth: self.max_depth,
+            price_precision: self.price_precision,
+            qty_precision: self.qty_precision,
+            checksum_stats: self.checksum_stats,
         }
     }
 }
@@ -34,10 +123,96 @@ impl OrderBook {
         OrderBook {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
-            is_empty: true
+            state: BookState::AwaitingSnapshot,
+            pending_deltas: PendingDeltas::Drop,
+            buffered: VecDeque::new(),
+            next_sequence: None,
+            max_depth: None,
+            price_precision: DEFAULT_PRICE_PRECISION,
+            qty_precision: DEFAULT_QTY_PRECISION,
+            checksum_stats: ChecksumStats::default(),
         }
     }
 
+    // Price and quantity precisions of the pair, needed to normalize levels for the checksum
+    pub fn with_precision(mut self, price_precision: u32, qty_precision: u32) -> Self {
+        self.price_precision = price_precision;
+        self.qty_precision = qty_precision;
+        self
+    }
+
+    // Number of levels kept per side, levels pushed out of it are discarded after each update
+    // as the exchange stops sending updates for them
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn with_pending_deltas(mut self, pending_deltas: PendingDeltas) -> Self {
+        self.pending_deltas = pending_deltas;
+        self
+    }
+
+    // Back to an empty book waiting for its first snapshot
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.buffered.clear();
+        self.state = BookState::AwaitingSnapshot;
+    }
+
+    pub fn state(&self) -> BookState {
+        self.state
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.state == BookState::Synced
+    }
+
+    // The book can no longer be trusted, deltas are ignored until the next snapshot
+    pub fn mark_stale(&mut self) {
+        self.state = BookState::Stale;
+    }
+
+    // A new snapshot has been requested
+    pub fn begin_resync(&mut self) {
+        self.state = BookState::Resyncing;
+        self.buffered.clear();
+    }
+
+    pub fn checksum_stats(&self) -> ChecksumStats {
+        self.checksum_stats
+    }
+
+    // Kraken CRC32 over the top 10 asks (ascending) and top 10 bids (descending)
+    pub fn checksum(&self) -> u32 {
+        checksum::compute(
+            self.asks.iter(),
+            self.bids.iter().rev(),
+            self.price_precision,
+            self.qty_precision,
+        )
+    }
+
+    // Compares the book with the checksum published by the exchange. On mismatch the book is
+    // marked stale and stays so until the next snapshot.
+    pub fn verify_checksum(&mut self, expected: u32) -> Result<(), ChecksumMismatch> {
+        let computed = self.checksum();
+        if computed == expected {
+            self.checksum_stats.passed += 1;
+            Ok(())
+        } else {
+            self.checksum_stats.failed += 1;
+            self.mark_stale();
+            Err(ChecksumMismatch { expected, computed })
+        }
+    }
+
+
 
     fn insert_order(&mut self, price: Price, quantity: Qty, quote_type: QuoteType) {
         match quote_type {
@@ -61,7 +236,8 @@ impl OrderBook {
     }
 
 
-    fn execute_sell_limit(&self, price: Price, qty: Qty) -> Vec<(Price, Qty)> {
+    // Level modifications caused by a sell limit order, and the quantity left unmatched
+    fn execute_sell_limit(&self, price: Price, qty: Qty) -> (Vec<(Price, Qty)>, Qty) {
         let mut sell_order_qty = qty;
         let mut modifications: Vec<(Price, Qty)> = Vec::new();
 
@@ -83,14 +259,13 @@ impl OrderBook {
 
             }
         }
-        modifications
+        (modifications, sell_order_qty)
     }
 
-    fn execute_buy_limit( &mut self, price: Price, qty: Qty) -> Vec<(Price, Qty)>  {
+    // Level modifications caused by a buy limit order, and the quantity left unmatched
+    fn execute_buy_limit(&self, price: Price, qty: Qty) -> (Vec<(Price, Qty)>, Qty)  {
         let mut buy_order_qty = qty;
         let mut modifications: Vec<(Price, Qty)> = Vec::new();
-        // todo there must be a bug: if the limit is empty at some point, the remaining of the order will stay in the book
-        // todo this scenario isnt cobered
 
         for (&ask_price, &ask_qty) in self.asks.iter() {
             if ask_price > price || buy_order_qty.is_zero() {
@@ -111,66 +286,42 @@ impl OrderBook {
             }
         }
 
-        modifications
+        (modifications, buy_order_qty)
     }
 
-    pub fn update_from_kraken(&mut self, update: &Vec<BookData>) {
-        if self.is_empty {
-            for book_data in update.iter() {
-                for level_data in book_data.asks.iter() {
-                    self.insert_order(level_data.price, level_data.qty, ASK)
-                }
-                for level_data in book_data.bids.iter() {
-                    self.insert_order(level_data.price, level_data.qty, BID)
-                }
-            }
-            // construct the order book from the update => straightforward update
-        } else {
-            // for each limit update, loop over the book and decide if it leads to execution
-            // if it leads to execution, proceed with the execution
-            // -> loop over the opposite side and remove liquidity
-            // if not proceed with a regular update
-            for book_data in update.iter() {
-                for level_data in book_data.asks.iter() {
-                    let price_in = level_data.price;
-                    let qty_in = level_data.qty;
-                    match self.best_bid() {
-                        Some((bid, qty)) => {
-                            if bid >= price_in {
-                                let modifications = self.execute_sell_limit(price_in, qty_in);
-                                // Apply modifications
-                                for (price, qty) in modifications {
-                                    self.insert_order(price, qty, BID);
-                                }
-                            } else {
-                                self.insert_order(price_in, qty, ASK);
-                            }
-                        },
-                        None => (),
-                    }
-                }
-                for level_data in book_data.asks.iter().rev() {
-                    let price_in = level_data.price;
-                    let qty_in = level_data.qty;
-
-                    match self.best_ask() {
-                        Some((ask, qty)) => {
-                            if ask <= price_in {
-                                let modifications = self.execute_buy_limit(price_in, qty_in);
-                                // Apply modifications
-                                for (price, qty) in modifications {
-                                    self.insert_order(price, qty, ASK);
-                                }
-                            } else {
-                                self.insert_order(price_in, qty, BID)
-                            }
-                        },
-                        None => (),
-                    }
-                }
-            }
+    // Takes the liquidity a limit order would consume and rests what is left of it on its own
+    // side. Levels have no order ids, see matching_engine for fills with maker/taker ids.
+    pub fn execute_limit(&mut self, price: Price, qty: Qty, quote_type: QuoteType) -> Qty {
+        let (modifications, remaining) = match quote_type {
+            BID => self.execute_buy_limit(price, qty),
+            ASK => self.execute_sell_limit(price, qty),
+        };
+        let opposite = match quote_type {
+            BID => ASK,
+            ASK => BID,
+        };
+        for (level_price, level_qty) in modifications {
+            self.insert_order(level_price, level_qty, opposite);
+        }
+        if !remaining.is_zero() {
+            let resting = self.level_qty(price, quote_type) + remaining;
+            self.insert_order(price, resting, quote_type);
         }
+        remaining
     }
+
+    fn level_qty(&self, price: Price, quote_type: QuoteType) -> Qty {
+        let side = match quote_type {
+            BID => &self.bids,
+            ASK => &self.asks,
+        };
+        side.get(&price).cloned().unwrap_or(Decimal::ZERO)
+    }
+
+    pub fn update_from_kraken(&mut self, event: &BookEvent) -> UpdateOutcome {
+        self.update(&from_kraken(event))
+    }
+
     pub fn ensure_book_is_valid(&mut self) {
         // let best_ask = self.best_ask()?;
         // let best_bid = self.best_bid()?;
@@ -199,8 +350,8 @@ impl OrderBook {
         }
     }
 
-    pub fn update(&mut self, update: &Vec<PriceLevel>) {
-        update.iter().for_each(|price_level| {
+    fn apply_levels(&mut self, price_levels: &[PriceLevel]) {
+        price_levels.iter().for_each(|price_level| {
             let price = price_level.price;
             let quantity = price_level.quantity;
             // let level_key = format!("{:.10}", price);
@@ -217,6 +368,86 @@ impl OrderBook {
         )
     }
 
+    fn truncate(&mut self) {
+        if let Some(max_depth) = self.max_depth {
+            // worst bids are the lowest prices, worst asks the highest
+            while self.bids.len() > max_depth {
+                self.bids.pop_first();
+            }
+            while self.asks.len() > max_depth {
+                self.asks.pop_last();
+            }
+        }
+    }
+
+    // A snapshot replaces the whole book at once, then buffered deltas are replayed on top.
+    // Numbered deltas the snapshot already covers are skipped, a missing one leaves the book
+    // stale until the next snapshot.
+    fn apply_snapshot(&mut self, update: &OrderBookUpdate) {
+        let mut book = OrderBook::new();
+        book.apply_levels(&update.price_levels);
+        self.bids = book.bids;
+        self.asks = book.asks;
+        self.state = BookState::Synced;
+
+        let mut next = update.sequence.map(|(_, last)| last + 1);
+        while let Some(delta) = self.buffered.pop_front() {
+            if let Some((first, last)) = delta.sequence {
+                match next {
+                    Some(next) if last < next => continue,
+                    Some(next) if first > next => {
+                        self.mark_stale();
+                        self.buffered.clear();
+                        break;
+                    },
+                    _ => next = Some(last + 1),
+                }
+            }
+            self.apply_levels(&delta.price_levels);
+        }
+        self.next_sequence = next;
+        self.truncate();
+    }
+
+    pub fn update(&mut self, update: &OrderBookUpdate) -> UpdateOutcome {
+        if update.is_snapshot() {
+            self.apply_snapshot(update);
+            return match self.state {
+                BookState::Synced => UpdateOutcome::Applied,
+                _ => UpdateOutcome::Gap,
+            };
+        }
+
+        match (self.state, self.pending_deltas) {
+            (BookState::Synced, _) => {
+                // Numbered deltas follow the same rules as the buffered ones
+                if let (Some((first, last)), Some(next)) = (update.sequence, self.next_sequence) {
+                    if last < next {
+                        return UpdateOutcome::Dropped;
+                    }
+                    if first > next {
+                        self.mark_stale();
+                        return UpdateOutcome::Gap;
+                    }
+                }
+                if let Some((_, last)) = update.sequence {
+                    self.next_sequence = Some(last + 1);
+                }
+                self.apply_levels(&update.price_levels);
+                self.truncate();
+                UpdateOutcome::Applied
+            },
+            (BookState::AwaitingSnapshot | BookState::Resyncing, PendingDeltas::Buffer) => {
+                if self.buffered.len() == MAX_BUFFERED_UPDATES {
+                    self.buffered.pop_front();
+                }
+                self.buffered.push_back(update.clone());
+                UpdateOutcome::Buffered
+            },
+            _ => UpdateOutcome::Dropped,
+        }
+    }
+
     // Best bid is the last key in bids, because it's the highest
     pub fn best_bid(&self) -> Option<(Decimal, Decimal)> {
         self.bids.iter().rev().next().map(|(&price, &volume)| (price, volume))
@@ -288,11 +519,55 @@ impl OrderBook {
     }
 }
 
+#[cfg(test)]
+impl OrderBook {
+    // A synced book of (price, qty) levels, in any order
+    pub fn from_levels(bids: &[(Decimal, Decimal)], asks: &[(Decimal, Decimal)]) -> Self {
+        let levels = bids.iter().map(|&(price, qty)| PriceLevel::new(price, qty, BID))
+            .chain(asks.iter().map(|&(price, qty)| PriceLevel::new(price, qty, ASK)))
+            .collect();
+        let mut book = OrderBook::new();
+        book.update(&OrderBookUpdate::snapshot(levels));
+        book
+    }
+}
+
+impl Book for OrderBook {
+    fn apply_snapshot(&mut self, snapshot: &OrderBookUpdate) {
+        OrderBook::apply_snapshot(self, snapshot);
+    }
+
+    // Applied whatever the state, sync is up to the caller going through update()
+    fn apply_delta(&mut self, delta: &OrderBookUpdate) {
+        self.apply_levels(&delta.price_levels);
+        self.truncate();
+    }
+
+    fn best_bid(&self) -> Option<(Decimal, Decimal)> {
+        OrderBook::best_bid(self)
+    }
+
+    fn best_ask(&self) -> Option<(Decimal, Decimal)> {
+        OrderBook::best_ask(self)
+    }
+
+    fn levels(&self, side: QuoteType) -> Box<dyn Iterator<Item = (Decimal, Decimal)> + '_> {
+        match side {
+            BID => Box::new(self.bids.iter().rev().map(|(&price, &qty)| (price, qty))),
+            ASK => Box::new(self.asks.iter().map(|(&price, &qty)| (price, qty))),
+        }
+    }
+
+    fn clear(&mut self) {
+        OrderBook::clear(self);
+    }
+}
+
 pub trait OrderBookEvent {
     fn update(&self, order_book: OrderBook);
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum QuoteType{
     BID, ASK
 }
@@ -306,7 +581,7 @@ impl fmt::Display for QuoteType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PriceLevel {
     price: Price,
     quantity: Qty,
@@ -317,16 +592,65 @@ impl PriceLevel {
     pub fn new(price: Price, quantity: Qty, quote_type: QuoteType) -> Self {
         PriceLevel{price, quantity, quote_type}
     }
+
+    pub fn price(&self) -> Price {
+        self.price
+    }
+
+    pub fn quantity(&self) -> Qty {
+        self.quantity
+    }
+
+    pub fn quote_type(&self) -> QuoteType {
+        self.quote_type
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateKind {
+    Snapshot,
+    Delta,
+}
+
+#[derive(Debug, Clone)]
 pub struct OrderBookUpdate {
-    price_levels: Vec<PriceLevel>
+    price_levels: Vec<PriceLevel>,
+    kind: UpdateKind,
+    // First and last update ids of the venue it covers, if the venue numbers them
+    sequence: Option<(u64, u64)>,
 }
 
 impl OrderBookUpdate {
+    // A delta: each level replaces the quantity at its price, zero removes the level
     pub fn new(price_levels: Vec<PriceLevel>) -> Self {
-        OrderBookUpdate { price_levels }
+        OrderBookUpdate { price_levels, kind: UpdateKind::Delta, sequence: None }
+    }
+
+    // A full image of the book
+    pub fn snapshot(price_levels: Vec<PriceLevel>) -> Self {
+        OrderBookUpdate { price_levels, kind: UpdateKind::Snapshot, sequence: None }
+    }
+
+    // A snapshot covers up to `last`, `first` is not relevant to it
+    pub fn with_sequence(mut self, first: u64, last: u64) -> Self {
+        self.sequence = Some((first, last));
+        self
+    }
+
+    pub fn sequence(&self) -> Option<(u64, u64)> {
+        self.sequence
+    }
+
+    pub fn kind(&self) -> UpdateKind {
+        self.kind
+    }
+
+    pub fn is_snapshot(&self) -> bool {
+        self.kind == UpdateKind::Snapshot
+    }
+
+    pub fn price_levels(&self) -> &Vec<PriceLevel> {
+        &self.price_levels
     }
 
     pub fn log_msg(&self) -> String {
@@ -367,11 +691,34 @@ mod tests {
             },
         ];
 
-        // order_book.update(&OrderBookUpdate::new(updates));
+        // Deltas are only applied to a synced book
+        order_book.update(&OrderBookUpdate::snapshot(vec![]));
+        assert_eq!(order_book.update(&OrderBookUpdate::new(updates)), UpdateOutcome::Applied);
 
         assert!(order_book.asks.is_empty(), "Asks should be empty after a zero-quantity update");
     }
 
+    #[test]
+    fn test_zero_quantity_delta_removes_level() {
+        let mut order_book = OrderBook::new();
+        order_book.update(&OrderBookUpdate::snapshot(vec![PriceLevel::new(dec!(100), dec!(10), QuoteType::ASK)]));
+        assert_eq!(order_book.best_ask(), Some((dec!(100), dec!(10))));
+
+        order_book.update(&OrderBookUpdate::new(vec![PriceLevel::new(dec!(100), Decimal::ZERO, QuoteType::ASK)]));
+        assert!(order_book.asks.is_empty(), "Asks should be empty after a zero-quantity update");
+    }
+
+    #[test]
+    fn test_zero_quantity_snapshot_level() {
+        let mut order_book = OrderBook::new();
+        order_book.update(&OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(dec!(100), dec!(10), QuoteType::ASK),
+            PriceLevel::new(dec!(100), Decimal::ZERO, QuoteType::ASK),
+            PriceLevel::new(dec!(101), dec!(1), QuoteType::ASK),
+        ]));
+        assert_eq!(order_book.asks.keys().cloned().collect::<Vec<_>>(), vec![dec!(101)]);
+    }
+
     #[tokio::test]
     async fn test_out_of_order_updates() {
         let mut order_book = OrderBook::new();
@@ -388,10 +735,174 @@ mod tests {
             },
         ];
 
-        // order_book.update(&OrderBookUpdate::new(updates));
+        order_book.update(&OrderBookUpdate::snapshot(vec![]));
+        assert_eq!(order_book.update(&OrderBookUpdate::new(updates.clone())), UpdateOutcome::Applied);
 
         let first_bid = order_book.bids.iter().next_back().unwrap(); // Bids are in ascending order, so the last one is the highest
         assert_eq!(first_bid.0, &dec!(101), "The higher bid should come last in the BTreeMap");
         assert_eq!(first_bid.1, &dec!(5), "The quantity of the highest bid should be 5");
+
+        // Same levels as a snapshot
+        let mut order_book = OrderBook::new();
+        order_book.update(&OrderBookUpdate::snapshot(updates));
+        assert_eq!(order_book.best_bid(), Some((dec!(101), dec!(5))));
+        assert_eq!(order_book.bids.len(), 2);
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let mut order_book = OrderBook::new().with_precision(1, 2);
+        order_book.update_ask(dec!(102.0), dec!(2));
+        order_book.update_ask(dec!(101.0), dec!(1));
+        order_book.update_bid(dec!(99.0), dec!(4));
+        order_book.update_bid(dec!(100.0), dec!(3));
+
+        // asks ascending then bids descending
+        let expected = crc32fast::hash(b"101010010202001000300990400");
+        assert_eq!(order_book.checksum(), expected);
+        assert!(order_book.verify_checksum(expected).is_ok());
+
+        let mismatch = order_book.verify_checksum(expected + 1).unwrap_err();
+        assert_eq!(mismatch.computed, expected);
+        assert_eq!(order_book.state(), BookState::Stale, "Book should be stale after a checksum mismatch");
+        assert_eq!(order_book.checksum_stats(), ChecksumStats { passed: 1, failed: 1 });
+    }
+
+    #[test]
+    fn test_lifecycle_drops_deltas_before_snapshot() {
+        let mut order_book = OrderBook::new();
+        assert_eq!(order_book.state(), BookState::AwaitingSnapshot);
+
+        let delta = OrderBookUpdate::new(vec![PriceLevel::new(dec!(99), dec!(1), QuoteType::BID)]);
+        assert_eq!(order_book.update(&delta), UpdateOutcome::Dropped);
+        assert!(order_book.best_bid().is_none());
+
+        let snapshot = OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(dec!(100), dec!(2), QuoteType::BID),
+            PriceLevel::new(dec!(101), dec!(3), QuoteType::ASK),
+        ]);
+        assert_eq!(order_book.update(&snapshot), UpdateOutcome::Applied);
+        assert!(order_book.is_synced());
+        assert_eq!(order_book.best_bid(), Some((dec!(100), dec!(2))));
+
+        order_book.mark_stale();
+        assert_eq!(order_book.update(&delta), UpdateOutcome::Dropped);
+        assert_eq!(order_book.best_bid(), Some((dec!(100), dec!(2))));
+    }
+
+    #[test]
+    fn test_clear_awaits_next_snapshot() {
+        let mut order_book = OrderBook::new().with_pending_deltas(PendingDeltas::Buffer);
+        order_book.update(&OrderBookUpdate::snapshot(vec![PriceLevel::new(dec!(100), dec!(2), QuoteType::BID)]));
+        assert!(order_book.is_synced());
+
+        order_book.clear();
+        assert_eq!(order_book.state(), BookState::AwaitingSnapshot);
+        let delta = OrderBookUpdate::new(vec![PriceLevel::new(dec!(99), dec!(1), QuoteType::BID)]);
+        assert_eq!(order_book.update(&delta), UpdateOutcome::Buffered);
+        order_book.clear();
+        order_book.update(&OrderBookUpdate::snapshot(vec![PriceLevel::new(dec!(101), dec!(1), QuoteType::ASK)]));
+        // The delta buffered before clearing is gone
+        assert!(order_book.best_bid().is_none());
+    }
+
+    #[test]
+    fn test_lifecycle_buffers_deltas_until_snapshot() {
+        let mut order_book = OrderBook::new().with_pending_deltas(PendingDeltas::Buffer);
+        order_book.update_bid(dec!(90), dec!(9));
+
+        let delta = OrderBookUpdate::new(vec![PriceLevel::new(dec!(100), dec!(5), QuoteType::BID)]);
+        assert_eq!(order_book.update(&delta), UpdateOutcome::Buffered);
+
+        let snapshot = OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(dec!(100), dec!(2), QuoteType::BID),
+            PriceLevel::new(dec!(99), dec!(1), QuoteType::BID),
+        ]);
+        order_book.update(&snapshot);
+
+        // the snapshot replaced the whole side, then the buffered delta was replayed
+        assert!(!order_book.bids.contains_key(&dec!(90)));
+        assert_eq!(order_book.best_bid(), Some((dec!(100), dec!(5))));
+        assert_eq!(order_book.bids.len(), 2);
+    }
+
+    #[test]
+    fn test_buffered_deltas_follow_snapshot_sequence() {
+        let delta = |first, last, price| OrderBookUpdate::new(vec![PriceLevel::new(price, dec!(1), QuoteType::BID)])
+            .with_sequence(first, last);
+        let snapshot = OrderBookUpdate::snapshot(vec![PriceLevel::new(dec!(100), dec!(2), QuoteType::BID)])
+            .with_sequence(10, 10);
+
+        let mut order_book = OrderBook::new().with_pending_deltas(PendingDeltas::Buffer);
+        // Covered by the snapshot, straddling it, then following
+        order_book.update(&delta(8, 10, dec!(97)));
+        order_book.update(&delta(9, 12, dec!(98)));
+        order_book.update(&delta(13, 13, dec!(99)));
+        order_book.update(&snapshot);
+        assert!(order_book.is_synced());
+        assert_eq!(order_book.bids.keys().cloned().collect::<Vec<_>>(), vec![dec!(98), dec!(99), dec!(100)]);
+
+        // 11 and 12 were lost
+        let mut order_book = OrderBook::new().with_pending_deltas(PendingDeltas::Buffer);
+        order_book.update(&delta(13, 13, dec!(99)));
+        order_book.update(&delta(14, 14, dec!(98)));
+        assert_eq!(order_book.update(&snapshot), UpdateOutcome::Gap);
+        assert_eq!(order_book.state(), BookState::Stale);
+        assert_eq!(order_book.bids.keys().cloned().collect::<Vec<_>>(), vec![dec!(100)]);
+        // The next snapshot does not replay what was buffered before the gap
+        assert_eq!(order_book.update(&snapshot.clone().with_sequence(20, 20)), UpdateOutcome::Applied);
+        assert!(order_book.is_synced());
+        assert_eq!(order_book.bids.len(), 1);
+    }
+
+    #[test]
+    fn test_live_deltas_follow_sequence() {
+        let delta = |first, last, price| OrderBookUpdate::new(vec![PriceLevel::new(price, dec!(1), QuoteType::BID)])
+            .with_sequence(first, last);
+        let mut order_book = OrderBook::new();
+        order_book.update(&OrderBookUpdate::snapshot(vec![PriceLevel::new(dec!(100), dec!(2), QuoteType::BID)])
+            .with_sequence(10, 10));
+
+        assert_eq!(order_book.update(&delta(8, 10, dec!(97))), UpdateOutcome::Dropped);
+        assert_eq!(order_book.update(&delta(9, 12, dec!(98))), UpdateOutcome::Applied);
+        assert_eq!(order_book.update(&delta(13, 13, dec!(99))), UpdateOutcome::Applied);
+        // 14 was lost
+        assert_eq!(order_book.update(&delta(15, 15, dec!(96))), UpdateOutcome::Gap);
+        assert_eq!(order_book.state(), BookState::Stale);
+        assert_eq!(order_book.bids.keys().cloned().collect::<Vec<_>>(), vec![dec!(98), dec!(99), dec!(100)]);
+    }
+
+    #[test]
+    fn test_max_depth_truncates_out_of_scope_levels() {
+        let mut order_book = OrderBook::new().with_max_depth(2);
+        order_book.update(&OrderBookUpdate::snapshot(vec![
+            PriceLevel::new(dec!(100), dec!(1), QuoteType::BID),
+            PriceLevel::new(dec!(99), dec!(1), QuoteType::BID),
+            PriceLevel::new(dec!(101), dec!(1), QuoteType::ASK),
+            PriceLevel::new(dec!(102), dec!(1), QuoteType::ASK),
+        ]));
+
+        // better levels push the worst ones out of the subscribed depth
+        order_book.update(&OrderBookUpdate::new(vec![
+            PriceLevel::new(dec!(100.5), dec!(1), QuoteType::BID),
+            PriceLevel::new(dec!(100.8), dec!(1), QuoteType::ASK),
+        ]));
+
+        assert_eq!(order_book.bids.keys().cloned().collect::<Vec<_>>(), vec![dec!(100), dec!(100.5)]);
+        assert_eq!(order_book.asks.keys().cloned().collect::<Vec<_>>(), vec![dec!(100.8), dec!(101)]);
+    }
+
+    #[test]
+    fn test_execute_limit_rests_remainder() {
+        let mut order_book = OrderBook::new();
+        order_book.update_ask(dec!(101), dec!(1));
+        order_book.update_ask(dec!(102), dec!(2));
+        order_book.update_bid(dec!(99), dec!(1));
+
+        let remaining = order_book.execute_limit(dec!(101.5), dec!(3), QuoteType::BID);
+
+        assert_eq!(remaining, dec!(2), "Only the 101 level is within the limit");
+        assert_eq!(order_book.best_bid(), Some((dec!(101.5), dec!(2))));
+        assert_eq!(order_book.best_ask(), Some((dec!(102), dec!(2))));
     }
 }
@@ -7,10 +7,23 @@ use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 
 use crate::models::types::*;
+use crate::models::book::Book;
+use crate::models::order_book::{OrderBookUpdate as LevelUpdate, QuoteType};
+
+// A resting order, chained with the other orders of its level in time priority
+#[derive(Debug, Copy, Clone)]
+pub struct Order {
+    pub qty: Qty,
+    pub level_id: LevelIdx,
+    pub side: BuySell,
+    pub(crate) prev: Option<usize>,
+    pub(crate) next: Option<usize>,
+}
 
-struct Order {
-    qty: Qty,
-    level_id: LevelIdx
+impl Order {
+    pub fn new(qty: Qty, level_id: LevelIdx, side: BuySell) -> Self {
+        Order { qty, level_id, side, prev: None, next: None }
+    }
 }
 
 
@@ -53,7 +66,7 @@ impl<T: Default> Pool<T> {
     }
 
     pub fn free(&mut self, idx: usize) -> () {
-        self.free.remove(idx);
+        self.free.push(idx);
     }
 
 }
@@ -63,8 +76,9 @@ impl<T : Default> Index<usize> for Pool<T> {
         & self.allocated[idx]
     }
 }
+// Only for dense ids assigned locally, a venue's order ids would size it to the largest of them
 #[derive(Debug)]
-struct OidMap<T : Default>{
+pub struct OidMap<T : Default>{
     data: Vec<T>,
     index: Vec<usize>,
 }
@@ -90,15 +104,24 @@ impl<T: Default> OidMap<T> {
         &self.data[oid]
     }
 
-    // Optionally, implement removal if needed
-    pub fn remove(&mut self, oid: usize) {
-        self.data.remove(oid);
+    pub fn try_get(&self, oid: usize) -> Option<&T> {
+        self.data.get(oid)
+    }
 
+    pub fn get_mut(&mut self, oid: usize) -> &mut T {
+        &mut self.data[oid]
+    }
+
+    // Resets the slot, the other oids keep their position
+    pub fn remove(&mut self, oid: usize) {
+        if oid < self.data.len() {
+            self.data[oid] = T::default();
+        }
     }
 }
 #[derive(Debug)]
 pub struct OrderBook {
-    num_levels: u32,
+    num_levels: Option<usize>,
     oid_map: OidMap<Level>,  // this determines the levels somehow?
     // sorted_levels: Vec<PriceLevel>,
     bids: SortedLevels,
@@ -110,7 +133,7 @@ impl OrderBook {
 
     pub fn new() -> Self {
         OrderBook{
-            num_levels: 5,
+            num_levels: None,
             oid_map: OidMap::new(),
             bids: Vec::new(),
             asks: Vec::new(),
@@ -118,6 +141,33 @@ impl OrderBook {
         }
     }
 
+    // Same setting as order_book::OrderBook::with_max_depth: levels beyond the subscribed depth
+    // are discarded after each update
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.num_levels = Some(max_depth);
+        self
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.num_levels
+    }
+
+    fn truncate(&mut self, buy_sell: BuySell) {
+        if let Some(num_levels) = self.num_levels {
+            // sides are sorted best first, the worst level is the last one
+            loop {
+                let len = match buy_sell {
+                    BuySell::Buy => self.bids.len(),
+                    BuySell::Sell => self.asks.len(),
+                };
+                if len <= num_levels {
+                    break;
+                }
+                self.remove_price_level(len - 1, buy_sell);
+            }
+        }
+    }
+
     fn process_entries<K, V>(&mut self, entries: &HashMap<K, V>, side: BuySell) -> ()
         where
             K: ToPrimitive + std::fmt::Debug + Copy + Clone,
@@ -250,7 +300,46 @@ impl OrderBook {
         }
     }
 
+    fn remove_level(&mut self, price: Price, buy_sell: BuySell) {
+        let sorted_levels = match buy_sell {
+            BuySell::Buy => &self.bids,
+            BuySell::Sell => &self.asks,
+        };
+        if let Some(idx) = sorted_levels.iter().position(|p| p.price() == price) {
+            self.remove_price_level(idx, buy_sell);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.levels = Pool::<Level>::new();
+    }
+
+    // Like set_level, but a level crossing the other side removes its best levels first
     fn update_level(&mut self, level: &Level, buy_sell: BuySell) {
+        let own_side_empty = match buy_sell {
+            BuySell::Buy => self.bids.is_empty(),
+            BuySell::Sell => self.asks.is_empty(),
+        };
+        if !level.qty.value().is_zero() && !own_side_empty && self.cross_(level, buy_sell) {
+            // Improve or execute against the other side
+            self.remove_price_level(0, buy_sell.other_side());
+            // let remaining = self.execute(&level, buy_sell, opposite_side);
+            self.update_level(level, buy_sell);
+            return;
+        }
+        self.set_level(level, buy_sell);
+    }
+
+    // Sets the quantity of the level as the venue sent it, even when it crosses the other side
+    fn set_level(&mut self, level: &Level, buy_sell: BuySell) {
+        if level.qty.value().is_zero() {
+            // A zero quantity deletes the level
+            self.remove_level(level.price, buy_sell);
+            return;
+        }
+
         if self.asks.is_empty() && matches!(buy_sell, BuySell::Sell) {
             let level_idx = self.levels.alloc();
             // self.levels.get(level_idx).clone_from(&level); // Efficiently copy level data
@@ -273,17 +362,6 @@ impl OrderBook {
             return;
         }
 
-
-        {
-            if self.cross_(&level, buy_sell) {
-                // Improve or execute against the other side
-                self.remove_price_level(0, buy_sell.other_side());
-                // let remaining = self.execute(&level, buy_sell, opposite_side);
-                self.update_level(level, buy_sell);
-                return;
-            }
-        }
-
         let sorted_levels = match buy_sell {
             BuySell::Buy =>  &mut self.bids,
             BuySell::Sell => &mut self.asks,
@@ -315,6 +393,7 @@ impl OrderBook {
 
             let new_price_level = PriceLevel::new(level.price, LevelIdx(level_idx), buy_sell.clone());
             sorted_levels.insert(insert_index, new_price_level);
+            self.truncate(buy_sell);
         } else {
             // Update existing level quantity
 
@@ -339,6 +418,52 @@ impl OrderBook {
     }
 }
 
+fn buy_sell(quote_type: QuoteType) -> BuySell {
+    match quote_type {
+        QuoteType::BID => BuySell::Buy,
+        QuoteType::ASK => BuySell::Sell,
+    }
+}
+
+impl Book for OrderBook {
+    fn apply_snapshot(&mut self, snapshot: &LevelUpdate) {
+        OrderBook::clear(self);
+        self.apply_delta(snapshot);
+    }
+
+    fn apply_delta(&mut self, delta: &LevelUpdate) {
+        for price_level in delta.price_levels() {
+            self.set_level(
+                &Level::new(Price(price_level.price()), Qty(price_level.quantity())),
+                buy_sell(price_level.quote_type()),
+            );
+        }
+    }
+
+    fn best_bid(&self) -> Option<(Decimal, Decimal)> {
+        self.levels(QuoteType::BID).next()
+    }
+
+    fn best_ask(&self) -> Option<(Decimal, Decimal)> {
+        self.levels(QuoteType::ASK).next()
+    }
+
+    // Both sides are sorted best first
+    fn levels(&self, side: QuoteType) -> Box<dyn Iterator<Item = (Decimal, Decimal)> + '_> {
+        let sorted_levels = match buy_sell(side) {
+            BuySell::Buy => &self.bids,
+            BuySell::Sell => &self.asks,
+        };
+        Box::new(sorted_levels.iter().map(move |price_level| {
+            (price_level.price().value(), self.get_qty(price_level).value())
+        }))
+    }
+
+    fn clear(&mut self) {
+        OrderBook::clear(self);
+    }
+}
+
 impl fmt::Display for OrderBook {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "{}", "Order Book Table");
@@ -537,6 +662,25 @@ mod tests {
 
 
 
+    #[test]
+    fn test_max_depth() {
+        let mut order_book = OrderBook::new().with_max_depth(2);
+
+        for bid in vec![(100, 1), (98, 1), (99, 1), (101, 1)] {
+            order_book.update_level(&Level::from_tuple(bid), BuySell::Buy);
+        }
+        for ask in vec![(103, 1), (105, 1), (102, 1)] {
+            order_book.update_level(&Level::from_tuple(ask), BuySell::Sell);
+        }
+
+        let (bids, asks) = order_book.get_repr();
+        assert_eq!(bids.len(), 2, "Only the 2 best bids should be kept");
+        assert!(bids.contains_key(&Price(dec!(101))) && bids.contains_key(&Price(dec!(100))));
+        assert_eq!(asks.len(), 2, "Only the 2 best asks should be kept");
+        assert!(asks.contains_key(&Price(dec!(102))) && asks.contains_key(&Price(dec!(103))));
+    }
+
+
     // #[tokio::test]
     // async fn test_order_crossing() {
     //     let mut order_book = OrderBook::new();
@@ -0,0 +1,380 @@
+// Market by order (L3) book
+//
+// Orders are stored in a HashMap keyed by order id, venue ids are sparse and can be huge, and
+// chained per price level in time priority (head is the oldest order). Levels live in a Pool
+// and are referenced from sorted vectors, best price first, like order_book_2.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::error::Error;
+use rust_decimal::Decimal;
+
+use crate::models::order_book_2::{Order, Pool};
+use crate::models::types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderError {
+    DuplicateOrderId(usize),
+    UnknownOrderId(usize),
+    InvalidQty(usize),
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderError::DuplicateOrderId(oid) => write!(f, "order {} already exists", oid),
+            OrderError::UnknownOrderId(oid) => write!(f, "order {} not found", oid),
+            OrderError::InvalidQty(oid) => write!(f, "order {} has a non positive quantity", oid),
+        }
+    }
+}
+
+impl Error for OrderError {}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct OrderLevel {
+    price: Price,
+    qty: Qty,
+    order_count: usize,
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+/// Aggregated view of a price level.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LevelSummary {
+    pub price: Price,
+    pub qty: Qty,
+    pub order_count: usize,
+}
+
+/// What is ahead of an order in its level's queue.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QueuePosition {
+    pub orders_ahead: usize,
+    pub qty_ahead: Qty,
+}
+
+#[derive(Debug)]
+pub struct OrderBook {
+    orders: HashMap<usize, Order>,
+    levels: Pool<OrderLevel>,
+    bids: SortedLevels,
+    asks: SortedLevels,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        OrderBook {
+            orders: HashMap::new(),
+            levels: Pool::new(),
+            bids: Vec::new(),
+            asks: Vec::new(),
+        }
+    }
+
+    pub fn order(&self, oid: usize) -> Option<&Order> {
+        self.orders.get(&oid)
+    }
+
+    fn order_mut(&mut self, oid: usize) -> &mut Order {
+        self.orders.get_mut(&oid).expect("linked order must exist")
+    }
+
+    fn sorted_levels(&self, side: BuySell) -> &SortedLevels {
+        match side {
+            BuySell::Buy => &self.bids,
+            BuySell::Sell => &self.asks,
+        }
+    }
+
+    // Bids are sorted descending and asks ascending, so the best price is always first
+    fn search(&self, side: BuySell, price: Price) -> Result<usize, usize> {
+        let sorted_levels = self.sorted_levels(side);
+        match side {
+            BuySell::Buy => sorted_levels.binary_search_by(|p| price.value().cmp(&p.price().value())),
+            BuySell::Sell => sorted_levels.binary_search_by(|p| p.price().value().cmp(&price.value())),
+        }
+    }
+
+    fn find_or_insert_level(&mut self, side: BuySell, price: Price) -> usize {
+        match self.search(side, price) {
+            Ok(pos) => self.sorted_levels(side)[pos].level_idx().value(),
+            Err(pos) => {
+                let level_idx = self.levels.alloc();
+                *self.levels.get(level_idx) = OrderLevel { price, ..OrderLevel::default() };
+                let price_level = PriceLevel::new(price, LevelIdx(level_idx), side);
+                match side {
+                    BuySell::Buy => self.bids.insert(pos, price_level),
+                    BuySell::Sell => self.asks.insert(pos, price_level),
+                }
+                level_idx
+            }
+        }
+    }
+
+    fn remove_level(&mut self, side: BuySell, price: Price) {
+        if let Ok(pos) = self.search(side, price) {
+            let price_level = match side {
+                BuySell::Buy => self.bids.remove(pos),
+                BuySell::Sell => self.asks.remove(pos),
+            };
+            self.levels.free(price_level.level_idx().value());
+        }
+    }
+
+    // Appends the order at the back of its level's queue
+    fn link(&mut self, oid: usize, side: BuySell, price: Price, qty: Qty) {
+        let level_idx = self.find_or_insert_level(side, price);
+        let tail = self.levels[level_idx].tail;
+
+        let mut order = Order::new(qty, LevelIdx(level_idx), side);
+        order.prev = tail;
+        self.orders.insert(oid, order);
+        if let Some(tail) = tail {
+            self.order_mut(tail).next = Some(oid);
+        }
+
+        let level = self.levels.get(level_idx);
+        if level.head.is_none() {
+            level.head = Some(oid);
+        }
+        level.tail = Some(oid);
+        level.qty = Qty(level.qty.value() + qty.value());
+        level.order_count += 1;
+    }
+
+    // Takes the order out of its level's queue, the level is removed once empty
+    fn unlink(&mut self, oid: usize) -> Result<Order, OrderError> {
+        let order = *self.order(oid).ok_or(OrderError::UnknownOrderId(oid))?;
+        let level_idx = order.level_id.value();
+
+        match order.prev {
+            Some(prev) => self.order_mut(prev).next = order.next,
+            None => self.levels.get(level_idx).head = order.next,
+        }
+        match order.next {
+            Some(next) => self.order_mut(next).prev = order.prev,
+            None => self.levels.get(level_idx).tail = order.prev,
+        }
+        self.orders.remove(&oid);
+
+        let level = self.levels.get(level_idx);
+        level.qty = Qty(level.qty.value() - order.qty.value());
+        level.order_count -= 1;
+        if level.order_count == 0 {
+            let price = level.price;
+            self.remove_level(order.side, price);
+        }
+        Ok(order)
+    }
+
+    pub fn add(&mut self, oid: usize, side: BuySell, price: Price, qty: Qty) -> Result<(), OrderError> {
+        if qty.value() <= Decimal::ZERO {
+            return Err(OrderError::InvalidQty(oid));
+        }
+        if self.order(oid).is_some() {
+            return Err(OrderError::DuplicateOrderId(oid));
+        }
+        self.link(oid, side, price, qty);
+        Ok(())
+    }
+
+    pub fn cancel(&mut self, oid: usize) -> Result<Order, OrderError> {
+        self.unlink(oid)
+    }
+
+    // A quantity decrease at the same price keeps the order's priority, any other change sends
+    // it to the back of the queue of its (new) level.
+    pub fn modify(&mut self, oid: usize, price: Price, qty: Qty) -> Result<(), OrderError> {
+        let order = *self.order(oid).ok_or(OrderError::UnknownOrderId(oid))?;
+        if qty.value().is_zero() {
+            return self.cancel(oid).map(|_| ());
+        }
+        if qty.value() < Decimal::ZERO {
+            return Err(OrderError::InvalidQty(oid));
+        }
+
+        let level_idx = order.level_id.value();
+        if self.levels[level_idx].price == price && qty.value() <= order.qty.value() {
+            let reduced_by = order.qty.value() - qty.value();
+            self.order_mut(oid).qty = qty;
+            let level = self.levels.get(level_idx);
+            level.qty = Qty(level.qty.value() - reduced_by);
+        } else {
+            self.unlink(oid)?;
+            self.link(oid, order.side, price, qty);
+        }
+        Ok(())
+    }
+
+    // Fills up to `qty` of the order and returns the quantity left on it
+    pub fn execute(&mut self, oid: usize, qty: Qty) -> Result<Qty, OrderError> {
+        let order = *self.order(oid).ok_or(OrderError::UnknownOrderId(oid))?;
+        if qty.value() <= Decimal::ZERO {
+            return Err(OrderError::InvalidQty(oid));
+        }
+
+        if qty.value() >= order.qty.value() {
+            self.unlink(oid)?;
+            Ok(Qty(Decimal::ZERO))
+        } else {
+            let remaining = Qty(order.qty.value() - qty.value());
+            self.order_mut(oid).qty = remaining;
+            let level = self.levels.get(order.level_id.value());
+            level.qty = Qty(level.qty.value() - qty.value());
+            Ok(remaining)
+        }
+    }
+
+    fn summary(&self, level_idx: usize) -> LevelSummary {
+        let level = &self.levels[level_idx];
+        LevelSummary { price: level.price, qty: level.qty, order_count: level.order_count }
+    }
+
+    // O(1): the order points to its level
+    pub fn level_of(&self, oid: usize) -> Option<LevelSummary> {
+        self.order(oid).map(|order| self.summary(order.level_id.value()))
+    }
+
+    pub fn level(&self, side: BuySell, price: Price) -> Option<LevelSummary> {
+        self.search(side, price).ok()
+            .map(|pos| self.summary(self.sorted_levels(side)[pos].level_idx().value()))
+    }
+
+    pub fn best(&self, side: BuySell) -> Option<LevelSummary> {
+        self.levels(side).next()
+    }
+
+    pub fn levels(&self, side: BuySell) -> impl Iterator<Item = LevelSummary> + '_ {
+        self.sorted_levels(side).iter().map(move |p| self.summary(p.level_idx().value()))
+    }
+
+    // Oldest order at the best price of a side, the next one to be matched
+    pub fn best_order(&self, side: BuySell) -> Option<usize> {
+        self.sorted_levels(side).first()
+            .and_then(|p| self.levels[p.level_idx().value()].head)
+    }
+
+    // Order ids of a level, oldest first
+    pub fn queue(&self, side: BuySell, price: Price) -> Vec<usize> {
+        let mut oids = Vec::new();
+        if let Ok(pos) = self.search(side, price) {
+            let mut next = self.levels[self.sorted_levels(side)[pos].level_idx().value()].head;
+            while let Some(oid) = next {
+                oids.push(oid);
+                next = self.order(oid).and_then(|order| order.next);
+            }
+        }
+        oids
+    }
+
+    pub fn queue_position(&self, oid: usize) -> Option<QueuePosition> {
+        let order = self.order(oid)?;
+        let mut position = QueuePosition { orders_ahead: 0, qty_ahead: Qty(Decimal::ZERO) };
+        let mut prev = order.prev;
+        while let Some(prev_oid) = prev {
+            let ahead = self.order(prev_oid)?;
+            position.orders_ahead += 1;
+            position.qty_ahead = Qty(position.qty_ahead.value() + ahead.qty.value());
+            prev = ahead.prev;
+        }
+        Some(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn price(p: Decimal) -> Price {
+        Price(p)
+    }
+
+    fn qty(q: Decimal) -> Qty {
+        Qty(q)
+    }
+
+    #[test]
+    fn test_add_aggregates_levels() {
+        let mut book = OrderBook::new();
+        book.add(1, BuySell::Buy, price(dec!(100)), qty(dec!(1))).unwrap();
+        book.add(2, BuySell::Buy, price(dec!(100)), qty(dec!(2))).unwrap();
+        book.add(3, BuySell::Buy, price(dec!(101)), qty(dec!(5))).unwrap();
+        book.add(4, BuySell::Sell, price(dec!(103)), qty(dec!(1))).unwrap();
+        book.add(5, BuySell::Sell, price(dec!(102)), qty(dec!(1))).unwrap();
+
+        let best_bid = book.best(BuySell::Buy).unwrap();
+        assert_eq!(best_bid, LevelSummary { price: price(dec!(101)), qty: qty(dec!(5)), order_count: 1 });
+        assert_eq!(book.best(BuySell::Sell).unwrap().price, price(dec!(102)));
+
+        let level = book.level_of(2).unwrap();
+        assert_eq!(level, LevelSummary { price: price(dec!(100)), qty: qty(dec!(3)), order_count: 2 });
+        assert_eq!(book.add(2, BuySell::Buy, price(dec!(99)), qty(dec!(1))), Err(OrderError::DuplicateOrderId(2)));
+    }
+
+    #[test]
+    fn test_fifo_queue_and_position() {
+        let mut book = OrderBook::new();
+        for oid in 1..=3 {
+            book.add(oid, BuySell::Sell, price(dec!(50)), qty(dec!(2))).unwrap();
+        }
+        assert_eq!(book.queue(BuySell::Sell, price(dec!(50))), vec![1, 2, 3]);
+        assert_eq!(book.queue_position(3), Some(QueuePosition { orders_ahead: 2, qty_ahead: qty(dec!(4)) }));
+
+        // reducing keeps priority, increasing loses it
+        book.modify(1, price(dec!(50)), qty(dec!(1))).unwrap();
+        assert_eq!(book.queue(BuySell::Sell, price(dec!(50))), vec![1, 2, 3]);
+        book.modify(2, price(dec!(50)), qty(dec!(3))).unwrap();
+        assert_eq!(book.queue(BuySell::Sell, price(dec!(50))), vec![1, 3, 2]);
+        assert_eq!(book.level(BuySell::Sell, price(dec!(50))).unwrap().qty, qty(dec!(6)));
+
+        book.cancel(3).unwrap();
+        assert_eq!(book.queue(BuySell::Sell, price(dec!(50))), vec![1, 2]);
+        assert_eq!(book.queue_position(2), Some(QueuePosition { orders_ahead: 1, qty_ahead: qty(dec!(1)) }));
+        assert_eq!(book.cancel(3).unwrap_err(), OrderError::UnknownOrderId(3));
+    }
+
+    #[test]
+    fn test_venue_order_ids() {
+        // Venue ids are large and sparse, nothing is allocated up to them
+        let mut book = OrderBook::new();
+        let oid = usize::MAX - 1;
+        book.add(oid, BuySell::Buy, price(dec!(100)), qty(dec!(1))).unwrap();
+        book.add(42, BuySell::Buy, price(dec!(100)), qty(dec!(2))).unwrap();
+        assert_eq!(book.queue(BuySell::Buy, price(dec!(100))), vec![oid, 42]);
+        book.cancel(oid).unwrap();
+        assert!(book.order(oid).is_none());
+        assert_eq!(book.level_of(42).unwrap().qty, qty(dec!(2)));
+    }
+
+    #[test]
+    fn test_execute_removes_filled_orders_and_empty_levels() {
+        let mut book = OrderBook::new();
+        book.add(7, BuySell::Buy, price(dec!(10)), qty(dec!(4))).unwrap();
+        book.add(8, BuySell::Buy, price(dec!(9)), qty(dec!(1))).unwrap();
+
+        assert_eq!(book.execute(7, qty(dec!(1.5))), Ok(qty(dec!(2.5))));
+        assert_eq!(book.best(BuySell::Buy).unwrap().qty, qty(dec!(2.5)));
+
+        assert_eq!(book.execute(7, qty(dec!(2.5))), Ok(qty(Decimal::ZERO)));
+        assert!(book.order(7).is_none());
+        assert_eq!(book.best(BuySell::Buy).unwrap().price, price(dec!(9)));
+
+        // the freed level slot is reused
+        book.add(9, BuySell::Buy, price(dec!(11)), qty(dec!(1))).unwrap();
+        assert_eq!(book.levels(BuySell::Buy).map(|l| l.price).collect::<Vec<_>>(), vec![price(dec!(11)), price(dec!(9))]);
+    }
+
+    #[test]
+    fn test_modify_price_moves_order() {
+        let mut book = OrderBook::new();
+        book.add(1, BuySell::Buy, price(dec!(10)), qty(dec!(1))).unwrap();
+        book.add(2, BuySell::Buy, price(dec!(11)), qty(dec!(1))).unwrap();
+        book.modify(1, price(dec!(11)), qty(dec!(1))).unwrap();
+
+        assert!(book.level(BuySell::Buy, price(dec!(10))).is_none());
+        assert_eq!(book.queue(BuySell::Buy, price(dec!(11))), vec![2, 1]);
+    }
+}
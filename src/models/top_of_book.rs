@@ -0,0 +1,252 @@
+// Best bid and offer published by the venues' ticker channels, and a monitor checking them
+// against the book reconstructed from the depth channels.
+//
+// Both come from different channels, so they disagree for a while after each change. Only a
+// divergence lasting longer than the grace period is reported.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use rust_decimal::Decimal;
+
+use crate::models::book_registry::BookKey;
+use crate::models::order_book::OrderBook;
+use crate::quote::Exchange;
+
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopOfBook {
+    pub exchange: Exchange,
+    // Venue spelling, e.g. "XBT/USD" or "BTCUSDT"
+    pub symbol: String,
+    pub bid_price: Decimal,
+    pub bid_qty: Decimal,
+    pub ask_price: Decimal,
+    pub ask_qty: Decimal,
+}
+
+impl TopOfBook {
+    pub fn key(&self) -> BookKey {
+        BookKey::new(self.exchange, &self.symbol)
+    }
+
+    pub fn mid(&self) -> Decimal {
+        (self.bid_price + self.ask_price) / Decimal::TWO
+    }
+}
+
+impl fmt::Display for TopOfBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}@{} / {}@{}", self.key(), self.bid_qty, self.bid_price, self.ask_qty, self.ask_price)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    BidPrice,
+    BidQty,
+    AskPrice,
+    AskQty,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::BidPrice => write!(f, "bid price"),
+            Field::BidQty => write!(f, "bid qty"),
+            Field::AskPrice => write!(f, "ask price"),
+            Field::AskQty => write!(f, "ask qty"),
+        }
+    }
+}
+
+/// A field of the ticker that the book disagrees with, `book` is None for an empty side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub field: Field,
+    pub ticker: Decimal,
+    pub book: Option<Decimal>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.book {
+            Some(book) => write!(f, "{} ticker {} book {}", self.field, self.ticker, book),
+            None => write!(f, "{} ticker {} book empty", self.field, self.ticker),
+        }
+    }
+}
+
+fn compare_side(price_field: Field, qty_field: Field, price: Decimal, qty: Decimal,
+                best: Option<(Decimal, Decimal)>, divergences: &mut Vec<Divergence>) {
+    match best {
+        Some((book_price, _)) if book_price != price =>
+            divergences.push(Divergence { field: price_field, ticker: price, book: Some(book_price) }),
+        // Quantities are only comparable at the same price
+        Some((_, book_qty)) if book_qty != qty =>
+            divergences.push(Divergence { field: qty_field, ticker: qty, book: Some(book_qty) }),
+        Some(_) => {},
+        None => divergences.push(Divergence { field: price_field, ticker: price, book: None }),
+    }
+}
+
+// Fields of the ticker the best levels of the book disagree with
+pub fn compare(top: &TopOfBook, book: &OrderBook) -> Vec<Divergence> {
+    let mut divergences = Vec::new();
+    compare_side(Field::BidPrice, Field::BidQty, top.bid_price, top.bid_qty, book.best_bid(), &mut divergences);
+    compare_side(Field::AskPrice, Field::AskQty, top.ask_price, top.ask_qty, book.best_ask(), &mut divergences);
+    divergences
+}
+
+/// A divergence that outlasted the grace period, reported once per episode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergenceReport {
+    pub key: BookKey,
+    pub divergences: Vec<Divergence>,
+    // First check that diverged, nanoseconds since epoch
+    pub since_ns: u64,
+    pub duration: Duration,
+}
+
+impl fmt::Display for DivergenceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ticker and book diverge for {:?}:", self.key, self.duration)?;
+        for divergence in &self.divergences {
+            write!(f, " {};", divergence)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConsistencyStats {
+    pub checks: u64,
+    // Checks where the ticker and the book disagreed, within the grace period or not
+    pub divergent: u64,
+    pub reported: u64,
+}
+
+impl fmt::Display for ConsistencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} checks, {} divergent, {} reported", self.checks, self.divergent, self.reported)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Episode {
+    since_ns: u64,
+    reported: bool,
+}
+
+#[derive(Debug)]
+pub struct ConsistencyMonitor {
+    grace: Duration,
+    episodes: HashMap<BookKey, Episode>,
+    stats: HashMap<BookKey, ConsistencyStats>,
+}
+
+impl Default for ConsistencyMonitor {
+    fn default() -> Self {
+        ConsistencyMonitor::new()
+    }
+}
+
+impl ConsistencyMonitor {
+    pub fn new() -> Self {
+        ConsistencyMonitor { grace: DEFAULT_GRACE, episodes: HashMap::new(), stats: HashMap::new() }
+    }
+
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    // Checks the last ticker of a venue against its book, at each ticker or book update.
+    // A book that is not synced is not checked.
+    pub fn check(&mut self, top: &TopOfBook, book: &OrderBook, now_ns: u64) -> Option<DivergenceReport> {
+        if !book.is_synced() {
+            return None;
+        }
+        let key = top.key();
+        let stats = self.stats.entry(key.clone()).or_default();
+        stats.checks += 1;
+
+        let divergences = compare(top, book);
+        if divergences.is_empty() {
+            self.episodes.remove(&key);
+            return None;
+        }
+        stats.divergent += 1;
+
+        let episode = self.episodes.entry(key.clone()).or_insert(Episode { since_ns: now_ns, reported: false });
+        let duration = Duration::from_nanos(now_ns.saturating_sub(episode.since_ns));
+        if episode.reported || duration < self.grace {
+            return None;
+        }
+        episode.reported = true;
+        stats.reported += 1;
+        Some(DivergenceReport { key, divergences, since_ns: episode.since_ns, duration })
+    }
+
+    pub fn stats(&self, key: &BookKey) -> ConsistencyStats {
+        self.stats.get(key).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn top(bid_price: Decimal, ask_price: Decimal) -> TopOfBook {
+        TopOfBook {
+            exchange: Exchange::Binance,
+            symbol: "BTCUSDT".to_string(),
+            bid_price,
+            bid_qty: dec!(1),
+            ask_price,
+            ask_qty: dec!(2),
+        }
+    }
+
+    fn book() -> OrderBook {
+        OrderBook::from_levels(&[(dec!(100), dec!(1))], &[(dec!(101), dec!(3))])
+    }
+
+    #[test]
+    fn test_compare() {
+        let book = book();
+        assert_eq!(compare(&top(dec!(100), dec!(101)), &book), vec![
+            Divergence { field: Field::AskQty, ticker: dec!(2), book: Some(dec!(3)) },
+        ]);
+        assert_eq!(compare(&top(dec!(99), dec!(101)), &book)[0],
+                   Divergence { field: Field::BidPrice, ticker: dec!(99), book: Some(dec!(100)) });
+        assert_eq!(compare(&top(dec!(100), dec!(101)), &OrderBook::new()).len(), 2);
+    }
+
+    #[test]
+    fn test_report_after_grace() {
+        let book = book();
+        let mut monitor = ConsistencyMonitor::new().with_grace(Duration::from_millis(100));
+        let stale = top(dec!(99), dec!(101));
+
+        assert_eq!(monitor.check(&stale, &book, 0), None);
+        assert_eq!(monitor.check(&stale, &book, 50_000_000), None);
+        let report = monitor.check(&stale, &book, 150_000_000).unwrap();
+        assert_eq!(report.since_ns, 0);
+        assert_eq!(report.duration, Duration::from_millis(150));
+        assert_eq!(report.divergences.len(), 2);
+        // Once per episode
+        assert_eq!(monitor.check(&stale, &book, 300_000_000), None);
+
+        // Agreement ends the episode
+        let mut consistent = top(dec!(100), dec!(101));
+        consistent.ask_qty = dec!(3);
+        assert_eq!(monitor.check(&consistent, &book, 400_000_000), None);
+        assert_eq!(monitor.check(&stale, &book, 500_000_000), None);
+
+        let stats = monitor.stats(&stale.key());
+        assert_eq!(stats, ConsistencyStats { checks: 6, divergent: 5, reported: 1 });
+    }
+}
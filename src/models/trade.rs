@@ -0,0 +1,323 @@
+// Executions of the venues and the tape of each symbol, joined with book diffs to tell
+// executed liquidity from cancelled liquidity.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use rust_decimal::Decimal;
+
+use crate::models::book::Book;
+use crate::models::book_registry::BookKey;
+use crate::models::diff::{diff, SideDiff};
+use crate::models::order_book::QuoteType;
+use crate::quote::Exchange;
+
+// Trades kept per symbol by default, oldest are dropped first
+pub const DEFAULT_TAPE_CAPACITY: usize = 10_000;
+
+/// Side of the order that took liquidity: a buy executes against asks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aggressor {
+    Buy,
+    Sell,
+}
+
+impl Aggressor {
+    // Side of the book the trade executed against
+    pub fn resting_side(&self) -> QuoteType {
+        match self {
+            Aggressor::Buy => QuoteType::ASK,
+            Aggressor::Sell => QuoteType::BID,
+        }
+    }
+}
+
+impl fmt::Display for Aggressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggressor::Buy => write!(f, "BUY"),
+            Aggressor::Sell => write!(f, "SELL"),
+        }
+    }
+}
+
+/// Trade id of the venue. Binance numbers aggregate trades apart from the trades they group,
+/// the two never identify the same execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TradeId {
+    Trade(u64),
+    Aggregate(u64),
+}
+
+impl fmt::Display for TradeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradeId::Trade(id) => write!(f, "{}", id),
+            TradeId::Aggregate(id) => write!(f, "agg {}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trade {
+    pub exchange: Exchange,
+    // Venue spelling, e.g. "BTC/USD" or "BTCUSDT"
+    pub symbol: String,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub aggressor: Aggressor,
+    pub trade_id: TradeId,
+    // Execution time at the venue, nanoseconds since epoch
+    pub exchange_ns: u64,
+    // Local receive time, nanoseconds since epoch
+    pub received_ns: u64,
+}
+
+impl Trade {
+    pub fn key(&self) -> BookKey {
+        BookKey::new(self.exchange, &self.symbol)
+    }
+}
+
+impl fmt::Display for Trade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} #{} {} {}@{}", self.key(), self.trade_id, self.aggressor, self.qty, self.price)
+    }
+}
+
+/// Trades of one symbol in execution time order, a trade id is only kept once.
+#[derive(Debug, Clone)]
+pub struct TradeTape {
+    trades: VecDeque<Trade>,
+    capacity: usize,
+}
+
+impl Default for TradeTape {
+    fn default() -> Self {
+        TradeTape::new()
+    }
+}
+
+impl TradeTape {
+    pub fn new() -> Self {
+        TradeTape { trades: VecDeque::new(), capacity: DEFAULT_TAPE_CAPACITY }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    pub fn last(&self) -> Option<&Trade> {
+        self.trades.back()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Trade> {
+        self.trades.iter()
+    }
+
+    // False for a trade already on the tape. Trades received late are inserted in place.
+    pub fn push(&mut self, trade: Trade) -> bool {
+        let position = self.trades.partition_point(|other| other.exchange_ns <= trade.exchange_ns);
+        let same_time = self.trades.range(..position).rev().take_while(|other| other.exchange_ns == trade.exchange_ns);
+        if same_time.chain(self.trades.range(position..)).any(|other| other.trade_id == trade.trade_id) {
+            return false;
+        }
+        self.trades.insert(position, trade);
+        while self.trades.len() > self.capacity {
+            self.trades.pop_front();
+        }
+        true
+    }
+
+    // Trades executed in (from_ns, to_ns], the interval between two book updates
+    pub fn between(&self, from_ns: u64, to_ns: u64) -> impl Iterator<Item = &Trade> {
+        let start = self.trades.partition_point(|trade| trade.exchange_ns <= from_ns);
+        let end = self.trades.partition_point(|trade| trade.exchange_ns <= to_ns);
+        self.trades.range(start..end.max(start))
+    }
+
+    // Quantity executed at each price of a side in (from_ns, to_ns]
+    pub fn executed_between(&self, from_ns: u64, to_ns: u64, side: QuoteType) -> BTreeMap<Decimal, Decimal> {
+        let mut executed = BTreeMap::new();
+        for trade in self.between(from_ns, to_ns).filter(|trade| trade.aggressor.resting_side() == side) {
+            *executed.entry(trade.price).or_insert(Decimal::ZERO) += trade.qty;
+        }
+        executed
+    }
+
+    // Splits the liquidity that left the levels between two books, updated at from_ns and
+    // to_ns, into executions and cancellations
+    pub fn attribute<S: Book + ?Sized, T: Book + ?Sized>(&self, before: &S, after: &T, from_ns: u64, to_ns: u64) -> Vec<Depletion> {
+        let book_diff = diff(before, after);
+        let mut depletions = Vec::new();
+        for (side, side_diff) in [(QuoteType::BID, &book_diff.bids), (QuoteType::ASK, &book_diff.asks)] {
+            let executed = self.executed_between(from_ns, to_ns, side);
+            let previous: BTreeMap<Decimal, Decimal> = before.levels(side).collect();
+            for (price, depleted) in depleted_levels(side, side_diff, &previous) {
+                let executed = executed.get(&price).copied().unwrap_or(Decimal::ZERO).min(depleted);
+                depletions.push(Depletion { side, price, depleted, executed });
+            }
+        }
+        depletions
+    }
+}
+
+// Decreased and removed levels with the quantity they lost, best price first
+fn depleted_levels(side: QuoteType, side_diff: &SideDiff, previous: &BTreeMap<Decimal, Decimal>) -> Vec<(Decimal, Decimal)> {
+    let decreased = side_diff.changed.iter()
+        .filter(|(_, before, after)| after < before)
+        .map(|&(price, before, after)| (price, before - after));
+    let removed = side_diff.removed.iter()
+        .filter_map(|price| previous.get(price).map(|&qty| (*price, qty)));
+    let mut levels: Vec<(Decimal, Decimal)> = decreased.chain(removed).collect();
+    match side {
+        QuoteType::BID => levels.sort_by(|a, b| b.0.cmp(&a.0)),
+        QuoteType::ASK => levels.sort_by(|a, b| a.0.cmp(&b.0)),
+    }
+    levels
+}
+
+/// Quantity that left a level between two book updates, `executed` of it traded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Depletion {
+    pub side: QuoteType,
+    pub price: Decimal,
+    pub depleted: Decimal,
+    pub executed: Decimal,
+}
+
+impl Depletion {
+    pub fn cancelled(&self) -> Decimal {
+        self.depleted - self.executed
+    }
+}
+
+impl fmt::Display for Depletion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} -{} (executed {}, cancelled {})", self.side, self.price, self.depleted, self.executed, self.cancelled())
+    }
+}
+
+/// A tape per venue and symbol.
+#[derive(Debug, Default)]
+pub struct TradeTapes {
+    tapes: HashMap<BookKey, TradeTape>,
+    capacity: Option<usize>,
+}
+
+impl TradeTapes {
+    pub fn new() -> Self {
+        TradeTapes::default()
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    pub fn push(&mut self, trade: Trade) -> bool {
+        let capacity = self.capacity;
+        self.tapes.entry(trade.key())
+            .or_insert_with(|| match capacity {
+                Some(capacity) => TradeTape::new().with_capacity(capacity),
+                None => TradeTape::new(),
+            })
+            .push(trade)
+    }
+
+    pub fn get(&self, exchange: Exchange, symbol: &str) -> Option<&TradeTape> {
+        self.tapes.get(&BookKey::new(exchange, symbol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::models::order_book::{OrderBook, OrderBookUpdate, PriceLevel};
+
+    fn trade(trade_id: u64, exchange_ns: u64, price: Decimal, qty: Decimal, aggressor: Aggressor) -> Trade {
+        Trade {
+            exchange: Exchange::Kraken,
+            symbol: "BTC/USD".to_string(),
+            price,
+            qty,
+            aggressor,
+            trade_id: TradeId::Trade(trade_id),
+            exchange_ns,
+            received_ns: exchange_ns + 1_000,
+        }
+    }
+
+    #[test]
+    fn test_tape_order_and_window() {
+        let mut tape = TradeTape::new().with_capacity(3);
+        assert!(tape.push(trade(1, 100, dec!(10), dec!(1), Aggressor::Buy)));
+        assert!(tape.push(trade(3, 300, dec!(10), dec!(1), Aggressor::Buy)));
+        // Late and duplicated trades
+        assert!(tape.push(trade(2, 200, dec!(10), dec!(1), Aggressor::Sell)));
+        assert!(!tape.push(trade(3, 300, dec!(10), dec!(1), Aggressor::Buy)));
+        let ids: Vec<TradeId> = tape.iter().map(|trade| trade.trade_id).collect();
+        assert_eq!(ids, vec![TradeId::Trade(1), TradeId::Trade(2), TradeId::Trade(3)]);
+
+        let ids: Vec<TradeId> = tape.between(100, 300).map(|trade| trade.trade_id).collect();
+        assert_eq!(ids, vec![TradeId::Trade(2), TradeId::Trade(3)]);
+        assert_eq!(tape.between(300, 100).count(), 0);
+
+        assert!(tape.push(trade(4, 400, dec!(10), dec!(1), Aggressor::Buy)));
+        assert_eq!(tape.len(), 3);
+        assert_eq!(tape.iter().next().unwrap().trade_id, TradeId::Trade(2));
+    }
+
+    #[test]
+    fn test_aggregate_ids_apart_from_trade_ids() {
+        let mut tapes = TradeTapes::new();
+        assert!(tapes.push(trade(7, 100, dec!(10), dec!(1), Aggressor::Buy)));
+        let aggregate = Trade { trade_id: TradeId::Aggregate(7), ..trade(7, 100, dec!(10), dec!(2), Aggressor::Buy) };
+        assert!(tapes.push(aggregate.clone()));
+        assert!(!tapes.push(aggregate));
+        assert_eq!(tapes.get(Exchange::Kraken, "BTC/USD").unwrap().len(), 2);
+        assert_eq!(tapes.get(Exchange::Kraken, "BTC/USD").unwrap().last().unwrap().to_string(),
+                   "Kraken:BTC/USD #agg 7 BUY 2@10");
+    }
+
+    #[test]
+    fn test_attribute_depletion() {
+        let level = |price, qty, quote_type| PriceLevel::new(price, qty, quote_type);
+        let mut before = OrderBook::new();
+        before.update(&OrderBookUpdate::snapshot(vec![
+            level(dec!(100), dec!(5), QuoteType::BID),
+            level(dec!(101), dec!(2), QuoteType::ASK),
+            level(dec!(102), dec!(4), QuoteType::ASK),
+        ]));
+        let mut after = before.clone();
+        after.update(&OrderBookUpdate::new(vec![
+            level(dec!(100), dec!(3), QuoteType::BID),
+            level(dec!(101), dec!(0), QuoteType::ASK),
+            level(dec!(102), dec!(1), QuoteType::ASK),
+        ]));
+
+        let mut tape = TradeTape::new();
+        tape.push(trade(1, 150, dec!(101), dec!(2), Aggressor::Buy));
+        tape.push(trade(2, 160, dec!(102), dec!(1), Aggressor::Buy));
+        tape.push(trade(3, 170, dec!(100), dec!(2), Aggressor::Sell));
+        // Outside of the window
+        tape.push(trade(4, 250, dec!(102), dec!(2), Aggressor::Buy));
+
+        let depletions = tape.attribute(&before, &after, 100, 200);
+        assert_eq!(depletions, vec![
+            Depletion { side: QuoteType::BID, price: dec!(100), depleted: dec!(2), executed: dec!(2) },
+            Depletion { side: QuoteType::ASK, price: dec!(101), depleted: dec!(2), executed: dec!(2) },
+            Depletion { side: QuoteType::ASK, price: dec!(102), depleted: dec!(3), executed: dec!(1) },
+        ]);
+        assert_eq!(depletions[2].cancelled(), dec!(2));
+    }
+}
@@ -7,16 +7,16 @@ use rust_decimal::Decimal;
 use crate::models::order_book_2::OrderBook;
 
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Hash, Copy, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Hash, Copy, Clone)]
 pub struct Price(pub Decimal);
 
-#[derive(Debug, PartialEq, PartialOrd, Copy, Clone)]
+#[derive(Debug, Default, PartialEq, PartialOrd, Copy, Clone)]
 pub struct  Qty(pub Decimal);
 #[derive(Debug, Copy, Clone)]
 pub struct LevelIdx(pub usize);
 pub type SortedLevels = Vec<PriceLevel>;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BuySell {
     Buy,
     Sell
@@ -1,71 +1,281 @@
+use std::fmt;
+use std::str::FromStr;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use crate::connector::{Connector, FeedEvent};
 use crate::messages::IncomingMsg;
-use crate::quote::Exchange::Kraken;
+use crate::models::kraken::ticker as kraken_ticker;
+use crate::models::order_book::OrderBook;
+use crate::models::top_of_book::TopOfBook;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Exchange {
     Kraken,
     Binance,
+    Coinbase,
+    Okx,
+    Bybit,
 }
 
-#[derive(Debug, PartialEq)]
+/// Why a message gave no quote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuoteError {
+    InvalidJson(String),
+    MissingField(&'static str),
+    NonNumeric { field: &'static str, value: String },
+    // A message of another channel, or a book delta that needs its book (see Quote::from_book)
+    WrongChannel(String),
+}
+
+impl QuoteError {
+    // Short name to count rejects by
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QuoteError::InvalidJson(_) => "invalid json",
+            QuoteError::MissingField(_) => "missing field",
+            QuoteError::NonNumeric { .. } => "non numeric",
+            QuoteError::WrongChannel(_) => "wrong channel",
+        }
+    }
+}
+
+impl fmt::Display for QuoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuoteError::InvalidJson(e) => write!(f, "invalid json: {}", e),
+            QuoteError::MissingField(field) => write!(f, "missing field {}", field),
+            QuoteError::NonNumeric { field, value } => write!(f, "non numeric {}: {}", field, value),
+            QuoteError::WrongChannel(channel) => write!(f, "wrong channel {}", channel),
+        }
+    }
+}
+
+impl std::error::Error for QuoteError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Quote {
     pub exchange: Exchange,
-    pub best_bid: f64,
-    pub best_ask: f64,
+    pub symbol: String,
+    pub best_bid: Decimal,
+    pub best_bid_qty: Decimal,
+    pub best_ask: Decimal,
+    pub best_ask_qty: Decimal,
+    // Time of the quote at the venue when the message has one, nanoseconds since epoch
+    pub exchange_ns: Option<u64>,
+    // Local receive time, nanoseconds since epoch
+    pub received_ns: u64,
 }
 
 impl Quote {
-    fn new(exchange: Exchange, best_bid: f64, best_ask: f64) -> Self {
-        Quote {
+    // Top of the book, None until both sides are known
+    pub fn from_best(exchange: Exchange, symbol: &str, best_bid: Option<(Decimal, Decimal)>,
+                     best_ask: Option<(Decimal, Decimal)>, received_ns: u64) -> Option<Self> {
+        let (best_bid, best_bid_qty) = best_bid?;
+        let (best_ask, best_ask_qty) = best_ask?;
+        Some(Quote {
             exchange,
+            symbol: symbol.to_string(),
             best_bid,
+            best_bid_qty,
             best_ask,
-        }
+            best_ask_qty,
+            exchange_ns: None,
+            received_ns,
+        })
     }
 
-    pub fn parse(message: &IncomingMsg) -> Option<Self> {
-        // Mock parsing logic, replace with actual JSON parsing
+    pub fn from_book(exchange: Exchange, symbol: &str, book: &OrderBook, received_ns: u64) -> Option<Self> {
+        Self::from_best(exchange, symbol, book.best_bid(), book.best_ask(), received_ns)
+    }
 
-        match &message.exchange {
-            Kraken => Self::parse_kraken(&message.msg),
-            Exchange::Binance => Self::parse_binance(&message.msg),
-            _ => None,
+    pub fn from_top_of_book(top: &TopOfBook, received_ns: u64) -> Self {
+        Quote {
+            exchange: top.exchange,
+            symbol: top.symbol.clone(),
+            best_bid: top.bid_price,
+            best_bid_qty: top.bid_qty,
+            best_ask: top.ask_price,
+            best_ask_qty: top.ask_qty,
+            exchange_ns: None,
+            received_ns,
         }
+    }
 
+    // Quote from a ticker or a message holding a full image of the book. `connector` is the
+    // parser of the message's feed, used for the venues without a dedicated parser here.
+    pub fn parse(message: &IncomingMsg, connector: &dyn Connector) -> Result<Self, QuoteError> {
+        let value: Value = serde_json::from_str(&message.msg)
+            .map_err(|e| QuoteError::InvalidJson(e.to_string()))?;
+        let quote = match message.exchange {
+            Exchange::Kraken => parse_kraken(&value),
+            Exchange::Binance => parse_binance(&value),
+            _ => parse_snapshot(message, connector),
+        }?;
+        let (best_bid, best_bid_qty, best_ask, best_ask_qty) = quote;
+        Ok(Quote {
+            exchange: message.exchange,
+            symbol: message.symbol.clone(),
+            best_bid,
+            best_bid_qty,
+            best_ask,
+            best_ask_qty,
+            exchange_ns: connector.exchange_ns(&message.msg),
+            received_ns: message.received_ns,
+        })
+    }
 
+    pub fn mid(&self) -> Decimal {
+        (self.best_bid + self.best_ask) / Decimal::TWO
     }
 
-    fn parse_kraken(message: &str) -> Option<Self> {
-        // Parse the JSON message
-        let json_data: Result<Value, _> = serde_json::from_str(message);
-        if let Ok(data) = json_data {
-            // Extract bid and ask prices from the JSON
-            if let (Some(bid), Some(ask)) = (data["bid"].as_f64(), data["ask"].as_f64()) {
-                // Create and return a new Quote instance
-                return Some(Quote::new(Exchange::Kraken, bid, ask));
-            }
-        }
-        None
-    }
-
-    fn parse_binance(message: &str) -> Option<Self> {
-        // Parse the JSON message
-        let json_data: Result<Value, _> = serde_json::from_str(message);
-        if let Ok(data) = json_data {
-            // Extract bid and ask prices from the JSON
-            if let (Some(bid), Some(ask)) = (data["bids"][0][0].as_str().and_then(|x| x.parse::<f64>().ok()), data["asks"][0][0].as_str().and_then(|x| x.parse::<f64>().ok())) {
-                // Create and return a new Quote instance
-                return Some(Quote::new(Exchange::Binance, bid, ask));
-            }
-        }
-        None
+    pub fn spread(&self) -> Decimal {
+        self.best_ask - self.best_bid
+    }
+
+    pub fn mid_difference(&self, other: &Quote) -> Decimal {
+        self.mid() - other.mid()
     }
+}
 
+// (bid, bid qty, ask, ask qty)
+type Best = (Decimal, Decimal, Decimal, Decimal);
+
+fn decimal(value: &Value, field: &'static str) -> Result<Decimal, QuoteError> {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Null => return Err(QuoteError::MissingField(field)),
+        value => value.to_string(),
+    };
+    Decimal::from_str(&text).map_err(|_| QuoteError::NonNumeric { field, value: text })
 }
 
-fn compare_mid_prices(quote1: &Quote, quote2: &Quote) -> f64 {
-    let mid_price1 = (quote1.best_bid + quote1.best_ask) / 2.0;
-    let mid_price2 = (quote2.best_bid + quote2.best_ask) / 2.0;
-    mid_price1 - mid_price2
+// First [price, qty, ...] of a list of levels, best first
+fn first_level(levels: &Value, field: &'static str) -> Result<(Decimal, Decimal), QuoteError> {
+    let level = levels.get(0).ok_or(QuoteError::MissingField(field))?;
+    Ok((decimal(&level[0], field)?, decimal(&level[1], field)?))
+}
+
+// v1 ticker: {"a": [price, wholeLotVolume, lotVolume], "b": [...]}, book snapshot:
+// {"as": [...], "bs": [...]}
+fn parse_kraken(value: &Value) -> Result<Best, QuoteError> {
+    if let Some(event) = value.get("event") {
+        return Err(QuoteError::WrongChannel(event.as_str().unwrap_or_default().to_string()));
+    }
+    let frame = value.as_array().ok_or(QuoteError::MissingField("channelName"))?;
+    let channel = frame.iter().rev().nth(1).and_then(Value::as_str).ok_or(QuoteError::MissingField("channelName"))?;
+    let payload = &frame[1];
+    if channel == kraken_ticker::CHANNEL {
+        let (ask, bid) = (&payload["a"], &payload["b"]);
+        return Ok((decimal(&bid[0], "b")?, decimal(&bid[2], "b")?, decimal(&ask[0], "a")?, decimal(&ask[2], "a")?));
+    }
+    if channel.starts_with("book") && payload.get("as").is_some() {
+        let (bid, bid_qty) = first_level(&payload["bs"], "bs")?;
+        let (ask, ask_qty) = first_level(&payload["as"], "as")?;
+        return Ok((bid, bid_qty, ask, ask_qty));
+    }
+    Err(QuoteError::WrongChannel(channel.to_string()))
+}
+
+// bookTicker {"b", "B", "a", "A"} or partial depth {"lastUpdateId", "bids", "asks"}, raw or
+// wrapped by a combined stream
+fn parse_binance(value: &Value) -> Result<Best, QuoteError> {
+    let data = value.get("data").unwrap_or(value);
+    if let Some(event) = data.get("e") {
+        return Err(QuoteError::WrongChannel(event.as_str().unwrap_or_default().to_string()));
+    }
+    if data.get("lastUpdateId").is_some() {
+        let (bid, bid_qty) = first_level(&data["bids"], "bids")?;
+        let (ask, ask_qty) = first_level(&data["asks"], "asks")?;
+        return Ok((bid, bid_qty, ask, ask_qty));
+    }
+    if data.get("u").is_some() {
+        return Ok((decimal(&data["b"], "b")?, decimal(&data["B"], "B")?, decimal(&data["a"], "a")?, decimal(&data["A"], "A")?));
+    }
+    match value.get("stream").and_then(Value::as_str) {
+        Some(stream) => Err(QuoteError::WrongChannel(stream.to_string())),
+        None => Err(QuoteError::MissingField("u")),
+    }
+}
+
+// Other venues go through their connector, only snapshots make a quote
+fn parse_snapshot(message: &IncomingMsg, connector: &dyn Connector) -> Result<Best, QuoteError> {
+    let mut book = connector.new_book();
+    for event in connector.parse(&message.msg) {
+        match event {
+            FeedEvent::Book(update) if update.is_snapshot() => { book.update(&update); },
+            FeedEvent::TopOfBook(top) => return Ok((top.bid_price, top.bid_qty, top.ask_price, top.ask_qty)),
+            _ => {},
+        }
+    }
+    match (book.best_bid(), book.best_ask()) {
+        (Some((bid, bid_qty)), Some((ask, ask_qty))) => Ok((bid, bid_qty, ask, ask_qty)),
+        _ => Err(QuoteError::WrongChannel("not a snapshot".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use crate::connector::{ConnectorConfig, ConnectorRegistry};
+
+    fn message(exchange: Exchange, symbol: &str, text: &str) -> IncomingMsg {
+        IncomingMsg { exchange, symbol: symbol.to_string(), received_ns: 7, msg: text.to_string() }
+    }
+
+    fn parse(message: &IncomingMsg) -> Result<Quote, QuoteError> {
+        let connector = ConnectorRegistry::default()
+            .create_for(message.exchange, ConnectorConfig::new().with_symbol(&message.symbol))
+            .unwrap();
+        Quote::parse(message, connector.as_ref())
+    }
+
+    #[test]
+    fn test_parse_tickers() {
+        let kraken = message(Exchange::Kraken, "XBT/USD", include_str!("../tests/fixtures/kraken/ticker.json"));
+        let quote = parse(&kraken).unwrap();
+        assert_eq!((quote.best_bid, quote.best_bid_qty), (dec!(64009.95), dec!(1.1)));
+        assert_eq!((quote.best_ask, quote.best_ask_qty), (dec!(64010.4), dec!(0.3)));
+        assert_eq!(quote.received_ns, 7);
+        assert_eq!(quote.spread(), dec!(0.45));
+
+        let binance = message(Exchange::Binance, "BTCUSDT", include_str!("../tests/fixtures/binance/book_ticker.json"));
+        let quote = parse(&binance).unwrap();
+        assert_eq!(quote.mid(), dec!(64000.3));
+        assert_eq!(quote.mid_difference(&quote), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_parse_snapshots() {
+        let kraken = r#"[0,{"as":[["5541.30000","2.50700000","1534614248.123678"]],"bs":[["5541.20000","1.52900000","1534614248.765567"]]},"book-10","XBT/USD"]"#;
+        let quote = parse(&message(Exchange::Kraken, "XBT/USD", kraken)).unwrap();
+        assert_eq!((quote.best_bid, quote.best_ask), (dec!(5541.2), dec!(5541.3)));
+
+        let binance = r#"{"lastUpdateId":160,"bids":[["64000.00","1.5"]],"asks":[["64001.00","0.8"]]}"#;
+        assert_eq!(parse(&message(Exchange::Binance, "BTCUSDT", binance)).unwrap().best_ask_qty, dec!(0.8));
+
+        let coinbase = message(Exchange::Coinbase, "BTC-USD", include_str!("../tests/fixtures/coinbase/level2_snapshot.json"));
+        assert_eq!(parse(&coinbase).unwrap().exchange_ns, Some(1_712_052_930_120_456_789));
+        let okx = message(Exchange::Okx, "BTC-USDT", include_str!("../tests/fixtures/okx/books_snapshot.json"));
+        assert_eq!(parse(&okx).unwrap().exchange_ns, Some(1_712_052_930_120_000_000));
+        let bybit = message(Exchange::Bybit, "BTCUSDT", include_str!("../tests/fixtures/bybit/orderbook_snapshot.json"));
+        assert_eq!(parse(&bybit).unwrap().exchange_ns, Some(1_712_052_930_120_000_000));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let reject = |exchange, text| parse(&message(exchange, "XBT/USD", text)).unwrap_err();
+        assert_eq!(reject(Exchange::Kraken, "[0,").kind(), "invalid json");
+        assert_eq!(reject(Exchange::Kraken, r#"{"event":"heartbeat"}"#), QuoteError::WrongChannel("heartbeat".to_string()));
+        let update = r#"[0,{"a":[["5541.30000","0.00000000","1534614335.345903"]],"c":"1"},"book-10","XBT/USD"]"#;
+        assert_eq!(reject(Exchange::Kraken, update), QuoteError::WrongChannel("book-10".to_string()));
+        let ticker = r#"[340,{"a":["abc",0,"0.3"],"b":["64009.95000",1,"1.1"]},"ticker","XBT/USD"]"#;
+        assert_eq!(reject(Exchange::Kraken, ticker), QuoteError::NonNumeric { field: "a", value: "abc".to_string() });
+
+        assert_eq!(reject(Exchange::Binance, r#"{"u":1,"s":"BTCUSDT","b":"1","B":"1","a":"2"}"#), QuoteError::MissingField("A"));
+        assert_eq!(reject(Exchange::Binance, r#"{"e":"trade","s":"BTCUSDT"}"#).kind(), "wrong channel");
+        assert_eq!(reject(Exchange::Binance, r#"{"lastUpdateId":1,"bids":[],"asks":[]}"#), QuoteError::MissingField("bids"));
+    }
 }
@@ -0,0 +1,96 @@
+// Append-only recording of raw websocket frames, one JSON object per line.
+
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::messages::{now_ns, IncomingMsg};
+use crate::quote::Exchange;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub exchange: Exchange,
+    pub symbol: String,
+    // Local receive time, nanoseconds since epoch
+    pub received_ns: u64,
+    // Frame exactly as received, before any parsing
+    pub frame: String,
+}
+
+impl RecordedFrame {
+    // Stamps the frame with the current time
+    pub fn new(exchange: Exchange, symbol: &str, frame: String) -> Self {
+        RecordedFrame { exchange, symbol: symbol.to_string(), received_ns: now_ns(), frame }
+    }
+
+    pub fn from_incoming(msg: &IncomingMsg) -> Self {
+        RecordedFrame {
+            exchange: msg.exchange,
+            symbol: msg.symbol.clone(),
+            received_ns: msg.received_ns,
+            frame: msg.msg.clone(),
+        }
+    }
+
+    pub fn to_incoming(&self) -> IncomingMsg {
+        IncomingMsg {
+            exchange: self.exchange,
+            symbol: self.symbol.clone(),
+            received_ns: self.received_ns,
+            msg: self.frame.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Recorder {
+    file: File,
+    frames: u64,
+}
+
+impl Recorder {
+    // Opens the file in append mode, an existing recording is never truncated
+    pub async fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path).await?;
+        Ok(Recorder { file, frames: 0 })
+    }
+
+    pub async fn record(&mut self, frame: &RecordedFrame) -> io::Result<()> {
+        let mut line = serde_json::to_string(frame)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes()).await?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.file.flush().await
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+// Records every message going through the channel and forwards it unchanged
+pub fn tee(mut receiver: mpsc::Receiver<IncomingMsg>, mut recorder: Recorder) -> mpsc::Receiver<IncomingMsg> {
+    let (sender, forwarded) = mpsc::channel(32);
+    tokio::spawn(async move {
+        while let Some(msg) = receiver.recv().await {
+            if let Err(e) = recorder.record(&RecordedFrame::from_incoming(&msg)).await {
+                error!("Failed to record frame: {}", e);
+            }
+            if sender.send(msg).await.is_err() {
+                break;
+            }
+        }
+        if let Err(e) = recorder.flush().await {
+            error!("Failed to flush recording: {}", e);
+        }
+    });
+    forwarded
+}
@@ -0,0 +1,156 @@
+// Replays a recording made by recorder::Recorder through the same channels the live feeds
+// use, so that a session can be reproduced offline.
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Instant};
+
+use crate::messages::IncomingMsg;
+use crate::recorder::RecordedFrame;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    // Frames are spaced as they were received
+    Original,
+    // Original spacing divided by the factor
+    Accelerated(f64),
+    // No waiting, order is preserved
+    AsFastAsPossible,
+}
+
+impl std::str::FromStr for ReplaySpeed {
+    type Err = String;
+
+    // "original", "max" or an acceleration factor such as "10"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "original" => Ok(ReplaySpeed::Original),
+            "max" => Ok(ReplaySpeed::AsFastAsPossible),
+            factor => factor.parse::<f64>()
+                .ok()
+                .filter(|factor| factor.is_finite() && *factor > 0.0)
+                .map(ReplaySpeed::Accelerated)
+                .ok_or_else(|| format!("invalid replay speed: {}", s)),
+        }
+    }
+}
+
+pub fn parse_line(line: &str) -> io::Result<RecordedFrame> {
+    serde_json::from_str(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub async fn read_frames<P: AsRef<Path>>(path: P) -> io::Result<Vec<RecordedFrame>> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let mut frames = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        if !line.trim().is_empty() {
+            frames.push(parse_line(&line)?);
+        }
+    }
+    Ok(frames)
+}
+
+// Time to wait, from the start of the replay, before sending a frame
+fn offset(speed: ReplaySpeed, first_ns: u64, frame_ns: u64) -> Option<Duration> {
+    let elapsed = Duration::from_nanos(frame_ns.saturating_sub(first_ns));
+    match speed {
+        ReplaySpeed::Original => Some(elapsed),
+        ReplaySpeed::Accelerated(factor) => Some(elapsed.div_f64(factor)),
+        ReplaySpeed::AsFastAsPossible => None,
+    }
+}
+
+/// Sends the recorded frames in order and returns how many were sent. Stops early if the
+/// receiver is dropped.
+pub async fn replay<P: AsRef<Path>>(path: P, speed: ReplaySpeed, sender: mpsc::Sender<RecordedFrame>) -> io::Result<usize> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let start = Instant::now();
+    let mut first_ns = None;
+    let mut sent = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame = parse_line(&line)?;
+        let first_ns = *first_ns.get_or_insert(frame.received_ns);
+        if let Some(offset) = offset(speed, first_ns, frame.received_ns) {
+            sleep_until(start + offset).await;
+        }
+        if sender.send(frame).await.is_err() {
+            break;
+        }
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+// Same as replay, feeding the pipeline the live connect_and_listen feeds
+pub async fn replay_incoming<P: AsRef<Path>>(path: P, speed: ReplaySpeed, sender: mpsc::Sender<IncomingMsg>) -> io::Result<usize> {
+    let (frame_tx, mut frame_rx) = mpsc::channel::<RecordedFrame>(32);
+    let forward = tokio::spawn(async move {
+        while let Some(frame) = frame_rx.recv().await {
+            if sender.send(frame.to_incoming()).await.is_err() {
+                break;
+            }
+        }
+    });
+    let sent = replay(path, speed, frame_tx).await;
+    let _ = forward.await;
+    sent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::Exchange;
+    use crate::recorder::Recorder;
+
+    fn frame(received_ns: u64, text: &str) -> RecordedFrame {
+        RecordedFrame { exchange: Exchange::Kraken, symbol: "XBT/USD".to_string(), received_ns, frame: text.to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl", uuid::Uuid::new_v4()));
+        let frames = vec![
+            frame(1_000_000_000, r#"{"channel":"heartbeat"}"#),
+            frame(1_050_000_000, "[0,{\"a\":[[\"1.0\",\"2.0\",\"3.0\"]]},\"book-10\",\"XBT/USD\"]"),
+        ];
+
+        let mut recorder = Recorder::open(&path).await.unwrap();
+        for f in &frames {
+            recorder.record(f).await.unwrap();
+        }
+        recorder.flush().await.unwrap();
+        assert_eq!(read_frames(&path).await.unwrap(), frames);
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let started = Instant::now();
+        let sent = replay_incoming(&path, ReplaySpeed::Accelerated(10.0), tx).await.unwrap();
+        assert_eq!(sent, 2);
+        assert!(started.elapsed() >= Duration::from_millis(5), "Frames should keep their relative spacing");
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.exchange, Exchange::Kraken);
+        assert_eq!(first.msg, frames[0].frame);
+        assert_eq!(rx.recv().await.unwrap().received_ns, frames[1].received_ns);
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[test]
+    fn test_parse_speed() {
+        assert_eq!("max".parse::<ReplaySpeed>(), Ok(ReplaySpeed::AsFastAsPossible));
+        assert_eq!("4".parse::<ReplaySpeed>(), Ok(ReplaySpeed::Accelerated(4.0)));
+        assert!("-1".parse::<ReplaySpeed>().is_err());
+        // Would make every delay zero or NaN, div_f64 panics on those
+        for speed in ["0", "inf", "NaN", "1e400"] {
+            assert!(speed.parse::<ReplaySpeed>().is_err(), "{}", speed);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::time::Duration;
+use rust_decimal_macros::dec;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+use websocket::connect_and_listen::{connect_and_sync_binance_depth, supervise_binance_depth, BinanceDepthConfig,
+                                    ConnectionEvent, DisconnectCause, ReconnectConfig};
+use websocket::connector::binance::BinanceConnector;
+use websocket::connector::{Connector, ConnectorConfig, FeedEvent};
+use websocket::messages::IncomingMsg;
+use websocket::mock_exchange::{MockExchange, Session};
+use websocket::models::order_book::{OrderBook, OrderBookUpdate, UpdateOutcome};
+
+const SNAPSHOT: &str = include_str!("fixtures/binance/depth_snapshot.json");
+const UPDATES: &str = include_str!("fixtures/binance/depth_updates.jsonl");
+
+// Serves the given bodies to successive REST requests
+async fn serve_snapshots(snapshots: Vec<String>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        for body in snapshots {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    url
+}
+
+async fn run(events: Vec<String>, snapshots: Vec<String>) -> Vec<OrderBookUpdate> {
+    // Streams the events to the first client, then closes the connection
+    let mock = MockExchange::start(vec![Session::new().send_all(events)]).await.unwrap();
+    let config = BinanceDepthConfig {
+        ws_url: mock.url().to_string(),
+        rest_url: serve_snapshots(snapshots).await,
+        ..BinanceDepthConfig::default()
+    };
+    let (tx, mut rx) = mpsc::channel(32);
+    connect_and_sync_binance_depth(config, tx).await;
+
+    let mut frames = Vec::new();
+    while let Some(frame) = rx.recv().await {
+        frames.push(frame);
+    }
+    book_updates(&frames)
+}
+
+// The updates a book fed with the forwarded frames applied, a gap is not one of them
+fn book_updates(frames: &[IncomingMsg]) -> Vec<OrderBookUpdate> {
+    let connector = BinanceConnector::new(ConnectorConfig::new());
+    let mut book = connector.new_book();
+    let mut updates = Vec::new();
+    for frame in frames {
+        for event in connector.parse(&frame.msg) {
+            match event {
+                FeedEvent::Book(update) => if book.update(&update) == UpdateOutcome::Applied {
+                    updates.push(update);
+                },
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+    }
+    updates
+}
+
+fn replay(updates: &[OrderBookUpdate]) -> OrderBook {
+    let mut book = OrderBook::new();
+    for update in updates {
+        book.update(update);
+    }
+    book
+}
+
+#[tokio::test]
+async fn test_sync_against_stand_in() {
+    let events = UPDATES.lines().map(String::from).collect();
+    let updates = run(events, vec![SNAPSHOT.to_string()]).await;
+
+    // 150..=155 predates the snapshot
+    assert_eq!(updates.len(), 4);
+    assert!(updates[0].is_snapshot());
+    let book = replay(&updates);
+    assert_eq!(book.best_bid(), Some((dec!(64000.50), dec!(0.3))));
+    assert_eq!(book.best_ask(), Some((dec!(64001.00), dec!(0.5))));
+}
+
+#[tokio::test]
+async fn test_resnapshot_after_gap() {
+    let lines: Vec<&str> = UPDATES.lines().collect();
+    let events = vec![
+        lines[0].to_string(),
+        lines[1].to_string(),
+        // 163..=165 is lost
+        lines[3].to_string(),
+        r#"{"e":"depthUpdate","E":1712000000400,"s":"BTCUSDT","U":171,"u":172,"b":[["63990.00","4.00000000"]],"a":[]}"#.to_string(),
+    ];
+    let resnapshot = r#"{"lastUpdateId":170,"bids":[["63995.00","1.00000000"]],"asks":[["64005.00","1.00000000"]]}"#;
+    let updates = run(events, vec![SNAPSHOT.to_string(), resnapshot.to_string()]).await;
+
+    let snapshots = updates.iter().filter(|update| update.is_snapshot()).count();
+    assert_eq!(snapshots, 2);
+    assert_eq!(updates.len(), 4);
+    let book = replay(&updates);
+    assert_eq!(book.best_bid(), Some((dec!(63995.00), dec!(1))));
+    assert_eq!(book.best_ask(), Some((dec!(64005.00), dec!(1))));
+}
+
+#[tokio::test]
+async fn test_connect_failure_is_reported() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = BinanceDepthConfig {
+        ws_url: format!("ws://{}", listener.local_addr().unwrap()),
+        ..BinanceDepthConfig::default()
+    };
+    // Nothing accepts on the port anymore
+    drop(listener);
+    let (tx, _rx) = mpsc::channel(1);
+    assert!(matches!(connect_and_sync_binance_depth(config, tx).await, DisconnectCause::ConnectFailed(_)));
+}
+
+#[tokio::test]
+async fn test_reconnect_syncs_from_fresh_snapshot() {
+    let events: Vec<String> = UPDATES.lines().map(String::from).collect();
+    // The second connection resumes after updates the first one never got
+    let resumed = vec![
+        r#"{"e":"depthUpdate","E":1712000000400,"s":"BTCUSDT","U":171,"u":172,"b":[["63990.00","4.00000000"]],"a":[]}"#.to_string(),
+    ];
+    let resnapshot = r#"{"lastUpdateId":170,"bids":[["63995.00","1.00000000"]],"asks":[["64005.00","1.00000000"]]}"#;
+    let mock = MockExchange::start(vec![Session::new().send_all(events), Session::new().send_all(resumed).hold()])
+        .await.unwrap();
+    let config = BinanceDepthConfig {
+        ws_url: mock.url().to_string(),
+        rest_url: serve_snapshots(vec![SNAPSHOT.to_string(), resnapshot.to_string()]).await,
+        ..BinanceDepthConfig::default()
+    };
+    let reconnect = ReconnectConfig {
+        initial_delay: Duration::from_millis(10),
+        ..ReconnectConfig::default()
+    };
+    let (tx, mut rx) = mpsc::channel(32);
+    let (events_tx, mut events_rx) = mpsc::channel(8);
+    let (_resync_tx, resync_rx) = mpsc::channel(1);
+    tokio::spawn(supervise_binance_depth(config, reconnect, tx, events_tx, resync_rx));
+
+    let mut connection_events = Vec::new();
+    while connection_events.len() < 4 {
+        connection_events.push(timeout(Duration::from_secs(5), events_rx.recv()).await.unwrap().unwrap());
+    }
+    assert!(matches!(connection_events[0], ConnectionEvent::Connected { attempt: 0, .. }));
+    assert!(matches!(&connection_events[1], ConnectionEvent::Disconnected { cause: DisconnectCause::ClosedByPeer, .. }));
+    assert!(matches!(connection_events[2], ConnectionEvent::Reconnecting { attempt: 1, .. }));
+    assert!(matches!(connection_events[3], ConnectionEvent::Connected { attempt: 1, .. }));
+
+    let mut frames = Vec::new();
+    while frames.len() < 6 {
+        frames.push(timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap());
+    }
+    let updates = book_updates(&frames);
+    assert_eq!(updates.len(), 6);
+    // The first connection's snapshot and deltas, then the second connection's snapshot
+    assert!(updates[0].is_snapshot());
+    assert!(updates[4].is_snapshot());
+    let book = replay(&updates);
+    assert_eq!(book.best_bid(), Some((dec!(63995.00), dec!(1))));
+    assert_eq!(book.best_ask(), Some((dec!(64005.00), dec!(1))));
+}
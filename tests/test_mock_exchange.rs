@@ -0,0 +1,147 @@
+use std::time::Duration;
+use rust_decimal_macros::dec;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+use websocket::connect_and_listen::{supervise, ConnectionEvent, DisconnectCause, ReconnectConfig};
+use websocket::connector::binance::BinanceConnector;
+use websocket::connector::kraken::KrakenConnector;
+use websocket::connector::{Connector, ConnectorConfig, FeedEvent};
+use websocket::mock_exchange::{recorded_frames, MockExchange, Session};
+use websocket::models::order_book::OrderBook;
+use websocket::quote::Exchange;
+
+const KRAKEN_RECORDING: &str = "tests/fixtures/kraken/book_recording.jsonl";
+const BINANCE_PARTIAL_DEPTH: &str = include_str!("fixtures/binance/partial_depth.jsonl");
+
+// Book built from the frames forwarded by the supervisor, as main does
+struct Pipeline {
+    parser: Box<dyn Connector>,
+    book: OrderBook,
+    frames: usize,
+    checksums: usize,
+    events: Vec<ConnectionEvent>,
+}
+
+impl Pipeline {
+    fn new(parser: Box<dyn Connector>) -> Self {
+        let book = parser.new_book();
+        Pipeline { parser, book, frames: 0, checksums: 0, events: Vec::new() }
+    }
+
+    fn on_frame(&mut self, text: &str) {
+        self.frames += 1;
+        for event in self.parser.parse(text) {
+            match event {
+                FeedEvent::Book(update) => { self.book.update(&update); },
+                FeedEvent::Checksum(expected) => {
+                    assert_eq!(self.parser.checksum(&self.book), Some(expected), "checksum after {}", text);
+                    self.checksums += 1;
+                },
+                FeedEvent::TopOfBook(_) | FeedEvent::Trade(_) | FeedEvent::Heartbeat => {},
+            }
+        }
+    }
+
+    // Runs the supervisor against the mock until `frames` frames went through the book
+    async fn run(mut self, connector: Box<dyn Connector>, frames: usize) -> Self {
+        let config = ReconnectConfig {
+            initial_delay: Duration::from_millis(10),
+            ..ReconnectConfig::default()
+        };
+        let (tx, mut rx) = mpsc::channel(32);
+        let (events_tx, mut events_rx) = mpsc::channel(32);
+        let (_resync_tx, resync_rx) = mpsc::channel(1);
+        tokio::spawn(supervise(connector, config, tx, events_tx, resync_rx));
+
+        while self.frames < frames {
+            tokio::select! {
+                biased;
+                Some(event) = events_rx.recv() => {
+                    if let ConnectionEvent::Disconnected { .. } = event {
+                        self.book.mark_stale();
+                    }
+                    self.events.push(event);
+                },
+                msg = timeout(Duration::from_secs(5), rx.recv()) => self.on_frame(&msg.unwrap().unwrap().msg),
+            }
+        }
+        while let Ok(event) = events_rx.try_recv() {
+            self.events.push(event);
+        }
+        self
+    }
+}
+
+fn kraken(mock: &MockExchange) -> Box<dyn Connector> {
+    Box::new(KrakenConnector::new(ConnectorConfig::new().with_base_url(mock.url())))
+}
+
+#[tokio::test]
+async fn test_kraken_recorded_session() {
+    let recording = recorded_frames(KRAKEN_RECORDING, Exchange::Kraken, "XBT/USD").await.unwrap();
+    assert_eq!(recording.len(), 6);
+    let session = Session::new()
+        .with_subscriptions(1)
+        .send_recorded(&recording[..4])
+        // Malformed payloads are dropped by the parser, the book goes on
+        .send("[336,{\"a\":[[\"not a price\",\"1.0\",\"1712000000.2\"]]},\"book-10\",\"XBT/USD\"]")
+        .send("{\"event\":")
+        .send_recorded(&recording[4..])
+        .hold();
+    let mut mock = MockExchange::start(vec![session]).await.unwrap();
+
+    let pipeline = Pipeline::new(kraken(&mock)).run(kraken(&mock), 8).await;
+    assert_eq!(pipeline.checksums, 2);
+    assert!(pipeline.book.is_synced());
+    assert_eq!(pipeline.book.best_bid(), Some((dec!(64009.95), dec!(1.1))));
+    assert_eq!(pipeline.book.best_ask(), Some((dec!(64010.4), dec!(0.3))));
+    assert!(matches!(pipeline.events.as_slice(), [ConnectionEvent::Connected { attempt: 0, .. }]));
+
+    let accepted = timeout(Duration::from_secs(5), mock.accepted()).await.unwrap().unwrap();
+    assert_eq!(accepted.path, "/");
+    let subscription: serde_json::Value = serde_json::from_str(&accepted.subscriptions[0]).unwrap();
+    assert_eq!(subscription["pair"][0], "XBT/USD");
+    assert_eq!(subscription["subscription"]["depth"], 10);
+}
+
+#[tokio::test]
+async fn test_kraken_dropped_connection_resyncs() {
+    let recording = recorded_frames(KRAKEN_RECORDING, Exchange::Kraken, "XBT/USD").await.unwrap();
+    let sessions = vec![
+        // Lost without a close frame after the snapshot
+        Session::new().with_subscriptions(1).send_recorded(&recording[2..3]).drop_connection(),
+        Session::new().with_subscriptions(1).send_recorded(&recording).hold(),
+    ];
+    let mock = MockExchange::start(sessions).await.unwrap();
+
+    let pipeline = Pipeline::new(kraken(&mock)).run(kraken(&mock), 7).await;
+    match pipeline.events.as_slice() {
+        [ConnectionEvent::Connected { attempt: 0, .. },
+         ConnectionEvent::Disconnected { cause: DisconnectCause::Error(_), .. },
+         ConnectionEvent::Reconnecting { attempt: 1, .. },
+         ConnectionEvent::Connected { attempt: 1, .. }] => {},
+        events => panic!("unexpected events {:?}", events),
+    }
+    // The snapshot of the new connection brought the book back
+    assert_eq!(pipeline.checksums, 2);
+    assert!(pipeline.book.is_synced());
+    assert_eq!(pipeline.book.best_bid(), Some((dec!(64009.95), dec!(1.1))));
+}
+
+#[tokio::test]
+async fn test_binance_partial_depth() {
+    let session = Session::new().send_all(BINANCE_PARTIAL_DEPTH.lines()).hold();
+    let mut mock = MockExchange::start(vec![session]).await.unwrap();
+    let binance = |mock: &MockExchange| -> Box<dyn Connector> {
+        Box::new(BinanceConnector::new(ConnectorConfig::new().with_base_url(mock.url())))
+    };
+
+    let pipeline = Pipeline::new(binance(&mock)).run(binance(&mock), 2).await;
+    assert_eq!(pipeline.book.best_bid(), Some((dec!(64000.1), dec!(0.3))));
+    assert_eq!(pipeline.book.best_ask(), Some((dec!(64000.5), dec!(0.6))));
+
+    let accepted = timeout(Duration::from_secs(5), mock.accepted()).await.unwrap().unwrap();
+    assert_eq!(accepted.path, "/ws/btcusdt@depth5@100ms");
+    assert!(accepted.subscriptions.is_empty());
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+use websocket::connect_and_listen::{supervise, ConnectionEvent, DisconnectCause, ReconnectConfig};
+use websocket::connector::kraken::KrakenConnector;
+use websocket::connector::coinbase::CoinbaseConnector;
+use websocket::connector::ConnectorConfig;
+use websocket::mock_exchange::{MockExchange, Session};
+
+// Each connection must send its subscriptions, gets its frames and is then closed
+async fn serve_connections(subscriptions: usize, connections: Vec<Vec<String>>) -> MockExchange {
+    let sessions = connections.into_iter()
+        .map(|frames| Session::new().with_subscriptions(subscriptions).send_all(frames))
+        .collect();
+    MockExchange::start(sessions).await.unwrap()
+}
+
+async fn serve_dropping_connections(connections: usize) -> MockExchange {
+    let frames = (0..connections)
+        .map(|connection| vec![format!(r#"{{"connection":{}}}"#, connection)])
+        .collect();
+    serve_connections(1, frames).await
+}
+
+#[tokio::test]
+async fn test_reconnect_and_resubscribe() {
+    let mut mock = serve_dropping_connections(2).await;
+    let connector = KrakenConnector::new(ConnectorConfig::new().with_base_url(mock.url()));
+    let config = ReconnectConfig {
+        initial_delay: Duration::from_millis(10),
+        ..ReconnectConfig::default()
+    };
+    let (tx, mut rx) = mpsc::channel(8);
+    let (events_tx, mut events_rx) = mpsc::channel(8);
+    let (_resync_tx, resync_rx) = mpsc::channel(1);
+    let supervisor = tokio::spawn(supervise(Box::new(connector), config, tx, events_tx, resync_rx));
+
+    let mut events = Vec::new();
+    while events.len() < 4 {
+        events.push(timeout(Duration::from_secs(5), events_rx.recv()).await.unwrap().unwrap());
+    }
+    assert!(matches!(events[0], ConnectionEvent::Connected { attempt: 0, .. }));
+    assert!(matches!(&events[1], ConnectionEvent::Disconnected { cause: DisconnectCause::ClosedByPeer, .. }));
+    assert!(matches!(events[2], ConnectionEvent::Reconnecting { attempt: 1, .. }));
+    assert!(matches!(events[3], ConnectionEvent::Connected { attempt: 1, .. }));
+
+    // Both connections subscribed and forwarded their frame
+    let first = mock.accepted().await.unwrap().subscriptions;
+    assert_eq!(mock.accepted().await.unwrap().subscriptions, first);
+    assert!(first[0].contains("\"book\""));
+    assert_eq!(rx.recv().await.unwrap().msg, r#"{"connection":0}"#);
+    assert_eq!(rx.recv().await.unwrap().msg, r#"{"connection":1}"#);
+
+    // The supervisor stops once nobody listens
+    drop(rx);
+    drop(events_rx);
+    timeout(Duration::from_secs(5), supervisor).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_sequence_gap_reconnects() {
+    let frames = [0, 1, 3].iter()
+        .map(|sequence| format!(r#"{{"channel":"heartbeats","sequence_num":{},"events":[]}}"#, sequence))
+        .collect();
+    let mock = serve_connections(2, vec![frames]).await;
+    let connector = CoinbaseConnector::new(ConnectorConfig::new().with_base_url(mock.url()));
+    let (tx, mut rx) = mpsc::channel(8);
+    let (events_tx, mut events_rx) = mpsc::channel(8);
+    let (_resync_tx, resync_rx) = mpsc::channel(1);
+    tokio::spawn(supervise(Box::new(connector), ReconnectConfig::default(), tx, events_tx, resync_rx));
+
+    assert!(matches!(events_rx.recv().await.unwrap(), ConnectionEvent::Connected { .. }));
+    let disconnected = timeout(Duration::from_secs(5), events_rx.recv()).await.unwrap().unwrap();
+    match disconnected {
+        ConnectionEvent::Disconnected { cause, .. } =>
+            assert_eq!(cause, DisconnectCause::SequenceGap { expected: 2, received: 3 }),
+        event => panic!("expected a disconnect, got {}", event),
+    }
+
+    // The frame after the gap is not forwarded
+    assert!(rx.recv().await.unwrap().msg.contains("\"sequence_num\":0"));
+    assert!(rx.recv().await.unwrap().msg.contains("\"sequence_num\":1"));
+    assert!(rx.try_recv().is_err());
+}